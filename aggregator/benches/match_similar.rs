@@ -0,0 +1,96 @@
+use aggregator::{
+    Config, Latest, Media, MediaType, NgramIndex, ReleaseNumber, Similar, SubsPleaseRSS,
+    TransformOptions,
+};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::collections::HashMap;
+
+const WORDS: [&str; 16] = [
+    "Kimetsu", "no", "Yaiba", "Shingeki", "Kyojin", "Boku", "Hero", "Academia", "Jujutsu",
+    "Kaisen", "Spy", "Family", "Tensei", "Shitara", "Slime", "Datta",
+];
+
+fn titles(count: usize, seed: usize) -> Vec<String> {
+    (0..count)
+        .map(|i| {
+            let n = i * 7 + seed;
+            format!(
+                "{} {} {} {}",
+                WORDS[n % WORDS.len()],
+                WORDS[(n / 3) % WORDS.len()],
+                WORDS[(n / 11) % WORDS.len()],
+                i
+            )
+        })
+        .collect()
+}
+
+fn extras(count: usize) -> HashMap<String, Latest> {
+    titles(count, 0)
+        .into_iter()
+        .map(|title| {
+            let latest = Latest {
+                title: title.to_owned(),
+                number: ReleaseNumber::parse("1", None),
+                url: String::new(),
+                source: "subsplease_rss".to_owned(),
+                released_at: None,
+                links: None,
+            };
+            (title, latest)
+        })
+        .collect()
+}
+
+fn media(title: &str) -> Media {
+    Media {
+        media_type: Some(MediaType::Anime),
+        status: Some("CURRENT".to_owned()),
+        title: Some(title.to_owned()),
+        ..Default::default()
+    }
+}
+
+fn bench_match_similar(c: &mut Criterion) {
+    let config = Config::default();
+    let source = SubsPleaseRSS::new(&config);
+    let extras = extras(5000);
+    let titles = titles(200, 3);
+
+    let mut group = c.benchmark_group("match_similar");
+    group.sample_size(10);
+
+    group.bench_function("brute_force", |b| {
+        b.iter(|| {
+            for title in &titles {
+                let matched =
+                    source.match_similar(&mut media(title), MediaType::Anime, &extras, None);
+                black_box(matched.unwrap());
+            }
+        })
+    });
+
+    group.bench_function("ngram_index", |b| {
+        b.iter(|| {
+            let index = NgramIndex::new(extras.keys());
+            let options = TransformOptions {
+                index: Some(&index),
+            };
+            for title in &titles {
+                let matched = source.match_similar(
+                    &mut media(title),
+                    MediaType::Anime,
+                    &extras,
+                    Some(options),
+                );
+                black_box(matched.unwrap());
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_match_similar);
+criterion_main!(benches);
@@ -1,74 +1,505 @@
-use serde::Deserialize;
-use std::fs;
+use crate::anilist_api::ReleaseType;
+use crate::error::CustomError;
+use crate::result::Result;
 
-#[derive(Debug, Deserialize)]
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs};
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct AggregatorConfig {
     pub ttl: usize,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct AniListAPIConfig {
     pub url: String,
+    #[serde(default)]
+    pub dry_run: bool,
+    pub oauth: Option<AniListOAuthConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AniListOAuthConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+    #[serde(default = "AniListOAuthConfig::default_authorize_url")]
+    pub authorize_url: String,
+    #[serde(default = "AniListOAuthConfig::default_token_url")]
+    pub token_url: String,
+    // Deprecated, moved to the top-level encryption_key.
+    #[serde(default)]
+    pub encryption_key: Option<String>,
+}
+
+impl AniListOAuthConfig {
+    pub fn default_authorize_url() -> String {
+        "https://anilist.co/api/v2/oauth/authorize".to_owned()
+    }
+
+    pub fn default_token_url() -> String {
+        "https://anilist.co/api/v2/oauth/token".to_owned()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpBackend {
+    #[default]
+    Live,
+    Fixture,
+    Record,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpConfig {
+    #[serde(default)]
+    pub backend: HttpBackend,
+    #[serde(default)]
+    pub sources: HashMap<String, HttpBackend>,
+    #[serde(default = "HttpConfig::default_fixture_dir")]
+    pub fixture_dir: String,
+}
+
+impl HttpConfig {
+    fn default_fixture_dir() -> String {
+        "fixtures/http".to_owned()
+    }
+
+    pub fn backend(&self, source: &str) -> HttpBackend {
+        self.sources.get(source).copied().unwrap_or(self.backend)
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> HttpConfig {
+        HttpConfig {
+            backend: HttpBackend::default(),
+            sources: HashMap::new(),
+            fixture_dir: Self::default_fixture_dir(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KitsuAPIConfig {
+    pub url: String,
+}
+
+impl Default for KitsuAPIConfig {
+    fn default() -> KitsuAPIConfig {
+        KitsuAPIConfig {
+            url: "https://kitsu.io/api/edge".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MyAnimeListAPIConfig {
+    pub url: String,
+    pub client_id: Option<String>,
+}
+
+impl Default for MyAnimeListAPIConfig {
+    fn default() -> MyAnimeListAPIConfig {
+        MyAnimeListAPIConfig {
+            url: "https://api.myanimelist.net/v2".to_owned(),
+            client_id: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct MongoDBConfig {
     pub uri: String,
     pub database: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct RedisConfig {
     pub uri: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct DBConfig {
     pub mongodb: MongoDBConfig,
     pub redis: RedisConfig,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct MangaDexAPIConfig {
     pub url: String,
+    #[serde(default = "MangaDexAPIConfig::default_manga_url")]
+    pub manga_url: String,
     pub manga_agg_url: String,
+    #[serde(default = "MangaDexAPIConfig::default_chapter_url")]
+    pub chapter_url: String,
+    #[serde(default = "MangaDexAPIConfig::default_auth_url")]
+    pub auth_url: String,
+    #[serde(default = "MangaDexAPIConfig::default_follows_feed_url")]
+    pub follows_feed_url: String,
+    #[serde(default = "MangaDexAPIConfig::default_feed_limit")]
+    pub feed_limit: usize,
+    #[serde(default = "MangaDexAPIConfig::default_translated_language")]
+    pub translated_language: Vec<String>,
+    #[serde(default)]
+    pub included_groups: Vec<String>,
+    #[serde(default)]
+    pub excluded_groups: Vec<String>,
     pub rate_limit: usize,
+    pub enriched_statuses: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct MangaUpdatesAPIConfig {
+    pub url: String,
+    #[serde(default = "MangaUpdatesAPIConfig::default_pages")]
+    pub pages: usize,
+    pub enriched_statuses: Option<Vec<String>>,
+}
+
+impl MangaUpdatesAPIConfig {
+    fn default_pages() -> usize {
+        5
+    }
+}
+
+impl Default for MangaUpdatesAPIConfig {
+    fn default() -> MangaUpdatesAPIConfig {
+        MangaUpdatesAPIConfig {
+            url: "https://api.mangaupdates.com/v1".to_owned(),
+            pages: Self::default_pages(),
+            enriched_statuses: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize, Hash)]
+pub struct MangaDexFilters {
+    #[serde(default)]
+    pub translated_language: Vec<String>,
+    #[serde(default)]
+    pub included_groups: Vec<String>,
+    #[serde(default)]
+    pub excluded_groups: Vec<String>,
+}
+
+impl MangaDexFilters {
+    pub fn with_override(&self, other: Option<&MangaDexFilters>) -> MangaDexFilters {
+        let other = match other {
+            Some(other) => other,
+            None => return self.clone(),
+        };
+        let pick = |own: &Vec<String>, other: &Vec<String>| {
+            if other.is_empty() {
+                own.clone()
+            } else {
+                other.clone()
+            }
+        };
+
+        MangaDexFilters {
+            translated_language: pick(&self.translated_language, &other.translated_language),
+            included_groups: pick(&self.included_groups, &other.included_groups),
+            excluded_groups: pick(&self.excluded_groups, &other.excluded_groups),
+        }
+    }
+
+    // Identifies the chapters these filters allow, independent of the language.
+    pub fn key(&self) -> String {
+        let sorted = |groups: &Vec<String>| {
+            let mut groups = groups.clone();
+            groups.sort_unstable();
+            groups.dedup();
+            groups.join(",")
+        };
+
+        let mut parts = Vec::new();
+        if !self.included_groups.is_empty() {
+            parts.push(format!("include:{}", sorted(&self.included_groups)));
+        }
+        if !self.excluded_groups.is_empty() {
+            parts.push(format!("exclude:{}", sorted(&self.excluded_groups)));
+        }
+        if parts.is_empty() {
+            "all".to_owned()
+        } else {
+            parts.join(";")
+        }
+    }
+
+    pub fn has_group_filters(&self) -> bool {
+        !self.included_groups.is_empty() || !self.excluded_groups.is_empty()
+    }
+
+    pub fn allows(&self, groups: &[&str]) -> bool {
+        let included = self.included_groups.is_empty()
+            || groups.iter().any(|group| {
+                self.included_groups
+                    .iter()
+                    .any(|included| included == group)
+            });
+        let excluded = groups.iter().any(|group| {
+            self.excluded_groups
+                .iter()
+                .any(|excluded| excluded == group)
+        });
+
+        included && !excluded
+    }
+}
+
+impl MangaDexAPIConfig {
+    pub fn filters(&self) -> MangaDexFilters {
+        MangaDexFilters {
+            translated_language: self.translated_language.clone(),
+            included_groups: self.included_groups.clone(),
+            excluded_groups: self.excluded_groups.clone(),
+        }
+    }
+
+    fn default_manga_url() -> String {
+        "https://api.mangadex.org/manga".to_owned()
+    }
+
+    fn default_chapter_url() -> String {
+        "https://api.mangadex.org/chapter".to_owned()
+    }
+
+    fn default_auth_url() -> String {
+        "https://auth.mangadex.org/realms/mangadex/protocol/openid-connect/token".to_owned()
+    }
+
+    fn default_follows_feed_url() -> String {
+        "https://api.mangadex.org/user/follows/manga/feed".to_owned()
+    }
+
+    fn default_feed_limit() -> usize {
+        500
+    }
+
+    fn default_translated_language() -> Vec<String> {
+        vec!["en".to_owned()]
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct TransformConfig {
     pub similarity_threshold: f64,
+    #[serde(default = "TransformConfig::default_similarity_candidates")]
+    pub similarity_candidates: usize,
+    #[serde(default = "TransformConfig::default_enriched_statuses")]
+    pub enriched_statuses: Vec<String>,
+    #[serde(default)]
+    pub source_priority: SourcePriority,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourcePriority {
+    #[serde(default)]
+    pub anime: Vec<String>,
+    #[serde(default)]
+    pub manga: Vec<String>,
+}
+
+impl Default for SourcePriority {
+    fn default() -> SourcePriority {
+        SourcePriority {
+            anime: vec!["subsplease_rss".to_owned()],
+            manga: vec!["mangadex_api".to_owned(), "mangaupdates_api".to_owned()],
+        }
+    }
+}
+
+impl TransformConfig {
+    fn default_similarity_candidates() -> usize {
+        10
+    }
+
+    fn default_enriched_statuses() -> Vec<String> {
+        vec!["CURRENT".to_owned(), "REPEATING".to_owned()]
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct SubsPleaseRSSConfig {
     pub url: String,
+    #[serde(default = "SubsPleaseRSSConfig::default_resolution")]
+    pub resolution: String,
+    #[serde(default = "SubsPleaseRSSConfig::default_page_url")]
+    pub page_url: String,
+    #[serde(default = "SubsPleaseRSSConfig::default_latest_url")]
+    pub latest_url: String,
+    pub enriched_statuses: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize)]
+impl SubsPleaseRSSConfig {
+    fn default_resolution() -> String {
+        "720".to_owned()
+    }
+
+    fn default_page_url() -> String {
+        "https://subsplease.org/shows/{slug}/".to_owned()
+    }
+
+    fn default_latest_url() -> String {
+        "https://subsplease.org/api/?f=latest&tz=UTC".to_owned()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleBackend {
+    #[default]
+    Api,
+    Webdriver,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct SubsPleaseScraperConfig {
     pub url: String,
+    #[serde(default = "SubsPleaseScraperConfig::default_api_url")]
+    pub api_url: String,
+    #[serde(default)]
+    pub backend: ScheduleBackend,
+    #[serde(default = "SubsPleaseScraperConfig::default_webdriver_url")]
     pub webdriver_url: String,
+    #[serde(default = "SubsPleaseScraperConfig::default_chrome_options")]
     pub chrome_options: String,
+    #[serde(default = "SubsPleaseScraperConfig::default_timezone")]
+    pub timezone: String,
+    pub enriched_statuses: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize)]
+impl SubsPleaseScraperConfig {
+    fn default_api_url() -> String {
+        "https://subsplease.org/api/?f=schedule&h=true".to_owned()
+    }
+
+    fn default_webdriver_url() -> String {
+        "http://localhost:9515".to_owned()
+    }
+
+    fn default_chrome_options() -> String {
+        "--headless".to_owned()
+    }
+
+    fn default_timezone() -> String {
+        "UTC".to_owned()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct SubsPleaseConfig {
     pub rss: SubsPleaseRSSConfig,
     pub scraper: SubsPleaseScraperConfig,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedFormat {
+    #[default]
+    Rss,
+    Atom,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedLinkField {
+    #[default]
+    Url,
+    Page,
+    Magnet,
+    Torrent,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeedConfig {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub format: FeedFormat,
+    pub title_regex: String,
+    pub media_type: String,
+    #[serde(default)]
+    pub link_field: FeedLinkField,
+    #[serde(default)]
+    pub release_type: ReleaseType,
+    pub enriched_statuses: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StreamFeedConfig {
+    pub platform: String,
+    pub url: String,
+    #[serde(default = "StreamFeedConfig::default_title_regex")]
+    pub title_regex: String,
+    pub region: Option<String>,
+    pub enriched_statuses: Option<Vec<String>>,
+}
+
+impl StreamFeedConfig {
+    pub fn default_title_regex() -> String {
+        r"^(?P<title>.+?) - Episode (?P<episode>\d+(?:\.\d+)?)".to_owned()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig {
+    pub address: String,
+    // Grants access to the routes that change data shared by all users.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            address: "127.0.0.1:8080".to_owned(),
+            admin_token: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProgressConfig {
+    pub stale_weeks: i64,
+}
+
+impl Default for ProgressConfig {
+    fn default() -> ProgressConfig {
+        ProgressConfig { stale_weeks: 4 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct WorkerConfig {
     pub retry_timeout: usize,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Config {
+    pub encryption_key: Option<String>,
     pub aggregator: AggregatorConfig,
     pub anilist_api: AniListAPIConfig,
     pub db: DBConfig,
+    #[serde(default)]
+    pub feeds: Vec<FeedConfig>,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub kitsu_api: KitsuAPIConfig,
     pub mangadex_api: MangaDexAPIConfig,
+    #[serde(default)]
+    pub mangaupdates_api: MangaUpdatesAPIConfig,
+    #[serde(default)]
+    pub myanimelist_api: MyAnimeListAPIConfig,
+    #[serde(default)]
+    pub progress: ProgressConfig,
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub streams: Vec<StreamFeedConfig>,
     pub subsplease: SubsPleaseConfig,
     pub transform: TransformConfig,
     pub worker: WorkerConfig,
@@ -77,9 +508,71 @@ pub struct Config {
 impl Config {
     pub fn from_file(filename: &str) -> Config {
         let config = fs::read_to_string(filename).unwrap();
-        let config: Config = toml::from_str(&config).unwrap();
+        let mut config: Config = toml::from_str(&config).unwrap();
+        config.migrate_deprecated();
         config
     }
+
+    fn migrate_deprecated(&mut self) {
+        let oauth_encryption_key = self
+            .anilist_api
+            .oauth
+            .as_mut()
+            .and_then(|oauth| oauth.encryption_key.take());
+
+        if let Some(encryption_key) = oauth_encryption_key {
+            eprintln!(
+                "anilist_api.oauth.encryption_key is deprecated, move it to the top-level encryption_key."
+            );
+            self.encryption_key.get_or_insert(encryption_key);
+        }
+    }
+
+    pub fn encryption_key(&self) -> Result<&str> {
+        self.encryption_key
+            .as_deref()
+            .ok_or(CustomError::boxed("No encryption key provided."))
+    }
+
+    pub fn enriched_statuses(&self) -> Vec<&String> {
+        let mut statuses: Vec<&String> = [
+            Some(&self.transform.enriched_statuses),
+            self.mangadex_api.enriched_statuses.as_ref(),
+            self.mangaupdates_api.enriched_statuses.as_ref(),
+            self.subsplease.rss.enriched_statuses.as_ref(),
+            self.subsplease.scraper.enriched_statuses.as_ref(),
+        ]
+        .into_iter()
+        .chain(
+            self.feeds
+                .iter()
+                .map(|feed| feed.enriched_statuses.as_ref()),
+        )
+        .chain(
+            self.streams
+                .iter()
+                .map(|stream| stream.enriched_statuses.as_ref()),
+        )
+        .flatten()
+        .flatten()
+        .collect();
+        statuses.sort();
+        statuses.dedup();
+
+        statuses
+    }
+
+    pub fn release_type(&self, source: &str) -> ReleaseType {
+        if self.streams.iter().any(|stream| stream.platform == source) {
+            return ReleaseType::Official;
+        }
+
+        self.feeds
+            .iter()
+            .find(|feed| feed.name == source)
+            .map(|feed| feed.release_type)
+            .unwrap_or_default()
+    }
 }
 
 impl Default for Config {
@@ -98,6 +591,77 @@ mod tests {
         assert_eq!(config.aggregator.ttl, 600);
     }
 
+    #[test]
+    fn test_deprecated_encryption_key() {
+        let mut config = Config::from_file("config/config.toml");
+        config.encryption_key = None;
+        config.anilist_api.oauth = Some(AniListOAuthConfig {
+            client_id: "1".to_owned(),
+            client_secret: "secret".to_owned(),
+            redirect_uri: "http://localhost:8080/oauth/anilist/callback".to_owned(),
+            authorize_url: AniListOAuthConfig::default_authorize_url(),
+            token_url: AniListOAuthConfig::default_token_url(),
+            encryption_key: Some("old".to_owned()),
+        });
+
+        config.migrate_deprecated();
+        assert_eq!(config.encryption_key().unwrap(), "old");
+        assert_eq!(config.anilist_api.oauth.unwrap().encryption_key, None);
+
+        let mut config = Config::from_file("config/config.toml");
+        config.encryption_key = Some("new".to_owned());
+        config.anilist_api.oauth = Some(AniListOAuthConfig {
+            client_id: "1".to_owned(),
+            client_secret: "secret".to_owned(),
+            redirect_uri: "http://localhost:8080/oauth/anilist/callback".to_owned(),
+            authorize_url: AniListOAuthConfig::default_authorize_url(),
+            token_url: AniListOAuthConfig::default_token_url(),
+            encryption_key: Some("old".to_owned()),
+        });
+
+        config.migrate_deprecated();
+        assert_eq!(config.encryption_key().unwrap(), "new");
+    }
+
+    #[test]
+    fn test_enriched_statuses() {
+        let mut config = Config::from_file("config/config.toml");
+        config.transform.enriched_statuses = vec!["CURRENT".to_owned(), "REPEATING".to_owned()];
+        config.subsplease.rss.enriched_statuses =
+            Some(vec!["CURRENT".to_owned(), "PLANNING".to_owned()]);
+        config.mangadex_api.enriched_statuses = None;
+        config.subsplease.scraper.enriched_statuses = None;
+
+        assert_eq!(
+            config.enriched_statuses(),
+            vec!["CURRENT", "PLANNING", "REPEATING"]
+        );
+    }
+
+    #[test]
+    fn test_mangadex_filters() {
+        let filters = MangaDexFilters {
+            translated_language: vec!["en".to_owned()],
+            included_groups: Vec::new(),
+            excluded_groups: vec!["bad".to_owned()],
+        };
+        assert!(filters.allows(&["good"]));
+        assert!(filters.allows(&[]));
+        assert!(!filters.allows(&["good", "bad"]));
+
+        let filters = filters.with_override(Some(&MangaDexFilters {
+            translated_language: vec!["es-la".to_owned()],
+            included_groups: vec!["good".to_owned()],
+            ..Default::default()
+        }));
+        assert_eq!(filters.translated_language, vec!["es-la"]);
+        assert_eq!(filters.excluded_groups, vec!["bad"]);
+        assert!(filters.allows(&["good"]));
+        assert!(!filters.allows(&["other"]));
+        assert_eq!(filters.key(), "include:good;exclude:bad");
+        assert_eq!(MangaDexFilters::default().key(), "all");
+    }
+
     #[test]
     #[should_panic]
     fn test_from_file_failure() {
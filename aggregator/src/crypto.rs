@@ -0,0 +1,97 @@
+use crate::error::CustomError;
+use crate::result::Result;
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use sha2::{Digest, Sha256};
+
+const NONCE_SIZE: usize = 12;
+const TOKEN_SIZE: usize = 32;
+
+fn cipher(key: &str) -> Result<Aes256Gcm> {
+    let key = STANDARD.decode(key)?;
+    if key.len() != 32 {
+        return Err(CustomError::boxed("Encryption key must be 32 bytes."));
+    }
+
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+}
+
+pub fn encrypt(key: &str, plaintext: &str) -> Result<String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(key)?
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| CustomError::boxed("Could not encrypt."))?;
+
+    let mut encrypted = nonce.to_vec();
+    encrypted.extend(ciphertext);
+
+    Ok(STANDARD.encode(encrypted))
+}
+
+pub fn decrypt(key: &str, encrypted: &str) -> Result<String> {
+    let encrypted = STANDARD.decode(encrypted)?;
+    if encrypted.len() < NONCE_SIZE {
+        return Err(CustomError::boxed("Could not decrypt."));
+    }
+
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_SIZE);
+    let plaintext = cipher(key)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CustomError::boxed("Could not decrypt."))?;
+
+    Ok(String::from_utf8(plaintext)?)
+}
+
+pub fn generate_token() -> String {
+    let mut token = [0u8; TOKEN_SIZE];
+    OsRng.fill_bytes(&mut token);
+
+    URL_SAFE_NO_PAD.encode(token)
+}
+
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt() {
+        let key = STANDARD.encode([1u8; 32]);
+        let other_key = STANDARD.encode([2u8; 32]);
+
+        let encrypted = encrypt(&key, "token").unwrap();
+        assert_ne!(encrypted, "token");
+        assert_ne!(encrypted, encrypt(&key, "token").unwrap());
+        assert_eq!(decrypt(&key, &encrypted).unwrap(), "token");
+
+        assert!(decrypt(&other_key, &encrypted).is_err());
+        assert!(encrypt(&STANDARD.encode([1u8; 16]), "token").is_err());
+    }
+
+    #[test]
+    fn test_token() {
+        let token = generate_token();
+        assert_eq!(URL_SAFE_NO_PAD.decode(&token).unwrap().len(), TOKEN_SIZE);
+        assert_ne!(token, generate_token());
+
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), hash_token(&generate_token()));
+        assert_eq!(
+            hash_token("token"),
+            "3c469e9d6c5875d37a43f353d4f88e61fcf812c66eee3457465a40b0da4153e0"
+        );
+    }
+}
@@ -3,16 +3,21 @@ use crate::anilist_api::Media;
 use crate::anilist_api::User;
 use crate::config::Config;
 use crate::error::CustomError;
+use crate::mangaupdates_api::MangaUpdatesLatestEntry;
+use crate::match_overrides_db::MatchOverride;
+use crate::media_mappings_db::MediaMappingsEntry;
+use crate::release::Release;
 use crate::result::Result;
 use crate::sources::Document;
 
 use mongodb::{
     bson::doc,
-    options::{ClientOptions, FindOneAndUpdateOptions, IndexOptions},
+    options::{ClientOptions, IndexOptions},
     IndexModel,
 };
 use std::{collections::hash_map::DefaultHasher, hash::Hasher};
-use tokio::task::JoinSet;
+
+const BULK_WRITE_SIZE: usize = 1000;
 
 pub struct MongoDB<'a> {
     pub client: mongodb::Client,
@@ -20,7 +25,7 @@ pub struct MongoDB<'a> {
 }
 
 impl MongoDB<'_> {
-    pub async fn new(config: &Config) -> MongoDB {
+    pub async fn new(config: &Config) -> MongoDB<'_> {
         let mut options = ClientOptions::parse(&config.db.mongodb.uri).await.unwrap();
         options.app_name = Some("oshirase-aggregator".to_owned());
         let client = mongodb::Client::with_options(options).unwrap();
@@ -28,32 +33,77 @@ impl MongoDB<'_> {
         MongoDB { client, config }
     }
 
-    pub async fn init(config: &Config) -> MongoDB {
+    pub async fn init(config: &Config) -> MongoDB<'_> {
         let mongodb = MongoDB::new(config).await;
+        mongodb.migrate_users().await.unwrap();
 
         tokio::try_join!(
             mongodb.create_unique_index::<Media>("anime", "media_id"),
             mongodb.create_unique_index::<Media>("manga", "media_id"),
             mongodb.create_unique_index::<Media>("anime", "hash"),
             mongodb.create_unique_index::<Media>("manga", "hash"),
-            mongodb.create_unique_index::<User>("users", "id"),
+            mongodb.create_unique_compound_index::<User>("users", &["id", "provider"]),
             mongodb.create_unique_index::<AltTitlesEntry>("alt_titles", "media_id"),
+            mongodb.create_unique_index::<MediaMappingsEntry>("media_mappings", "media_id"),
+            mongodb.create_unique_index::<MangaUpdatesLatestEntry>("mangaupdates_latest", "key"),
+            mongodb.create_unique_compound_index::<Release>(
+                "releases",
+                &["media_id", "source", "url"]
+            ),
+            mongodb.create_unique_compound_index::<MatchOverride>(
+                "match_overrides",
+                &["source", "media_id"]
+            ),
         )
         .unwrap();
 
         mongodb
     }
 
+    // Users were unique by id before ids were scoped by provider, and users saved
+    // back then have no provider.
+    async fn migrate_users(&self) -> Result<()> {
+        let users = self
+            .client
+            .database(&self.config.db.mongodb.database)
+            .collection::<User>("users");
+
+        users
+            .update_many(
+                doc! { "provider": null },
+                doc! { "$set": { "provider": "anilist" } },
+                None,
+            )
+            .await?;
+        // Fails when the index or the collection doesn't exist, which is fine.
+        let _ = users.drop_index("id_1", None).await;
+
+        Ok(())
+    }
+
     async fn create_unique_index<T>(&self, collection: &str, key: &str) -> Result<()>
+    where
+        T: Document,
+    {
+        self.create_unique_compound_index::<T>(collection, &[key])
+            .await
+    }
+
+    async fn create_unique_compound_index<T>(&self, collection: &str, keys: &[&str]) -> Result<()>
     where
         T: Document,
     {
         let database = self.client.database(&self.config.db.mongodb.database);
         let collection = database.collection::<T>(collection);
 
+        let mut index_keys = bson::Document::new();
+        for key in keys {
+            index_keys.insert(*key, 1);
+        }
+
         let index_options = IndexOptions::builder().unique(true).build();
         let index = IndexModel::builder()
-            .keys(doc! { format!("{}", key): 1 })
+            .keys(index_keys)
             .options(index_options)
             .build();
         collection.create_index(index, None).await?;
@@ -80,8 +130,7 @@ impl MongoDB<'_> {
     where
         T: Document,
     {
-        let mut futures = JoinSet::new();
-
+        let mut updates = Vec::with_capacity(documents.len());
         for document in documents {
             let hash = Self::hash_document(document);
 
@@ -90,29 +139,74 @@ impl MongoDB<'_> {
 
             let id = document
                 .get(id_key)
-                .ok_or(CustomError::boxed(&format!("Could not find {}.", id_key)))?;
-
-            let collection = self
-                .client
-                .clone()
-                .database(&self.config.db.mongodb.database)
-                .collection::<bson::Document>(collection);
-            let filter = doc! { format!("{}", id_key): id };
-            let update = doc! { "$set": document };
-
-            futures.spawn(async move {
-                collection
-                    .find_one_and_update(
-                        filter,
-                        update,
-                        FindOneAndUpdateOptions::builder().upsert(true).build(),
-                    )
-                    .await
+                .ok_or(CustomError::boxed(&format!("Could not find {}.", id_key)))?
+                .clone();
+
+            updates.push(doc! {
+                "q": { id_key: id },
+                "u": { "$set": document },
+                "upsert": true,
             });
         }
 
-        while let Some(future) = futures.join_next().await {
-            future??;
+        self.bulk_update(collection, updates).await
+    }
+
+    pub async fn insert_new_documents<T>(
+        &self,
+        collection: &str,
+        documents: &[T],
+        keys: &[&str],
+    ) -> Result<()>
+    where
+        T: Document,
+    {
+        let mut updates = Vec::with_capacity(documents.len());
+        for document in documents {
+            let mut document = bson::to_document(document)?;
+            document.extend(doc! { "created": bson::DateTime::now() });
+
+            let mut filter = bson::Document::new();
+            for key in keys {
+                let value = document
+                    .get(key)
+                    .ok_or(CustomError::boxed(&format!("Could not find {}.", key)))?;
+                filter.insert(*key, value.clone());
+            }
+
+            updates.push(doc! {
+                "q": filter,
+                "u": { "$setOnInsert": document },
+                "upsert": true,
+            });
+        }
+
+        self.bulk_update(collection, updates).await
+    }
+
+    async fn bulk_update(&self, collection: &str, updates: Vec<bson::Document>) -> Result<()> {
+        let database = self.client.database(&self.config.db.mongodb.database);
+
+        for batch in updates.chunks(BULK_WRITE_SIZE) {
+            let result = database
+                .run_command(
+                    doc! { "update": collection, "updates": batch, "ordered": false },
+                    None,
+                )
+                .await?;
+
+            if let Some(error) = result
+                .get_array("writeErrors")
+                .ok()
+                .and_then(|errors| errors.first())
+                .and_then(|error| error.as_document())
+            {
+                return Err(CustomError::boxed(&format!(
+                    "Could not write to {}: {}",
+                    collection,
+                    error.get_str("errmsg").unwrap_or("unknown error")
+                )));
+            }
         }
 
         Ok(())
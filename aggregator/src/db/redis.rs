@@ -1,15 +1,14 @@
 use crate::config::Config;
 
-pub struct Redis<'a> {
-    pub config: &'a Config,
+pub struct Redis {
     pub client: redis::Client,
 }
 
-impl Redis<'_> {
+impl Redis {
     pub fn new(config: &Config) -> Redis {
         let client = redis::Client::open(config.db.redis.uri.as_str()).unwrap();
 
-        Redis { client, config }
+        Redis { client }
     }
 }
 
@@ -0,0 +1,453 @@
+use crate::config::{Config, HttpBackend};
+use crate::error::CustomError;
+use crate::result::Result;
+
+use reqwest::{header::CONTENT_TYPE, IntoUrl, Request, RequestBuilder, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use std::{fs, path::PathBuf};
+use tokio::time::{sleep, Duration};
+
+// Only bodies are recorded, so request headers like Authorization never reach a
+// fixture, but token responses and OAuth request bodies carry these fields.
+const SECRET_FIELDS: [&str; 4] = ["access_token", "refresh_token", "client_secret", "password"];
+const REDACTED: &str = "REDACTED";
+
+fn redact_json(value: &mut serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Object(fields) => {
+            let mut redacted = false;
+            for (key, value) in fields.iter_mut() {
+                if SECRET_FIELDS.contains(&key.as_str()) && !value.is_null() {
+                    *value = serde_json::Value::String(REDACTED.to_owned());
+                    redacted = true;
+                } else {
+                    redacted |= redact_json(value);
+                }
+            }
+            redacted
+        }
+        serde_json::Value::Array(values) => {
+            let mut redacted = false;
+            for value in values {
+                redacted |= redact_json(value);
+            }
+            redacted
+        }
+        _ => false,
+    }
+}
+
+fn redact_form(form: &str) -> String {
+    form.split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if SECRET_FIELDS.contains(&key) => format!("{}={}", key, REDACTED),
+            _ => pair.to_owned(),
+        })
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+fn redact(body: &str, form: bool) -> String {
+    match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(mut value) => {
+            if redact_json(&mut value) {
+                value.to_string()
+            } else {
+                body.to_owned()
+            }
+        }
+        Err(_) if form => redact_form(body),
+        Err(_) => body.to_owned(),
+    }
+}
+
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub url: Url,
+    pub status: StatusCode,
+    body: String,
+}
+
+impl HttpResponse {
+    pub fn error_for_status(self) -> Result<HttpResponse> {
+        if self.status.is_client_error() || self.status.is_server_error() {
+            return Err(CustomError::boxed(&format!(
+                "HTTP status {} for url ({}).",
+                self.status, self.url
+            )));
+        }
+
+        Ok(self)
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(serde_json::from_str(&self.body)?)
+    }
+
+    pub fn text(self) -> String {
+        self.body
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    source: String,
+    backend: HttpBackend,
+    fixture_dir: PathBuf,
+}
+
+impl HttpClient {
+    pub fn new(config: &Config, source: &str) -> HttpClient {
+        HttpClient {
+            client: reqwest::Client::new(),
+            source: source.to_owned(),
+            backend: config.http.backend(source),
+            fixture_dir: PathBuf::from(&config.http.fixture_dir),
+        }
+    }
+
+    pub fn is_fixture(&self) -> bool {
+        self.backend == HttpBackend::Fixture
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.backend == HttpBackend::Record
+    }
+
+    pub async fn throttle(&self, duration: Duration) {
+        if !self.is_fixture() {
+            sleep(duration).await;
+        }
+    }
+
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub async fn send(&self, request: RequestBuilder) -> Result<HttpResponse> {
+        let request = request.build()?;
+        if self.is_fixture() {
+            return self.read_fixture(&request);
+        }
+
+        let (_, variant) = Self::fixture_name(&request);
+        let response = self.client.execute(request).await?;
+        let extension = Self::extension(&response);
+        let response = HttpResponse {
+            url: response.url().to_owned(),
+            status: response.status(),
+            body: response.text().await?,
+        };
+
+        if self.is_recording() {
+            if response.status.is_success() {
+                self.write_fixture(&variant, extension, &redact(&response.body, false))?;
+            } else {
+                eprintln!(
+                    "Not recording {} response for {}.",
+                    response.status, response.url
+                );
+            }
+        }
+
+        Ok(response)
+    }
+
+    #[cfg(feature = "webdriver")]
+    pub fn save_fixture(&self, request: RequestBuilder, extension: &str, body: &str) -> Result<()> {
+        let (_, variant) = Self::fixture_name(&request.build()?);
+        self.write_fixture(&variant, extension, body)
+    }
+
+    fn extension(response: &Response) -> &'static str {
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .unwrap_or_default();
+
+        if content_type.contains("json") {
+            "json"
+        } else if content_type.contains("xml") {
+            "xml"
+        } else if content_type.contains("html") {
+            "html"
+        } else {
+            "txt"
+        }
+    }
+
+    fn write_fixture(&self, name: &str, extension: &str, body: &str) -> Result<()> {
+        let dir = self.fixture_dir.join(&self.source);
+        fs::create_dir_all(&dir)?;
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.file_stem().and_then(|stem| stem.to_str()) == Some(name) {
+                fs::remove_file(path)?;
+            }
+        }
+        fs::write(dir.join(format!("{}.{}", name, extension)), body)?;
+
+        Ok(())
+    }
+
+    fn fixture_name(request: &Request) -> (String, String) {
+        let url = request.url();
+        let name: String = format!(
+            "{}_{}{}",
+            request.method().as_str().to_lowercase(),
+            url.host_str().unwrap_or_default(),
+            url.path()
+        )
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+            _ => '_',
+        })
+        .collect();
+        let name = name.trim_end_matches('_').to_owned();
+
+        let mut variant = redact_form(url.query().unwrap_or_default());
+        if let Some(body) = request.body().and_then(|body| body.as_bytes()) {
+            variant.push_str(&redact(&String::from_utf8_lossy(body), true));
+        }
+        let variant = format!("{}-{:016x}", name, fnv1a(variant.as_bytes()));
+
+        (name, variant)
+    }
+
+    fn find_fixture(&self, request: &Request) -> Option<PathBuf> {
+        let dir = self.fixture_dir.join(&self.source);
+        let (name, variant) = Self::fixture_name(request);
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        paths.sort();
+
+        let stem = |path: &PathBuf| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .map(|stem| stem.to_owned())
+        };
+        let exact = paths
+            .iter()
+            .find(|path| stem(path).as_deref() == Some(variant.as_str()))
+            .or_else(|| {
+                paths
+                    .iter()
+                    .find(|path| stem(path).as_deref() == Some(name.as_str()))
+            });
+        if let Some(path) = exact {
+            return Some(path.to_owned());
+        }
+
+        let prefix = format!("{}-", name);
+        let mut variants = paths.iter().filter(|path| {
+            stem(path)
+                .and_then(|stem| stem.strip_prefix(&prefix).map(|hash| hash.len() == 16))
+                .unwrap_or(false)
+        });
+        match (variants.next(), variants.next()) {
+            (Some(path), None) => Some(path.to_owned()),
+            _ => None,
+        }
+    }
+
+    fn read_fixture(&self, request: &Request) -> Result<HttpResponse> {
+        let path = self
+            .find_fixture(request)
+            .ok_or(CustomError::boxed(&format!(
+                "No {} fixture for {} {} in {}.",
+                self.source,
+                request.method(),
+                request.url(),
+                self.fixture_dir.join(&self.source).display()
+            )))?;
+
+        Ok(HttpResponse {
+            url: request.url().to_owned(),
+            status: StatusCode::OK,
+            body: fs::read_to_string(path)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn client(dir: &str) -> HttpClient {
+        let mut config = Config::default();
+        config.http.backend = HttpBackend::Fixture;
+        config.http.fixture_dir = std::env::temp_dir().join(dir).to_str().unwrap().to_owned();
+        HttpClient::new(&config, "test_source")
+    }
+
+    #[tokio::test]
+    async fn test_record_replay() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/schedule"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "day": "Monday" })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/missing"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let mut http = client("oshirase-http-record");
+        let dir = http.fixture_dir.join("test_source");
+        let _ = fs::remove_dir_all(&dir);
+        http.backend = HttpBackend::Record;
+
+        let url = format!("{}/schedule", server.uri());
+        let recorded: serde_json::Value = http
+            .send(http.get(&url).query(&[("tz", "UTC")]))
+            .await
+            .unwrap()
+            .json()
+            .unwrap();
+        let missing = http
+            .send(http.get(format!("{}/missing", server.uri())))
+            .await;
+        assert_eq!(missing.unwrap().status, StatusCode::NOT_FOUND);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        http.backend = HttpBackend::Fixture;
+        let replayed: serde_json::Value = http
+            .send(http.get(&url).query(&[("tz", "UTC")]))
+            .await
+            .unwrap()
+            .json()
+            .unwrap();
+        assert_eq!(replayed, recorded);
+        assert!(http
+            .send(http.get(format!("{}/missing", server.uri())))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_record_redacts_secrets() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "access-secret",
+                "token": { "refresh_token": "refresh-secret", "expires_in": 900 }
+            })))
+            .mount(&server)
+            .await;
+
+        let mut http = client("oshirase-http-redact");
+        let dir = http.fixture_dir.join("test_source");
+        let _ = fs::remove_dir_all(&dir);
+        http.backend = HttpBackend::Record;
+
+        let url = format!("{}/token", server.uri());
+        let requests = reqwest::Client::new();
+        let token = |password: &str| {
+            requests
+                .post(&url)
+                .bearer_auth("bearer-secret")
+                .form(&[("username", "test"), ("password", password)])
+        };
+        let live: serde_json::Value = http
+            .send(token("password-secret"))
+            .await
+            .unwrap()
+            .json()
+            .unwrap();
+        assert_eq!(live["access_token"], "access-secret");
+
+        let paths: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(paths.len(), 1);
+        let recorded = fs::read_to_string(&paths[0]).unwrap();
+        assert!(!recorded.contains("secret"));
+
+        http.backend = HttpBackend::Fixture;
+        let replayed: serde_json::Value = http
+            .send(token("other-password"))
+            .await
+            .unwrap()
+            .json()
+            .unwrap();
+        assert_eq!(replayed["access_token"], REDACTED);
+        assert_eq!(replayed["token"]["refresh_token"], REDACTED);
+        assert_eq!(replayed["token"]["expires_in"], 900);
+    }
+
+    #[tokio::test]
+    async fn test_find_fixture() {
+        let http = client("oshirase-http-fixture");
+        let dir = http.fixture_dir.join("test_source");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let request = |f: &str| {
+            http.get("http://localhost/api/")
+                .query(&[("f", f)])
+                .build()
+                .unwrap()
+        };
+        let (name, schedule) = HttpClient::fixture_name(&request("schedule"));
+        let (_, latest) = HttpClient::fixture_name(&request("latest"));
+        assert_eq!(name, "get_localhost_api");
+        let path = |stem: &str| dir.join(format!("{}.json", stem));
+
+        fs::write(path(&schedule), "{}").unwrap();
+        assert_eq!(
+            http.find_fixture(&request("schedule")),
+            Some(path(&schedule))
+        );
+        assert_eq!(http.find_fixture(&request("other")), Some(path(&schedule)));
+
+        fs::write(path(&latest), "{}").unwrap();
+        assert_eq!(http.find_fixture(&request("latest")), Some(path(&latest)));
+        assert_eq!(http.find_fixture(&request("other")), None);
+
+        fs::write(path(&name), "{}").unwrap();
+        assert_eq!(
+            http.find_fixture(&request("schedule")),
+            Some(path(&schedule))
+        );
+        assert_eq!(http.find_fixture(&request("other")), Some(path(&name)));
+    }
+
+    #[test]
+    fn test_backend() {
+        let mut config = Config::default();
+        assert_eq!(config.http.backend("subsplease_rss"), HttpBackend::Live);
+
+        config
+            .http
+            .sources
+            .insert("subsplease_rss".to_owned(), HttpBackend::Fixture);
+        assert!(HttpClient::new(&config, "subsplease_rss").is_fixture());
+        assert!(!HttpClient::new(&config, "mangadex_api").is_fixture());
+    }
+}
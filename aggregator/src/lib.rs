@@ -1,8 +1,15 @@
 mod config;
+mod crypto;
 mod db;
 mod error;
+mod http;
+mod ngram_index;
 mod options;
+mod progress;
+mod release;
+mod report;
 mod result;
+mod server;
 mod sources;
 mod test;
 mod worker;
@@ -10,36 +17,110 @@ mod worker;
 use alt_titles_db::*;
 use anilist_api::*;
 use db::MongoDB;
+use kitsu_api::*;
 use mangadex_api::*;
+use mangaupdates_api::*;
+use match_overrides_db::*;
+use media_mappings_db::*;
+use myanimelist_api::*;
 use options::*;
+use release_feed::*;
+use simulcast_feed::*;
 use sources::*;
 use subsplease_rss::*;
 use subsplease_scraper::*;
 
-pub use config::Config;
+pub use anilist_api::{
+    Latest, Media, MediaType, Provider, ReleaseType, UserPreferences, UserProfile,
+};
+pub use config::{Config, MangaDexFilters};
 pub use error::CustomError;
+pub use mangadex_api::MangaDexCredentials;
+pub use match_overrides_db::MatchOverride;
+pub use ngram_index::NgramIndex;
+pub use options::TransformOptions;
+pub use progress::{MediaProgress, ProgressReport, ProgressSort};
+pub use release::{release_cadence, released_ago, Release, ReleaseNumber};
+pub use report::{RunIssue, RunReport};
 pub use result::Result;
+pub use server::Server;
+pub use sources::{Similar, Transform};
+pub use subsplease_rss::SubsPleaseRSS;
+pub use subsplease_scraper::ScheduledAnime;
 pub use worker::Worker;
 
+use futures::TryStreamExt;
+use mongodb::{bson::doc, options::FindOptions};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use time::{Duration, OffsetDateTime};
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Data {
     lists: MediaLists,
     schedule: AnimeSchedule,
     alt_titles: AltTitles,
+    match_overrides: MatchOverrides,
+    media_mappings: MediaMappings,
     anime_latest: AnimeLatest,
     manga_latest: MangaLatest,
+    mangaupdates_latest: MangaUpdatesLatest,
+    feeds: Vec<FeedLatest>,
+    streams: Vec<StreamLatest>,
+    report: RunReport,
+}
+
+impl Data {
+    pub fn report(&self) -> &RunReport {
+        &self.report
+    }
 }
 
 pub struct Aggregator<'a> {
     config: &'a Config,
+    mongodb_client: Option<mongodb::Client>,
+}
+
+fn transform_similar<T>(
+    source: &T,
+    media: &mut Media,
+    extras: &HashMap<String, T::Extra>,
+    index: &NgramIndex,
+) where
+    T: Transform + Similar,
+{
+    match source.transform(media, extras, Some(TransformOptions { index: Some(index) })) {
+        Ok(transformed) => *media = transformed,
+        Err(err) => eprintln!("Could not transform media: {}", err),
+    }
 }
 
 impl<'a> Aggregator<'a> {
     pub fn new(config: &'a Config) -> Aggregator<'a> {
-        Aggregator { config }
+        Aggregator {
+            config,
+            mongodb_client: None,
+        }
+    }
+
+    // Long-running callers like the server connect and create the indexes once
+    // and hand the client to every aggregator they build.
+    pub fn with_mongodb_client(config: &'a Config, client: mongodb::Client) -> Aggregator<'a> {
+        Aggregator {
+            config,
+            mongodb_client: Some(client),
+        }
+    }
+
+    async fn mongodb(&self) -> MongoDB<'a> {
+        match &self.mongodb_client {
+            Some(client) => MongoDB {
+                client: client.clone(),
+                config: self.config,
+            },
+            None => MongoDB::init(self.config).await,
+        }
     }
 
     async fn extract(
@@ -47,31 +128,180 @@ impl<'a> Aggregator<'a> {
         sources: &Sources<'a>,
         options: Option<ExtractOptions>,
     ) -> Result<Data> {
-        let (lists, alt_titles, schedule, anime_latest, manga_latest) = tokio::try_join!(
+        let (
+            lists,
+            alt_titles,
+            match_overrides,
+            media_mappings,
+            schedule,
+            anime_latest,
+            manga_latest,
+            mangaupdates_latest,
+            feeds,
+            streams,
+        ) = tokio::try_join!(
             sources.anilist_api.extract(options.clone()),
             sources.alt_titles_db.extract(options.clone()),
+            sources.match_overrides_db.extract(options.clone()),
+            sources.media_mappings_db.extract(options.clone()),
             sources.subsplease_scraper.extract(options.clone()),
             sources.subsplease_rss.extract(options.clone()),
-            sources.mangadex_api.extract(options)
+            sources.mangadex_api.extract(options.clone()),
+            async { Result::Ok(sources.mangaupdates_api.extract(options.clone()).await,) },
+            async {
+                Result::Ok(
+                    futures::future::join_all(
+                        sources
+                            .release_feeds
+                            .iter()
+                            .map(|feed| feed.extract(options.clone())),
+                    )
+                    .await,
+                )
+            },
+            async {
+                Result::Ok(
+                    futures::future::join_all(
+                        sources
+                            .simulcast_feeds
+                            .iter()
+                            .map(|feed| feed.extract(options.clone())),
+                    )
+                    .await,
+                )
+            }
         )?;
 
+        let mut report = options
+            .as_ref()
+            .map(ExtractOptions::take_report)
+            .unwrap_or_default();
+
+        let mangaupdates_latest = match mangaupdates_latest {
+            Ok(mangaupdates_latest) => mangaupdates_latest,
+            Err(err) => {
+                report.add(
+                    sources.mangaupdates_api.get_source(),
+                    &format!("Could not fetch MangaUpdates releases: {}", err),
+                );
+                MangaUpdatesLatest::default()
+            }
+        };
+
+        let feeds = sources
+            .release_feeds
+            .iter()
+            .zip(feeds)
+            .map(|(feed, latest)| match latest {
+                Ok(latest) => latest,
+                Err(err) => {
+                    report.add(
+                        feed.name(),
+                        &format!("Could not fetch feed {}: {}", feed.name(), err),
+                    );
+                    FeedLatest::default()
+                }
+            })
+            .collect();
+
+        let streams = sources
+            .simulcast_feeds
+            .iter()
+            .zip(streams)
+            .map(|(feed, latest)| match latest {
+                Ok(latest) => latest,
+                Err(err) => {
+                    report.add(
+                        feed.platform(),
+                        &format!("Could not fetch stream feed {}: {}", feed.platform(), err),
+                    );
+                    StreamLatest::default()
+                }
+            })
+            .collect();
+
+        for issue in &report.issues {
+            eprintln!("{}", issue);
+        }
+
         Ok(Data {
             lists,
             alt_titles,
+            match_overrides,
+            media_mappings,
             schedule,
             anime_latest,
             manga_latest,
+            mangaupdates_latest,
+            feeds,
+            streams,
+            report,
         })
     }
 
     fn transform(&self, sources: &Sources<'a>, data: &'a mut Data) -> Result<&'a mut Data> {
+        let schedule_index = NgramIndex::new(data.schedule.0.keys().filter(|key| !is_id_key(key)));
+        let anime_latest_index =
+            NgramIndex::new(data.anime_latest.0.keys().filter(|key| !is_id_key(key)));
+        let manga_latest_index =
+            NgramIndex::new(data.manga_latest.0.keys().filter(|key| !is_id_key(key)));
+        let mangaupdates_latest_index = NgramIndex::new(
+            data.mangaupdates_latest
+                .0
+                .keys()
+                .filter(|key| !is_id_key(key)),
+        );
+        let feed_indices: Vec<NgramIndex> = data
+            .feeds
+            .iter()
+            .map(|feed| NgramIndex::new(feed.0.keys().filter(|key| !is_id_key(key))))
+            .collect();
+        let stream_indices: Vec<NgramIndex> = data
+            .streams
+            .iter()
+            .map(|stream| NgramIndex::new(stream.0.keys()))
+            .collect();
+
         let anime = data
             .lists
             .anime
             .par_iter_mut()
             .map(|anime| {
                 let mut transformed =
-                    match sources.alt_titles_db.transform(anime, &data.alt_titles.0) {
+                    match sources
+                        .match_overrides_db
+                        .transform(anime, &data.match_overrides.0, None)
+                    {
+                        Ok(anime) => anime,
+                        Err(err) => {
+                            eprintln!("Could not add match overrides: {}", err);
+                            std::mem::take(anime)
+                        }
+                    };
+                *anime = std::mem::take(&mut transformed);
+                anime
+            })
+            .map(|anime| {
+                let mut transformed =
+                    match sources
+                        .media_mappings_db
+                        .transform(anime, &data.media_mappings.0, None)
+                    {
+                        Ok(anime) => anime,
+                        Err(err) => {
+                            eprintln!("Could not add media mappings: {}", err);
+                            std::mem::take(anime)
+                        }
+                    };
+                *anime = std::mem::take(&mut transformed);
+                anime
+            })
+            .map(|anime| {
+                let mut transformed =
+                    match sources
+                        .alt_titles_db
+                        .transform(anime, &data.alt_titles.0, None)
+                    {
                         Ok(anime) => anime,
                         Err(err) => {
                             eprintln!("Could not add alt titles: {}", err);
@@ -90,30 +320,45 @@ impl<'a> Aggregator<'a> {
                 for extra in extras {
                     match extra {
                         Extras::SubsPleaseScraper(extra) => {
-                            let mut transformed = match extra.transform(anime, &data.schedule.0) {
-                                Ok(anime) => anime,
-                                Err(err) => {
-                                    eprintln!("Could not transform media: {}", err);
-                                    std::mem::take(anime)
-                                }
-                            };
-                            *anime = std::mem::take(&mut transformed);
+                            transform_similar(&extra, anime, &data.schedule.0, &schedule_index);
                         }
                         Extras::SubsPleaseRSS(extra) => {
-                            let mut transformed = match extra.transform(anime, &data.anime_latest.0)
-                            {
-                                Ok(anime) => anime,
-                                Err(err) => {
-                                    eprintln!("Could not transform media: {}", err);
-                                    std::mem::take(anime)
-                                }
-                            };
-                            *anime = std::mem::take(&mut transformed);
+                            transform_similar(
+                                &extra,
+                                anime,
+                                &data.anime_latest.0,
+                                &anime_latest_index,
+                            );
                         }
                         _ => {}
                     }
                 }
 
+                for ((feed, latest), index) in sources
+                    .release_feeds
+                    .iter()
+                    .zip(&data.feeds)
+                    .zip(&feed_indices)
+                {
+                    if latest.0.is_empty() {
+                        continue;
+                    }
+                    transform_similar(feed, anime, &latest.0, index);
+                }
+
+                for ((feed, latest), index) in sources
+                    .simulcast_feeds
+                    .iter()
+                    .zip(&data.streams)
+                    .zip(&stream_indices)
+                {
+                    if latest.0.is_empty() {
+                        continue;
+                    }
+                    transform_similar(feed, anime, &latest.0, index);
+                }
+
+                anime.resolve_latest(&self.config.transform.source_priority.anime);
                 anime
             })
             .map(std::mem::take)
@@ -126,7 +371,40 @@ impl<'a> Aggregator<'a> {
             .par_iter_mut()
             .map(|manga| {
                 let mut transformed =
-                    match sources.alt_titles_db.transform(manga, &data.alt_titles.0) {
+                    match sources
+                        .match_overrides_db
+                        .transform(manga, &data.match_overrides.0, None)
+                    {
+                        Ok(manga) => manga,
+                        Err(err) => {
+                            eprintln!("Could not add match overrides: {}", err);
+                            std::mem::take(manga)
+                        }
+                    };
+                *manga = std::mem::take(&mut transformed);
+                manga
+            })
+            .map(|manga| {
+                let mut transformed =
+                    match sources
+                        .media_mappings_db
+                        .transform(manga, &data.media_mappings.0, None)
+                    {
+                        Ok(manga) => manga,
+                        Err(err) => {
+                            eprintln!("Could not add media mappings: {}", err);
+                            std::mem::take(manga)
+                        }
+                    };
+                *manga = std::mem::take(&mut transformed);
+                manga
+            })
+            .map(|manga| {
+                let mut transformed =
+                    match sources
+                        .alt_titles_db
+                        .transform(manga, &data.alt_titles.0, None)
+                    {
                         Ok(manga) => manga,
                         Err(err) => {
                             eprintln!("Could not add alt titles: {}", err);
@@ -137,21 +415,46 @@ impl<'a> Aggregator<'a> {
                 manga
             })
             .map(|manga| {
-                let extras = [Extras::MangaDexAPI(sources.mangadex_api.clone())];
+                let extras = [
+                    Extras::MangaDexAPI(sources.mangadex_api.clone()),
+                    Extras::MangaUpdatesAPI(sources.mangaupdates_api.clone()),
+                ];
 
                 for extra in extras {
-                    if let Extras::MangaDexAPI(extra) = extra {
-                        let mut transformed = match extra.transform(manga, &data.manga_latest.0) {
-                            Ok(manga) => manga,
-                            Err(err) => {
-                                eprintln!("Could not transform media: {}", err);
-                                std::mem::take(manga)
-                            }
-                        };
-                        *manga = std::mem::take(&mut transformed);
+                    match extra {
+                        Extras::MangaDexAPI(extra) => {
+                            transform_similar(
+                                &extra,
+                                manga,
+                                &data.manga_latest.0,
+                                &manga_latest_index,
+                            );
+                        }
+                        Extras::MangaUpdatesAPI(extra) => {
+                            transform_similar(
+                                &extra,
+                                manga,
+                                &data.mangaupdates_latest.0,
+                                &mangaupdates_latest_index,
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+
+                for ((feed, latest), index) in sources
+                    .release_feeds
+                    .iter()
+                    .zip(&data.feeds)
+                    .zip(&feed_indices)
+                {
+                    if latest.0.is_empty() {
+                        continue;
                     }
+                    transform_similar(feed, manga, &latest.0, index);
                 }
 
+                manga.resolve_latest(&self.config.transform.source_priority.manga);
                 manga
             })
             .map(std::mem::take)
@@ -162,9 +465,20 @@ impl<'a> Aggregator<'a> {
     }
 
     async fn load(&self, data: &'a mut Data, mongodb: &'a MongoDB<'a>) -> Result<&'a mut Data> {
+        let releases: Vec<Release> = data
+            .lists
+            .anime
+            .iter()
+            .chain(data.lists.manga.iter())
+            .filter_map(Release::from_media)
+            .collect::<HashSet<Release>>()
+            .into_iter()
+            .collect();
+
         tokio::try_join!(
             mongodb.upsert_documents("anime", &data.lists.anime, "media_id"),
-            mongodb.upsert_documents("manga", &data.lists.manga, "media_id")
+            mongodb.upsert_documents("manga", &data.lists.manga, "media_id"),
+            mongodb.insert_new_documents("releases", &releases, &["media_id", "source", "url"])
         )?;
 
         Ok(data)
@@ -176,13 +490,19 @@ impl<'a> Aggregator<'a> {
             subsplease_scraper: SubsPleaseScraper::new(self.config),
             subsplease_rss: SubsPleaseRSS::new(self.config),
             mangadex_api: MangaDexAPI::new(self.config),
+            mangaupdates_api: MangaUpdatesAPI::new(self.config),
             alt_titles_db: AltTitlesDB::new(self.config),
+            match_overrides_db: MatchOverridesDB::new(self.config),
+            media_mappings_db: MediaMappingsDB::new(self.config),
+            release_feeds: ReleaseFeed::all(self.config),
+            simulcast_feeds: SimulcastFeed::all(self.config),
         };
 
-        let mongodb = MongoDB::init(self.config).await;
+        let mongodb = self.mongodb().await;
 
         let extract_options = ExtractOptions {
             mongodb_client: Some(mongodb.client.clone()),
+            ..Default::default()
         };
 
         let mut data = self.extract(&sources, Some(extract_options)).await?;
@@ -191,12 +511,378 @@ impl<'a> Aggregator<'a> {
 
         Ok(std::mem::take(data))
     }
+
+    pub async fn add_match_override(&self, match_override: &MatchOverride) -> Result<()> {
+        let mongodb = self.mongodb().await;
+
+        MatchOverridesDB::new(self.config)
+            .add(&mongodb.client, match_override)
+            .await
+    }
+
+    pub async fn fetch_releases(&self, media_id: u64) -> Result<Vec<Release>> {
+        let mongodb = self.mongodb().await;
+
+        let releases = mongodb
+            .client
+            .database(&self.config.db.mongodb.database)
+            .collection::<Release>("releases")
+            .find(
+                doc! { "media_id": media_id as i64 },
+                FindOptions::builder()
+                    .sort(doc! { "released_at": -1, "created": -1 })
+                    .build(),
+            )
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(releases)
+    }
+
+    pub async fn fetch_schedule(
+        &self,
+        timezone: Option<&str>,
+        user_id: Option<u64>,
+        provider: Provider,
+    ) -> Result<Vec<ScheduledAnime>> {
+        let mongodb = self.mongodb().await;
+
+        let user = match user_id {
+            Some(user_id) => AniListAPI::new(self.config)
+                .fetch_user(mongodb.client.clone(), user_id, provider)
+                .await?
+                .ok_or(CustomError::boxed(&format!(
+                    "Could not find user {}.",
+                    user_id
+                )))?,
+            None => User::default(),
+        };
+        let timezone = get_timezone(timezone.or(user.timezone.as_deref()).unwrap_or("UTC"))?;
+        let now = OffsetDateTime::now_utc();
+
+        let anime: Vec<Media> = mongodb
+            .client
+            .database(&self.config.db.mongodb.database)
+            .collection::<Media>("anime")
+            .find(doc! { "schedule": { "$ne": null } }, None)
+            .await?
+            .try_collect()
+            .await?;
+
+        let mut schedule: Vec<ScheduledAnime> = anime
+            .iter()
+            .filter_map(|media| ScheduledAnime::new(media, timezone, now))
+            .collect();
+        schedule.sort_by_key(|scheduled| scheduled.next_release_at);
+
+        Ok(schedule)
+    }
+
+    pub async fn fetch_progress(
+        &self,
+        user_id: u64,
+        provider: Provider,
+        media_type: Option<&str>,
+        sort: ProgressSort,
+    ) -> Result<ProgressReport> {
+        let mongodb = self.mongodb().await;
+        let media_type = match media_type {
+            Some(media_type) => Some(media_type.parse::<MediaType>()?),
+            None => None,
+        };
+
+        let anilist_api = AniListAPI::new(self.config);
+        let user = anilist_api
+            .fetch_user(mongodb.client.clone(), user_id, provider)
+            .await?
+            .ok_or(CustomError::boxed(&format!(
+                "Could not find user {}.",
+                user_id
+            )))?;
+        let lists = anilist_api
+            .fetch_user_lists(mongodb.client.clone(), &user)
+            .await?;
+        let statuses = self.config.enriched_statuses();
+        let media: Vec<Media> = lists
+            .anime
+            .into_iter()
+            .chain(lists.manga)
+            .filter(|media| match &media.status {
+                Some(status) => statuses.contains(&status),
+                None => false,
+            })
+            .filter(|media| media_type.is_none() || media.media_type == media_type)
+            .collect();
+
+        let media_ids: Vec<i64> = media
+            .iter()
+            .filter_map(|media| media.media_id)
+            .map(|media_id| media_id as i64)
+            .collect();
+        let database = mongodb.client.database(&self.config.db.mongodb.database);
+        let filter = doc! { "media_id": { "$in": &media_ids } };
+        let (anime, manga): (Vec<Media>, Vec<Media>) = tokio::try_join!(
+            async {
+                database
+                    .collection::<Media>("anime")
+                    .find(filter.clone(), None)
+                    .await?
+                    .try_collect()
+                    .await
+            },
+            async {
+                database
+                    .collection::<Media>("manga")
+                    .find(filter.clone(), None)
+                    .await?
+                    .try_collect()
+                    .await
+            }
+        )?;
+        let filters = self
+            .config
+            .mangadex_api
+            .filters()
+            .with_override(user.mangadex_filters.as_ref());
+        let filters_key = filters.key();
+        let preferences = user.preferences.clone().unwrap_or_default();
+        let latest: HashMap<u64, Latest> = anime
+            .into_iter()
+            .chain(manga)
+            .filter_map(|media| {
+                let preferred = media
+                    .latest_by_filters
+                    .clone()
+                    .and_then(|mut by_filters| by_filters.remove(&filters_key))
+                    .and_then(|mut by_language| {
+                        filters
+                            .translated_language
+                            .iter()
+                            .find_map(|language| by_language.remove(language))
+                    });
+                let latest = preferences.latest(preferred, &media, self.config);
+                Some((media.media_id?, latest?))
+            })
+            .collect();
+
+        let now = OffsetDateTime::now_utc();
+        let stale_after = Duration::weeks(self.config.progress.stale_weeks);
+        let entries = media
+            .iter()
+            .map(|media| {
+                let latest = media.media_id.and_then(|media_id| latest.get(&media_id));
+                MediaProgress::new(media, latest, now, stale_after)
+            })
+            .collect();
+
+        Ok(ProgressReport::new(entries, sort))
+    }
+
+    pub async fn save_progress(
+        &self,
+        user_id: u64,
+        provider: Provider,
+        media_id: u64,
+        progress: u64,
+        dry_run: bool,
+    ) -> Result<SavedMediaListEntry> {
+        let mongodb = self.mongodb().await;
+        let anilist_api = AniListAPI::new(self.config);
+
+        let user = anilist_api
+            .fetch_user(mongodb.client.clone(), user_id, provider)
+            .await?
+            .ok_or(CustomError::boxed(&format!(
+                "Could not find user {}.",
+                user_id
+            )))?;
+
+        anilist_api
+            .save_progress(&user, media_id, progress, dry_run)
+            .await
+    }
+
+    pub async fn set_access_token(&self, user_id: u64, access_token: &str) -> Result<()> {
+        let mongodb = self.mongodb().await;
+
+        AniListAPI::new(self.config)
+            .set_access_token(mongodb.client.clone(), user_id, access_token, None)
+            .await
+    }
+
+    pub async fn add_user(&self, name: &str, provider: Provider) -> Result<UserProfile> {
+        let mongodb = self.mongodb().await;
+
+        let user = match provider {
+            Provider::AniList => {
+                AniListAPI::new(self.config)
+                    .add_user(mongodb.client.clone(), name)
+                    .await?
+            }
+            Provider::Kitsu => {
+                KitsuAPI::new(self.config)
+                    .add_user(mongodb.client.clone(), name)
+                    .await?
+            }
+            Provider::MyAnimeList => {
+                MyAnimeListAPI::new(self.config)
+                    .add_user(mongodb.client.clone(), name)
+                    .await?
+            }
+        };
+
+        Ok(UserProfile::from(&user))
+    }
+
+    pub async fn fetch_users(&self) -> Result<Vec<UserProfile>> {
+        let mongodb = self.mongodb().await;
+
+        let users = AniListAPI::new(self.config)
+            .fetch_users(mongodb.client.clone())
+            .await?;
+
+        Ok(users.iter().map(UserProfile::from).collect())
+    }
+
+    pub async fn fetch_user(&self, user_id: u64, provider: Provider) -> Result<UserProfile> {
+        let mongodb = self.mongodb().await;
+
+        let user = AniListAPI::new(self.config)
+            .fetch_user(mongodb.client.clone(), user_id, provider)
+            .await?
+            .ok_or(CustomError::boxed(&format!(
+                "Could not find user {}.",
+                user_id
+            )))?;
+
+        Ok(UserProfile::from(&user))
+    }
+
+    pub async fn remove_user(&self, user_id: u64, provider: Provider) -> Result<()> {
+        let mongodb = self.mongodb().await;
+
+        AniListAPI::new(self.config)
+            .remove_user(mongodb.client.clone(), user_id, provider)
+            .await
+    }
+
+    pub async fn set_mangadex_credentials(
+        &self,
+        user_id: u64,
+        provider: Provider,
+        credentials: &MangaDexCredentials,
+    ) -> Result<()> {
+        let mongodb = self.mongodb().await;
+
+        MangaDexAPI::new(self.config)
+            .set_credentials(mongodb.client.clone(), user_id, provider, credentials)
+            .await
+    }
+
+    pub async fn set_mangadex_filters(
+        &self,
+        user_id: u64,
+        provider: Provider,
+        filters: &MangaDexFilters,
+    ) -> Result<()> {
+        let mongodb = self.mongodb().await;
+
+        MangaDexAPI::new(self.config)
+            .set_filters(mongodb.client.clone(), user_id, provider, filters)
+            .await
+    }
+
+    pub async fn set_preferences(
+        &self,
+        user_id: u64,
+        provider: Provider,
+        preferences: &UserPreferences,
+    ) -> Result<()> {
+        let mongodb = self.mongodb().await;
+
+        AniListAPI::new(self.config)
+            .set_preferences(mongodb.client.clone(), user_id, provider, preferences)
+            .await
+    }
+
+    pub async fn set_timezone(
+        &self,
+        user_id: u64,
+        provider: Provider,
+        timezone: Option<&str>,
+    ) -> Result<()> {
+        let mongodb = self.mongodb().await;
+
+        AniListAPI::new(self.config)
+            .set_timezone(mongodb.client.clone(), user_id, provider, timezone)
+            .await
+    }
+
+    pub async fn authorize_url(&self, session: &str) -> Result<String> {
+        let mongodb = self.mongodb().await;
+
+        AniListAPI::new(self.config)
+            .authorize_url(mongodb.client.clone(), session)
+            .await
+    }
+
+    pub async fn login(&self, code: &str, state: &str, session: &str) -> Result<(User, String)> {
+        let mongodb = self.mongodb().await;
+        let api = AniListAPI::new(self.config);
+
+        let user = api
+            .login(mongodb.client.clone(), code, state, session)
+            .await?;
+        let token = api
+            .issue_api_token(mongodb.client.clone(), user.id, user.provider)
+            .await?;
+
+        Ok((user, token))
+    }
+
+    pub async fn issue_api_token(&self, user_id: u64, provider: Provider) -> Result<String> {
+        let mongodb = self.mongodb().await;
+
+        AniListAPI::new(self.config)
+            .issue_api_token(mongodb.client.clone(), user_id, provider)
+            .await
+    }
+
+    pub async fn authenticate(
+        &self,
+        user_id: u64,
+        provider: Provider,
+        token: &str,
+    ) -> Result<bool> {
+        let mongodb = self.mongodb().await;
+
+        AniListAPI::new(self.config)
+            .verify_api_token(mongodb.client.clone(), user_id, provider, token)
+            .await
+    }
+
+    pub async fn import_media_mappings(&self, filename: &str) -> Result<usize> {
+        let mongodb = self.mongodb().await;
+
+        MediaMappingsDB::new(self.config)
+            .import(&mongodb, filename)
+            .await
+    }
+
+    pub async fn remove_match_override(&self, source: &str, media_id: u64) -> Result<()> {
+        let mongodb = self.mongodb().await;
+
+        MatchOverridesDB::new(self.config)
+            .remove(&mongodb.client, source, media_id)
+            .await
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use test::helpers::{init, reset_db, ONCE};
+    use test::helpers::{fixture_config, init, reset_db, ONCE};
 
     use mongodb::bson::doc;
 
@@ -205,7 +891,7 @@ mod tests {
         ONCE.get_or_init(init).await;
         reset_db().await;
 
-        let config = Config::default();
+        let config = fixture_config();
         let mongodb = MongoDB::new(&config).await;
         let aggregator = Aggregator::new(&config);
         aggregator.run().await.unwrap();
@@ -1,9 +1,24 @@
 use aggregator::Aggregator;
 use aggregator::Config;
+use aggregator::CustomError;
+use aggregator::MangaDexCredentials;
+use aggregator::MangaDexFilters;
+use aggregator::MatchOverride;
+use aggregator::ProgressSort;
+use aggregator::Provider;
+use aggregator::ReleaseType;
 use aggregator::Result;
+use aggregator::Server;
+use aggregator::UserPreferences;
 use aggregator::Worker;
+use aggregator::{release_cadence, released_ago};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::{
+    env,
+    io::{self, Write},
+};
+use time::OffsetDateTime;
 
 #[derive(Parser)]
 #[command(
@@ -19,6 +34,280 @@ struct Cli {
 
     #[arg(short, long, help = "Run in worker mode")]
     worker_mode: bool,
+
+    #[arg(short, long, help = "Run in server mode")]
+    server_mode: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Manage manual match overrides")]
+    Override {
+        #[command(subcommand)]
+        command: OverrideCommand,
+    },
+    #[command(about = "Manage cross-source media id mappings")]
+    Mappings {
+        #[command(subcommand)]
+        command: MappingsCommand,
+    },
+    #[command(about = "Manage AniList, Kitsu and MyAnimeList users")]
+    User {
+        #[command(subcommand)]
+        command: UserCommand,
+    },
+    #[command(about = "Show when the current anime air next")]
+    Schedule {
+        #[arg(short, long, help = "Timezone to show times in, e.g. Europe/Berlin")]
+        timezone: Option<String>,
+        #[arg(short, long, help = "User id whose timezone to use")]
+        user_id: Option<u64>,
+        #[arg(
+            short,
+            long,
+            default_value = "anilist",
+            help = "Service the user comes from, anilist, kitsu or myanimelist"
+        )]
+        provider: Provider,
+    },
+    #[command(about = "Show what a user has left to watch or read")]
+    Progress {
+        #[arg(help = "User id")]
+        user_id: u64,
+        #[arg(
+            short,
+            long,
+            default_value = "anilist",
+            help = "Service the user comes from, anilist, kitsu or myanimelist"
+        )]
+        provider: Provider,
+        #[arg(short, long, help = "Only show anime or manga")]
+        media_type: Option<String>,
+        #[arg(
+            short,
+            long,
+            default_value = "unwatched",
+            help = "Sort by unwatched, updated or title"
+        )]
+        sort: ProgressSort,
+    },
+    #[command(about = "Store a user's AniList access token")]
+    Token {
+        #[arg(help = "AniList user id")]
+        user_id: u64,
+        #[arg(help = "AniList access token")]
+        access_token: String,
+    },
+    #[command(about = "Save watched or read progress to AniList")]
+    Sync {
+        #[arg(help = "User id")]
+        user_id: u64,
+        #[arg(
+            short,
+            long,
+            default_value = "anilist",
+            help = "Service the user comes from, anilist, kitsu or myanimelist"
+        )]
+        provider: Provider,
+        #[arg(help = "AniList media id")]
+        media_id: u64,
+        #[arg(help = "Watched episodes or read chapters")]
+        progress: u64,
+        #[arg(short, long, help = "Don't send the mutation to AniList")]
+        dry_run: bool,
+    },
+    #[command(about = "Show the release history of a media")]
+    Releases {
+        #[arg(help = "AniList media id")]
+        media_id: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum UserCommand {
+    #[command(about = "Add a user by AniList, Kitsu or MyAnimeList username")]
+    Add {
+        #[arg(help = "AniList, Kitsu or MyAnimeList username")]
+        name: String,
+        #[arg(
+            short,
+            long,
+            default_value = "anilist",
+            help = "Service the user's lists come from, anilist, kitsu or myanimelist"
+        )]
+        provider: Provider,
+    },
+    #[command(about = "List users")]
+    List,
+    #[command(about = "Remove a user")]
+    Remove {
+        #[arg(help = "User id")]
+        user_id: u64,
+        #[arg(
+            short,
+            long,
+            default_value = "anilist",
+            help = "Service the user comes from, anilist, kitsu or myanimelist"
+        )]
+        provider: Provider,
+    },
+    #[command(about = "Show a user")]
+    Show {
+        #[arg(help = "User id")]
+        user_id: u64,
+        #[arg(
+            short,
+            long,
+            default_value = "anilist",
+            help = "Service the user comes from, anilist, kitsu or myanimelist"
+        )]
+        provider: Provider,
+    },
+    #[command(
+        about = "Store a user's MangaDex personal API client credentials",
+        long_about = "Store a user's MangaDex personal API client credentials. The password and \
+                      client secret are read from OSHIRASE_MANGADEX_PASSWORD and \
+                      OSHIRASE_MANGADEX_CLIENT_SECRET, or prompted for on stdin."
+    )]
+    Mangadex {
+        #[arg(help = "User id")]
+        user_id: u64,
+        #[arg(
+            short,
+            long,
+            default_value = "anilist",
+            help = "Service the user comes from, anilist, kitsu or myanimelist"
+        )]
+        provider: Provider,
+        #[arg(help = "MangaDex username")]
+        username: String,
+        #[arg(help = "MangaDex personal client id")]
+        client_id: String,
+    },
+    #[command(about = "Set a user's MangaDex language and scanlation group filters")]
+    MangadexFilters {
+        #[arg(help = "User id")]
+        user_id: u64,
+        #[arg(
+            short,
+            long,
+            default_value = "anilist",
+            help = "Service the user comes from, anilist, kitsu or myanimelist"
+        )]
+        provider: Provider,
+        #[arg(
+            short,
+            long,
+            help = "Translated language in order of preference, e.g. en"
+        )]
+        language: Vec<String>,
+        #[arg(short, long, help = "Only use chapters from this scanlation group id")]
+        include_group: Vec<String>,
+        #[arg(short, long, help = "Never use chapters from this scanlation group id")]
+        exclude_group: Vec<String>,
+    },
+    #[command(about = "Set which release types count towards a user's progress")]
+    Preferences {
+        #[arg(help = "User id")]
+        user_id: u64,
+        #[arg(
+            short,
+            long,
+            default_value = "anilist",
+            help = "Service the user comes from, anilist, kitsu or myanimelist"
+        )]
+        provider: Provider,
+        #[arg(
+            short,
+            long,
+            required = true,
+            help = "Release type to follow, fansub or official"
+        )]
+        release_type: Vec<ReleaseType>,
+    },
+    #[command(about = "Issue a new server API token for a user")]
+    ApiToken {
+        #[arg(help = "User id")]
+        user_id: u64,
+        #[arg(
+            short,
+            long,
+            default_value = "anilist",
+            help = "Service the user comes from, anilist, kitsu or myanimelist"
+        )]
+        provider: Provider,
+    },
+    #[command(about = "Set the timezone a user's schedule is shown in")]
+    Timezone {
+        #[arg(help = "User id")]
+        user_id: u64,
+        #[arg(
+            short,
+            long,
+            default_value = "anilist",
+            help = "Service the user comes from, anilist, kitsu or myanimelist"
+        )]
+        provider: Provider,
+        #[arg(help = "Timezone, e.g. Europe/Berlin, leave out to reset to UTC")]
+        timezone: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum MappingsCommand {
+    #[command(about = "Import an anime-offline-database or id mappings JSON file")]
+    Import {
+        #[arg(help = "Path to mappings file")]
+        filename: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum OverrideCommand {
+    #[command(about = "Always match a media to the given source key")]
+    Add {
+        #[arg(help = "Source name, e.g. subsplease_rss")]
+        source: String,
+        #[arg(help = "AniList media id")]
+        media_id: u64,
+        #[arg(help = "Source key to match")]
+        key: String,
+    },
+    #[command(about = "Never match a media in the given source")]
+    Block {
+        #[arg(help = "Source name, e.g. subsplease_rss")]
+        source: String,
+        #[arg(help = "AniList media id")]
+        media_id: u64,
+    },
+    #[command(about = "Remove a match override")]
+    Remove {
+        #[arg(help = "Source name, e.g. subsplease_rss")]
+        source: String,
+        #[arg(help = "AniList media id")]
+        media_id: u64,
+    },
+}
+
+fn read_secret(prompt: &str, env: &str) -> Result<String> {
+    if let Ok(secret) = env::var(env) {
+        return Ok(secret);
+    }
+
+    eprint!("{}: ", prompt);
+    io::stderr().flush()?;
+    let mut secret = String::new();
+    io::stdin().read_line(&mut secret)?;
+
+    let secret = secret.trim_end_matches(['\r', '\n']);
+    if secret.is_empty() {
+        return Err(CustomError::boxed(&format!("No {} provided.", prompt)));
+    }
+
+    Ok(secret.to_owned())
 }
 
 #[tokio::main]
@@ -32,12 +321,239 @@ async fn main() -> Result<()> {
 
     let aggregator = Aggregator::new(&config);
 
-    if cli.worker_mode {
+    if let Some(command) = cli.command {
+        match command {
+            Command::Override { command } => match command {
+                OverrideCommand::Add {
+                    source,
+                    media_id,
+                    key,
+                } => {
+                    aggregator
+                        .add_match_override(&MatchOverride {
+                            source,
+                            media_id,
+                            key: Some(key),
+                        })
+                        .await?
+                }
+                OverrideCommand::Block { source, media_id } => {
+                    aggregator
+                        .add_match_override(&MatchOverride {
+                            source,
+                            media_id,
+                            key: None,
+                        })
+                        .await?
+                }
+                OverrideCommand::Remove { source, media_id } => {
+                    aggregator.remove_match_override(&source, media_id).await?
+                }
+            },
+            Command::Mappings { command } => match command {
+                MappingsCommand::Import { filename } => {
+                    let imported = aggregator.import_media_mappings(&filename).await?;
+                    println!("Imported {} media mappings.", imported);
+                }
+            },
+            Command::User { command } => match command {
+                UserCommand::Add { name, provider } => {
+                    let user = aggregator.add_user(&name, provider).await?;
+                    println!("Added {} ({}).", user.name, user.id);
+                }
+                UserCommand::List => {
+                    for user in aggregator.fetch_users().await? {
+                        println!("{}\t{:?}\t{}", user.id, user.provider, user.name);
+                    }
+                }
+                UserCommand::Remove { user_id, provider } => {
+                    aggregator.remove_user(user_id, provider).await?
+                }
+                UserCommand::Show { user_id, provider } => {
+                    let user = aggregator.fetch_user(user_id, provider).await?;
+                    println!("{:#?}", user);
+                }
+                UserCommand::Mangadex {
+                    user_id,
+                    provider,
+                    username,
+                    client_id,
+                } => {
+                    let password = read_secret("MangaDex password", "OSHIRASE_MANGADEX_PASSWORD")?;
+                    let client_secret =
+                        read_secret("MangaDex client secret", "OSHIRASE_MANGADEX_CLIENT_SECRET")?;
+                    aggregator
+                        .set_mangadex_credentials(
+                            user_id,
+                            provider,
+                            &MangaDexCredentials {
+                                username,
+                                password,
+                                client_id,
+                                client_secret,
+                            },
+                        )
+                        .await?
+                }
+                UserCommand::MangadexFilters {
+                    user_id,
+                    provider,
+                    language,
+                    include_group,
+                    exclude_group,
+                } => {
+                    aggregator
+                        .set_mangadex_filters(
+                            user_id,
+                            provider,
+                            &MangaDexFilters {
+                                translated_language: language,
+                                included_groups: include_group,
+                                excluded_groups: exclude_group,
+                            },
+                        )
+                        .await?
+                }
+                UserCommand::Preferences {
+                    user_id,
+                    provider,
+                    release_type,
+                } => {
+                    aggregator
+                        .set_preferences(
+                            user_id,
+                            provider,
+                            &UserPreferences {
+                                release_types: release_type,
+                            },
+                        )
+                        .await?
+                }
+                UserCommand::ApiToken { user_id, provider } => {
+                    let token = aggregator.issue_api_token(user_id, provider).await?;
+                    println!("{}", token);
+                }
+                UserCommand::Timezone {
+                    user_id,
+                    provider,
+                    timezone,
+                } => {
+                    aggregator
+                        .set_timezone(user_id, provider, timezone.as_deref())
+                        .await?
+                }
+            },
+            Command::Schedule {
+                timezone,
+                user_id,
+                provider,
+            } => {
+                let schedule = aggregator
+                    .fetch_schedule(timezone.as_deref(), user_id, provider)
+                    .await?;
+
+                for scheduled in &schedule {
+                    println!(
+                        "{:?} {}\t{}\t{}",
+                        scheduled.day,
+                        scheduled.time,
+                        scheduled.title.as_deref().unwrap_or_default(),
+                        scheduled.next_release_at
+                    );
+                }
+            }
+            Command::Progress {
+                user_id,
+                provider,
+                media_type,
+                sort,
+            } => {
+                let report = aggregator
+                    .fetch_progress(user_id, provider, media_type.as_deref(), sort)
+                    .await?;
+
+                for entry in &report.entries {
+                    let mut flags = Vec::new();
+                    if entry.is_caught_up {
+                        flags.push("caught up");
+                    }
+                    if entry.is_stale {
+                        flags.push("stale");
+                    }
+                    println!(
+                        "{}\t{}/{}\t{}\t{}",
+                        entry.unwatched.unwrap_or_default(),
+                        entry.progress,
+                        entry
+                            .latest
+                            .as_ref()
+                            .map(|latest| latest.label.as_str())
+                            .unwrap_or("?"),
+                        entry.title.as_deref().unwrap_or_default(),
+                        flags.join(", ")
+                    );
+                }
+                println!("Backlog: {}.", report.backlog_total);
+            }
+            Command::Token {
+                user_id,
+                access_token,
+            } => aggregator.set_access_token(user_id, &access_token).await?,
+            Command::Sync {
+                user_id,
+                provider,
+                media_id,
+                progress,
+                dry_run,
+            } => {
+                let saved = aggregator
+                    .save_progress(user_id, provider, media_id, progress, dry_run)
+                    .await?;
+
+                if saved.dry_run {
+                    println!(
+                        "Would save progress {} for {}.",
+                        saved.progress, saved.media_id
+                    );
+                } else {
+                    println!("Saved progress {} for {}.", saved.progress, saved.media_id);
+                }
+            }
+            Command::Releases { media_id } => {
+                let releases = aggregator.fetch_releases(media_id).await?;
+                let now = OffsetDateTime::now_utc();
+
+                for release in &releases {
+                    let released_at = match release.released_at {
+                        Some(released_at) => released_ago(released_at, now),
+                        None => "unknown".to_owned(),
+                    };
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        release.number.label, release.source, released_at, release.url
+                    );
+                }
+                if let Some(cadence) = release_cadence(&releases) {
+                    println!(
+                        "Releases every {:.1} days.",
+                        cadence.as_seconds_f64() / 86400.0
+                    );
+                }
+            }
+        }
+    } else if cli.server_mode {
+        let server = Server::new(&aggregator);
+        server.run().await?;
+    } else if cli.worker_mode {
         let worker = Worker::new(&aggregator);
         worker.run().await;
     } else {
         let data = aggregator.run().await?;
 
+        if !data.report().is_empty() {
+            eprintln!("Finished with {} issues.", data.report().issues.len());
+        }
+
         if cli.print {
             println!("{:?}", data);
         }
@@ -0,0 +1,109 @@
+use std::collections::{HashMap, HashSet};
+
+const NGRAM_SIZE: usize = 3;
+
+#[derive(Debug, Default)]
+pub struct NgramIndex {
+    keys: Vec<String>,
+    sizes: Vec<usize>,
+    postings: HashMap<String, Vec<usize>>,
+}
+
+impl NgramIndex {
+    pub fn new<'a, I>(keys: I) -> NgramIndex
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        let mut index = NgramIndex::default();
+
+        for key in keys {
+            let position = index.keys.len();
+            let ngrams = Self::ngrams(key);
+
+            index.keys.push(key.to_owned());
+            index.sizes.push(ngrams.len());
+            for ngram in ngrams {
+                index.postings.entry(ngram).or_default().push(position);
+            }
+        }
+
+        index
+    }
+
+    fn ngrams(s: &str) -> HashSet<String> {
+        let mut ngrams = HashSet::new();
+        if s.trim().is_empty() {
+            return ngrams;
+        }
+
+        let padded: Vec<char> = format!("  {} ", s.to_lowercase()).chars().collect();
+        for window in padded.windows(NGRAM_SIZE) {
+            ngrams.insert(window.iter().collect());
+        }
+
+        ngrams
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn candidates(&self, query: &str, limit: usize) -> Vec<&str> {
+        let ngrams = Self::ngrams(query);
+        if ngrams.is_empty() || limit == 0 {
+            return Vec::new();
+        }
+
+        let mut shared: HashMap<usize, usize> = HashMap::new();
+        for ngram in &ngrams {
+            if let Some(positions) = self.postings.get(ngram) {
+                for position in positions {
+                    *shared.entry(*position).or_default() += 1;
+                }
+            }
+        }
+
+        // Dice coefficient, so long keys aren't favored just for having more n-grams.
+        let mut scored: Vec<(f64, usize)> = shared
+            .into_iter()
+            .map(|(position, count)| {
+                let total = ngrams.len() + self.sizes[position];
+                (2.0 * count as f64 / total as f64, position)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, position)| self.keys[position].as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let keys = vec![
+            "Gintama".to_owned(),
+            "Naruto".to_owned(),
+            "Tamako Market".to_owned(),
+            "Gintama'".to_owned(),
+        ];
+        let index = NgramIndex::new(&keys);
+
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.candidates("gintama", 1), vec!["Gintama"]);
+        assert_eq!(index.candidates("Gin Tama", 2), vec!["Gintama", "Gintama'"]);
+        assert_eq!(index.candidates("tamako market", 1), vec!["Tamako Market"]);
+        assert!(index.candidates("", 10).is_empty());
+        assert!(index.candidates("xyz", 10).is_empty());
+    }
+}
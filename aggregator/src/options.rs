@@ -1,4 +1,29 @@
-#[derive(Clone)]
+use crate::ngram_index::NgramIndex;
+use crate::report::RunReport;
+
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
 pub struct ExtractOptions {
     pub mongodb_client: Option<mongodb::Client>,
+    pub report: Arc<Mutex<RunReport>>,
+}
+
+impl ExtractOptions {
+    pub fn add_issue(&self, source: &str, message: &str) {
+        self.report.lock().unwrap().add(source, message);
+    }
+
+    pub fn merge_report(&self, report: RunReport) {
+        self.report.lock().unwrap().merge(report);
+    }
+
+    pub fn take_report(&self) -> RunReport {
+        std::mem::take(&mut self.report.lock().unwrap())
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct TransformOptions<'a> {
+    pub index: Option<&'a NgramIndex>,
 }
@@ -0,0 +1,212 @@
+use crate::anilist_api::{Latest, Media, MediaType};
+use crate::error::CustomError;
+use crate::release::ReleaseNumber;
+
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, str::FromStr};
+use time::{Duration, OffsetDateTime};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressSort {
+    #[default]
+    Unwatched,
+    Updated,
+    Title,
+}
+
+impl FromStr for ProgressSort {
+    type Err = Box<CustomError>;
+
+    fn from_str(sort: &str) -> std::result::Result<ProgressSort, Self::Err> {
+        match sort.to_lowercase().as_str() {
+            "unwatched" => Ok(ProgressSort::Unwatched),
+            "updated" => Ok(ProgressSort::Updated),
+            "title" => Ok(ProgressSort::Title),
+            _ => Err(CustomError::boxed(&format!("Invalid sort: {}.", sort))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MediaProgress {
+    pub media_id: Option<u64>,
+    pub media_type: Option<String>,
+    pub title: Option<String>,
+    pub status: Option<String>,
+    pub progress: u64,
+    pub latest: Option<ReleaseNumber>,
+    pub unwatched: Option<u64>,
+    pub is_caught_up: bool,
+    pub is_stale: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+impl MediaProgress {
+    pub fn new(
+        media: &Media,
+        latest: Option<&Latest>,
+        now: OffsetDateTime,
+        stale_after: Duration,
+    ) -> MediaProgress {
+        let progress = media.progress.unwrap_or_default();
+        let number = latest.map(|latest| latest.number.clone());
+
+        let unwatched = number
+            .as_ref()
+            .and_then(|number| number.value)
+            .map(|value| (value.floor() as u64).saturating_sub(progress));
+        let is_caught_up = match &number {
+            Some(number) => !number.is_newer_than(progress),
+            None => true,
+        };
+        let is_stale = media.status.as_deref() == Some("CURRENT")
+            && match media.updated_at {
+                Some(updated_at) => now - updated_at > stale_after,
+                None => false,
+            };
+
+        MediaProgress {
+            media_id: media.media_id,
+            media_type: media
+                .media_type
+                .as_ref()
+                .map(|media_type| match media_type {
+                    MediaType::Anime => "anime".to_owned(),
+                    MediaType::Manga => "manga".to_owned(),
+                }),
+            title: media.title.to_owned().or(media.english_title.to_owned()),
+            status: media.status.to_owned(),
+            progress,
+            latest: number,
+            unwatched,
+            is_caught_up,
+            is_stale,
+            updated_at: media.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ProgressReport {
+    pub entries: Vec<MediaProgress>,
+    pub backlog_total: u64,
+}
+
+impl ProgressReport {
+    pub fn new(mut entries: Vec<MediaProgress>, sort: ProgressSort) -> ProgressReport {
+        match sort {
+            ProgressSort::Unwatched => {
+                entries.sort_by_key(|entry| (Reverse(entry.unwatched), entry.title.clone()))
+            }
+            ProgressSort::Updated => {
+                entries.sort_by_key(|entry| (entry.updated_at, entry.title.clone()))
+            }
+            ProgressSort::Title => entries.sort_by_key(|entry| entry.title.clone()),
+        }
+        let backlog_total = entries.iter().filter_map(|entry| entry.unwatched).sum();
+
+        ProgressReport {
+            entries,
+            backlog_total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn latest(number: &str) -> Latest {
+        Latest {
+            title: "Gintama".to_owned(),
+            number: ReleaseNumber::parse(number, None),
+            url: "http://www.test.nyaa".to_owned(),
+            source: "test".to_owned(),
+            released_at: None,
+            links: None,
+        }
+    }
+
+    #[test]
+    fn test_media_progress() {
+        let now = datetime!(2023-05-20 12:00 UTC);
+        let stale_after = Duration::weeks(4);
+        let media = |progress: u64, updated_at: OffsetDateTime| Media {
+            media_id: Some(1),
+            status: Some("CURRENT".to_owned()),
+            title: Some("Gintama".to_owned()),
+            media_type: Some(MediaType::Anime),
+            progress: Some(progress),
+            updated_at: Some(updated_at),
+            ..Default::default()
+        };
+
+        let actual = MediaProgress::new(
+            &media(10, datetime!(2023-05-19 12:00 UTC)),
+            Some(&latest("12")),
+            now,
+            stale_after,
+        );
+        assert_eq!(actual.unwatched, Some(2));
+        assert!(!actual.is_caught_up);
+        assert!(!actual.is_stale);
+
+        let actual = MediaProgress::new(
+            &media(12, datetime!(2023-03-01 12:00 UTC)),
+            Some(&latest("12.5")),
+            now,
+            stale_after,
+        );
+        assert_eq!(actual.unwatched, Some(0));
+        assert!(!actual.is_caught_up);
+        assert!(actual.is_stale);
+
+        let actual = MediaProgress::new(
+            &media(3, datetime!(2023-05-19 12:00 UTC)),
+            None,
+            now,
+            stale_after,
+        );
+        assert_eq!(actual.unwatched, None);
+        assert!(actual.is_caught_up);
+    }
+
+    #[test]
+    fn test_progress_report() {
+        let now = datetime!(2023-05-20 12:00 UTC);
+        let entry = |title: &str, progress: u64, number: &str| {
+            let media = Media {
+                title: Some(title.to_owned()),
+                status: Some("CURRENT".to_owned()),
+                progress: Some(progress),
+                ..Default::default()
+            };
+            MediaProgress::new(&media, Some(&latest(number)), now, Duration::weeks(4))
+        };
+
+        let report = ProgressReport::new(
+            vec![
+                entry("Naruto", 5, "6"),
+                entry("Gintama", 1, "4"),
+                entry("Bleach", 2, "2"),
+            ],
+            ProgressSort::Unwatched,
+        );
+        let titles: Vec<&str> = report
+            .entries
+            .iter()
+            .filter_map(|entry| entry.title.as_deref())
+            .collect();
+        assert_eq!(titles, vec!["Gintama", "Naruto", "Bleach"]);
+        assert_eq!(report.backlog_total, 4);
+
+        assert_eq!(
+            "title".parse::<ProgressSort>().unwrap(),
+            ProgressSort::Title
+        );
+        assert!("latest".parse::<ProgressSort>().is_err());
+    }
+}
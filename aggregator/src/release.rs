@@ -0,0 +1,293 @@
+use crate::anilist_api::Media;
+use crate::sources::Document;
+
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+};
+use time::{Duration, OffsetDateTime};
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct ReleaseNumber {
+    pub value: Option<f64>,
+    pub label: String,
+    pub volume: Option<String>,
+    pub special: bool,
+}
+
+impl ReleaseNumber {
+    pub fn parse(label: &str, volume: Option<String>) -> ReleaseNumber {
+        let label = label.trim();
+
+        let number = match label.split_once('v') {
+            Some((number, version))
+                if !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                number
+            }
+            _ => label,
+        };
+        let value = if number.starts_with(|c: char| c.is_ascii_digit())
+            && number.chars().all(|c| c.is_ascii_digit() || c == '.')
+        {
+            number.parse::<f64>().ok()
+        } else {
+            None
+        };
+        let special = match value {
+            Some(value) => value.fract() != 0.0,
+            None => true,
+        };
+
+        ReleaseNumber {
+            value,
+            label: label.to_owned(),
+            volume,
+            special,
+        }
+    }
+
+    fn normalized_label(&self) -> String {
+        let value = match self.value {
+            Some(value) => value,
+            None => return self.label.to_owned(),
+        };
+
+        match self.label.split_once('v') {
+            Some((_, version)) => format!("{}v{}", value, version),
+            None => value.to_string(),
+        }
+    }
+
+    pub fn cmp_value(&self, other: &ReleaseNumber) -> Ordering {
+        match (self.value, other.value) {
+            (Some(value), Some(other)) => value.total_cmp(&other),
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+            (None, None) => self.label.cmp(&other.label),
+        }
+    }
+
+    pub fn is_newer_than(&self, progress: u64) -> bool {
+        match self.value {
+            Some(value) => value > progress as f64,
+            None => false,
+        }
+    }
+}
+
+impl PartialEq for ReleaseNumber {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ReleaseNumber {}
+
+impl PartialOrd for ReleaseNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ReleaseNumber {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp_value(other)
+            .then_with(|| self.normalized_label().cmp(&other.normalized_label()))
+            .then_with(|| self.volume.cmp(&other.volume))
+            .then_with(|| self.special.cmp(&other.special))
+    }
+}
+
+impl Hash for ReleaseNumber {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.map(f64::to_bits).hash(state);
+        self.normalized_label().hash(state);
+        self.volume.hash(state);
+        self.special.hash(state);
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize, Hash)]
+pub struct ReleaseLinks {
+    pub page: Option<String>,
+    pub magnet: Option<String>,
+    pub torrent: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Hash)]
+pub struct Release {
+    pub media_id: u64,
+    pub source: String,
+    pub number: ReleaseNumber,
+    pub url: String,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub released_at: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub links: Option<ReleaseLinks>,
+}
+
+impl Document for Release {}
+
+impl Release {
+    pub fn from_media(media: &Media) -> Option<Release> {
+        let latest = media.latest.as_ref()?;
+
+        Some(Release {
+            media_id: media.media_id?,
+            source: latest.source.to_owned(),
+            number: latest.number.clone(),
+            url: latest.url.to_owned(),
+            released_at: latest.released_at,
+            links: latest.links.clone(),
+        })
+    }
+}
+
+pub fn released_ago(released_at: OffsetDateTime, now: OffsetDateTime) -> String {
+    let elapsed = now - released_at;
+
+    let (value, unit) = if elapsed.whole_days() > 0 {
+        (elapsed.whole_days(), "day")
+    } else if elapsed.whole_hours() > 0 {
+        (elapsed.whole_hours(), "hour")
+    } else if elapsed.whole_minutes() > 0 {
+        (elapsed.whole_minutes(), "minute")
+    } else {
+        return "just now".to_owned();
+    };
+
+    if value == 1 {
+        format!("1 {} ago", unit)
+    } else {
+        format!("{} {}s ago", value, unit)
+    }
+}
+
+pub fn release_cadence(releases: &[Release]) -> Option<Duration> {
+    let mut released_at: Vec<OffsetDateTime> = releases
+        .iter()
+        .filter_map(|release| release.released_at)
+        .collect();
+    released_at.sort();
+    released_at.dedup();
+
+    let mut intervals: Vec<Duration> = released_at
+        .windows(2)
+        .map(|window| window[1] - window[0])
+        .collect();
+    if intervals.is_empty() {
+        return None;
+    }
+    intervals.sort();
+
+    Some(intervals[intervals.len() / 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_parse() {
+        let number = ReleaseNumber::parse("105.5", Some("12".to_owned()));
+        assert_eq!(number.value, Some(105.5));
+        assert_eq!(number.label, "105.5");
+        assert_eq!(number.volume, Some("12".to_owned()));
+        assert!(number.special);
+
+        let number = ReleaseNumber::parse("01v2", None);
+        assert_eq!(number.value, Some(1.0));
+        assert_eq!(number.label, "01v2");
+        assert!(!number.special);
+
+        let number = ReleaseNumber::parse("Oneshot", None);
+        assert_eq!(number.value, None);
+        assert!(number.special);
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut numbers = [
+            ReleaseNumber::parse("105.5", None),
+            ReleaseNumber::parse("none", None),
+            ReleaseNumber::parse("106", None),
+            ReleaseNumber::parse("105", None),
+            ReleaseNumber::parse("01v2", None),
+            ReleaseNumber::parse("01", None),
+        ];
+        numbers.sort();
+
+        let labels: Vec<&str> = numbers.iter().map(|n| n.label.as_str()).collect();
+        assert_eq!(labels, vec!["none", "01", "01v2", "105", "105.5", "106"]);
+
+        assert_eq!(
+            ReleaseNumber::parse("01", None),
+            ReleaseNumber::parse("1", None)
+        );
+        assert_eq!(
+            ReleaseNumber::parse("1.50", None),
+            ReleaseNumber::parse("01.5", None)
+        );
+        assert_eq!(
+            ReleaseNumber::parse("05v2", None),
+            ReleaseNumber::parse("5v2", None)
+        );
+        assert!(ReleaseNumber::parse("05", None) < ReleaseNumber::parse("5v2", None));
+
+        let hash = |number: &ReleaseNumber| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            number.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(
+            hash(&ReleaseNumber::parse("01", None)),
+            hash(&ReleaseNumber::parse("1", None))
+        );
+    }
+
+    #[test]
+    fn test_is_newer_than() {
+        assert!(ReleaseNumber::parse("12.5", None).is_newer_than(12));
+        assert!(!ReleaseNumber::parse("12", None).is_newer_than(12));
+        assert!(!ReleaseNumber::parse("Oneshot", None).is_newer_than(0));
+    }
+
+    #[test]
+    fn test_released_ago() {
+        let now = datetime!(2023-05-20 12:00 UTC);
+        assert_eq!(
+            released_ago(datetime!(2023-05-20 09:00 UTC), now),
+            "3 hours ago"
+        );
+        assert_eq!(
+            released_ago(datetime!(2023-05-19 11:00 UTC), now),
+            "1 day ago"
+        );
+        assert_eq!(released_ago(now, now), "just now");
+    }
+
+    #[test]
+    fn test_release_cadence() {
+        let release = |released_at: Option<OffsetDateTime>| Release {
+            media_id: 1,
+            source: "subsplease_rss".to_owned(),
+            number: ReleaseNumber::parse("1", None),
+            url: "http://www.test.nyaa".to_owned(),
+            released_at,
+            links: None,
+        };
+
+        let releases = [
+            release(Some(datetime!(2023-05-06 12:00 UTC))),
+            release(Some(datetime!(2023-05-13 12:00 UTC))),
+            release(None),
+            release(Some(datetime!(2023-05-20 12:00 UTC))),
+            release(Some(datetime!(2023-05-21 12:00 UTC))),
+        ];
+        assert_eq!(release_cadence(&releases), Some(Duration::days(7)));
+        assert_eq!(release_cadence(&releases[..1]), None);
+    }
+}
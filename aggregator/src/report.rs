@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RunIssue {
+    pub source: String,
+    pub message: String,
+}
+
+impl fmt::Display for RunIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.source, self.message)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RunReport {
+    pub issues: Vec<RunIssue>,
+}
+
+impl RunReport {
+    pub fn add(&mut self, source: &str, message: &str) {
+        self.issues.push(RunIssue {
+            source: source.to_owned(),
+            message: message.to_owned(),
+        });
+    }
+
+    pub fn merge(&mut self, other: RunReport) {
+        self.issues.extend(other.issues);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut report = RunReport::default();
+        assert!(report.is_empty());
+
+        report.add("mangadex_api", "Could not fetch manga batch 1.");
+        let mut other = RunReport::default();
+        other.add("mangadex_api", "Could not fetch manga batch 2.");
+        report.merge(other);
+
+        assert_eq!(report.issues.len(), 2);
+        assert_eq!(
+            report.issues[1].to_string(),
+            "[mangadex_api] Could not fetch manga batch 2."
+        );
+    }
+}
@@ -0,0 +1,600 @@
+use crate::anilist_api::{Provider, SavedMediaListEntry, UserPreferences, UserProfile};
+use crate::config::{Config, MangaDexFilters};
+use crate::crypto;
+use crate::db::MongoDB;
+use crate::error::CustomError;
+use crate::mangadex_api::MangaDexCredentials;
+use crate::match_overrides_db::MatchOverride;
+use crate::progress::{ProgressReport, ProgressSort};
+use crate::release::{release_cadence, Release};
+use crate::result::Result;
+use crate::subsplease_scraper::ScheduledAnime;
+use crate::Aggregator;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{
+        header::{AUTHORIZATION, COOKIE, SET_COOKIE},
+        HeaderMap, HeaderName, Request, StatusCode,
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, net::SocketAddr, sync::Arc};
+
+const OAUTH_SESSION_COOKIE: &str = "oshirase_oauth_session";
+
+pub struct Server<'a> {
+    aggregator: &'a Aggregator<'a>,
+}
+
+#[derive(Clone)]
+struct ServerState {
+    config: Arc<Config>,
+    mongodb_client: mongodb::Client,
+}
+
+impl ServerState {
+    fn aggregator(&self) -> Aggregator<'_> {
+        Aggregator::with_mongodb_client(&self.config, self.mongodb_client.clone())
+    }
+}
+
+#[derive(Serialize)]
+struct ReleasesResponse {
+    releases: Vec<Release>,
+    cadence_seconds: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct ScheduleQuery {
+    timezone: Option<String>,
+    user_id: Option<u64>,
+    #[serde(default)]
+    provider: Provider,
+}
+
+// Ids are only unique per provider, so user routes take ?provider=, AniList by default.
+#[derive(Deserialize)]
+struct UserQuery {
+    #[serde(default)]
+    provider: Provider,
+}
+
+#[derive(Deserialize)]
+struct ProgressQuery {
+    media_type: Option<String>,
+    #[serde(default)]
+    sort: ProgressSort,
+}
+
+#[derive(Deserialize)]
+struct SaveProgress {
+    media_id: u64,
+    progress: u64,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Deserialize)]
+struct SetTimezone {
+    timezone: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OAuthCallback {
+    code: String,
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct AddUser {
+    name: String,
+    #[serde(default)]
+    provider: Provider,
+}
+
+struct ServerError(StatusCode, Box<dyn Error + Send + Sync>);
+
+impl ServerError {
+    fn unauthorized() -> ServerError {
+        ServerError(
+            StatusCode::UNAUTHORIZED,
+            CustomError::boxed("Missing or invalid user token."),
+        )
+    }
+}
+
+impl IntoResponse for ServerError {
+    fn into_response(self) -> Response {
+        (
+            self.0,
+            Json(serde_json::json!({ "error": self.1.to_string() })),
+        )
+            .into_response()
+    }
+}
+
+impl<E> From<E> for ServerError
+where
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    fn from(err: E) -> ServerError {
+        ServerError(StatusCode::INTERNAL_SERVER_ERROR, err.into())
+    }
+}
+
+impl<'a> Server<'a> {
+    pub fn new(aggregator: &'a Aggregator<'a>) -> Server<'a> {
+        Server { aggregator }
+    }
+
+    fn router(config: Config, mongodb_client: mongodb::Client) -> Router {
+        let state = ServerState {
+            config: Arc::new(config),
+            mongodb_client,
+        };
+
+        // Everything under /users/:user_id needs the token issued to that user.
+        let user_routes = Router::new()
+            .route("/users/:user_id", get(fetch_user).delete(remove_user))
+            .route("/users/:user_id/mangadex", put(set_mangadex_credentials))
+            .route(
+                "/users/:user_id/mangadex/filters",
+                put(set_mangadex_filters),
+            )
+            .route("/users/:user_id/preferences", put(set_preferences))
+            .route("/users/:user_id/timezone", put(set_timezone))
+            .route(
+                "/users/:user_id/progress",
+                get(fetch_progress).post(save_progress),
+            )
+            .route_layer(middleware::from_fn_with_state(state.clone(), authenticate));
+
+        // Match overrides end up in the media of every user, and the user list
+        // would otherwise let anyone enumerate or create accounts.
+        let admin_routes = Router::new()
+            .route("/users", get(fetch_users).post(add_user))
+            .route("/overrides", post(add_match_override))
+            .route(
+                "/overrides/:source/:media_id",
+                delete(remove_match_override),
+            )
+            .route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                authenticate_admin,
+            ));
+
+        Router::new()
+            .route("/releases/:media_id", get(fetch_releases))
+            .route("/oauth/anilist/login", get(login))
+            .route("/oauth/anilist/callback", get(login_callback))
+            .route("/schedule", get(fetch_schedule))
+            .merge(user_routes)
+            .merge(admin_routes)
+            .with_state(state)
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        let address: SocketAddr = self.aggregator.config.server.address.parse()?;
+        let mongodb = MongoDB::init(self.aggregator.config).await;
+        let router = Self::router(self.aggregator.config.clone(), mongodb.client);
+
+        println!("Listening on {}.", address);
+        axum::Server::bind(&address)
+            .serve(router.into_make_service())
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> std::result::Result<&str, ServerError> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .ok_or_else(ServerError::unauthorized)
+}
+
+async fn authenticate<B>(
+    State(state): State<ServerState>,
+    Path(user_id): Path<u64>,
+    Query(user_query): Query<UserQuery>,
+    headers: HeaderMap,
+    request: Request<B>,
+    next: Next<B>,
+) -> std::result::Result<Response, ServerError> {
+    let token = bearer_token(&headers)?;
+    if !state
+        .aggregator()
+        .authenticate(user_id, user_query.provider, token)
+        .await?
+    {
+        return Err(ServerError::unauthorized());
+    }
+
+    Ok(next.run(request).await)
+}
+
+async fn authenticate_admin<B>(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    request: Request<B>,
+    next: Next<B>,
+) -> std::result::Result<Response, ServerError> {
+    let token = bearer_token(&headers)?;
+    // Comparing hashes keeps the comparison time independent of the token.
+    let authorized = match &state.config.server.admin_token {
+        Some(admin_token) => crypto::hash_token(admin_token) == crypto::hash_token(token),
+        None => false,
+    };
+    if !authorized {
+        return Err(ServerError::unauthorized());
+    }
+
+    Ok(next.run(request).await)
+}
+
+async fn add_match_override(
+    State(state): State<ServerState>,
+    Json(match_override): Json<MatchOverride>,
+) -> std::result::Result<StatusCode, ServerError> {
+    state
+        .aggregator()
+        .add_match_override(&match_override)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_match_override(
+    State(state): State<ServerState>,
+    Path((source, media_id)): Path<(String, u64)>,
+) -> std::result::Result<StatusCode, ServerError> {
+    state
+        .aggregator()
+        .remove_match_override(&source, media_id)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn fetch_releases(
+    State(state): State<ServerState>,
+    Path(media_id): Path<u64>,
+) -> std::result::Result<Json<ReleasesResponse>, ServerError> {
+    let releases = state.aggregator().fetch_releases(media_id).await?;
+    let cadence_seconds = release_cadence(&releases).map(|cadence| cadence.whole_seconds());
+
+    Ok(Json(ReleasesResponse {
+        releases,
+        cadence_seconds,
+    }))
+}
+
+async fn fetch_schedule(
+    State(state): State<ServerState>,
+    Query(query): Query<ScheduleQuery>,
+    headers: HeaderMap,
+) -> std::result::Result<Json<Vec<ScheduledAnime>>, ServerError> {
+    // The plain schedule is public, a user's own schedule is not.
+    if let Some(user_id) = query.user_id {
+        let token = bearer_token(&headers)?;
+        if !state
+            .aggregator()
+            .authenticate(user_id, query.provider, token)
+            .await?
+        {
+            return Err(ServerError::unauthorized());
+        }
+    }
+
+    let schedule = state
+        .aggregator()
+        .fetch_schedule(query.timezone.as_deref(), query.user_id, query.provider)
+        .await?;
+
+    Ok(Json(schedule))
+}
+
+async fn fetch_progress(
+    State(state): State<ServerState>,
+    Path(user_id): Path<u64>,
+    Query(user_query): Query<UserQuery>,
+    Query(query): Query<ProgressQuery>,
+) -> std::result::Result<Json<ProgressReport>, ServerError> {
+    let report = state
+        .aggregator()
+        .fetch_progress(
+            user_id,
+            user_query.provider,
+            query.media_type.as_deref(),
+            query.sort,
+        )
+        .await?;
+
+    Ok(Json(report))
+}
+
+async fn save_progress(
+    State(state): State<ServerState>,
+    Path(user_id): Path<u64>,
+    Query(user_query): Query<UserQuery>,
+    Json(save_progress): Json<SaveProgress>,
+) -> std::result::Result<Json<SavedMediaListEntry>, ServerError> {
+    let saved = state
+        .aggregator()
+        .save_progress(
+            user_id,
+            user_query.provider,
+            save_progress.media_id,
+            save_progress.progress,
+            save_progress.dry_run,
+        )
+        .await?;
+
+    Ok(Json(saved))
+}
+
+fn oauth_session_cookie(session: &str, max_age: i64) -> String {
+    format!(
+        "{}={}; Path=/oauth/anilist; Max-Age={}; HttpOnly; SameSite=Lax",
+        OAUTH_SESSION_COOKIE, session, max_age
+    )
+}
+
+fn oauth_session(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|cookie| cookie.to_str().ok())
+        .flat_map(|cookie| cookie.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == OAUTH_SESSION_COOKIE)
+        .map(|(_, session)| session)
+}
+
+async fn login(
+    State(state): State<ServerState>,
+) -> std::result::Result<([(HeaderName, String); 1], Redirect), ServerError> {
+    let session = crypto::generate_token();
+    let url = state.aggregator().authorize_url(&session).await?;
+
+    Ok((
+        [(SET_COOKIE, oauth_session_cookie(&session, 600))],
+        Redirect::to(&url),
+    ))
+}
+
+async fn login_callback(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Query(callback): Query<OAuthCallback>,
+) -> std::result::Result<([(HeaderName, String); 1], Json<serde_json::Value>), ServerError> {
+    let session = oauth_session(&headers).ok_or_else(|| {
+        ServerError(
+            StatusCode::BAD_REQUEST,
+            CustomError::boxed("Missing OAuth session cookie."),
+        )
+    })?;
+    let (user, token) = state
+        .aggregator()
+        .login(&callback.code, &callback.state, session)
+        .await?;
+
+    Ok((
+        [(SET_COOKIE, oauth_session_cookie("", 0))],
+        Json(serde_json::json!({
+            "id": user.id,
+            "name": user.name,
+            "provider": user.provider,
+            "token": token
+        })),
+    ))
+}
+
+async fn add_user(
+    State(state): State<ServerState>,
+    Json(add_user): Json<AddUser>,
+) -> std::result::Result<(StatusCode, Json<UserProfile>), ServerError> {
+    let user = state
+        .aggregator()
+        .add_user(&add_user.name, add_user.provider)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(user)))
+}
+
+async fn fetch_users(
+    State(state): State<ServerState>,
+) -> std::result::Result<Json<Vec<UserProfile>>, ServerError> {
+    let users = state.aggregator().fetch_users().await?;
+
+    Ok(Json(users))
+}
+
+async fn fetch_user(
+    State(state): State<ServerState>,
+    Path(user_id): Path<u64>,
+    Query(user_query): Query<UserQuery>,
+) -> std::result::Result<Json<UserProfile>, ServerError> {
+    let user = state
+        .aggregator()
+        .fetch_user(user_id, user_query.provider)
+        .await?;
+
+    Ok(Json(user))
+}
+
+async fn remove_user(
+    State(state): State<ServerState>,
+    Path(user_id): Path<u64>,
+    Query(user_query): Query<UserQuery>,
+) -> std::result::Result<StatusCode, ServerError> {
+    state
+        .aggregator()
+        .remove_user(user_id, user_query.provider)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_mangadex_credentials(
+    State(state): State<ServerState>,
+    Path(user_id): Path<u64>,
+    Query(user_query): Query<UserQuery>,
+    Json(credentials): Json<MangaDexCredentials>,
+) -> std::result::Result<StatusCode, ServerError> {
+    state
+        .aggregator()
+        .set_mangadex_credentials(user_id, user_query.provider, &credentials)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_preferences(
+    State(state): State<ServerState>,
+    Path(user_id): Path<u64>,
+    Query(user_query): Query<UserQuery>,
+    Json(preferences): Json<UserPreferences>,
+) -> std::result::Result<StatusCode, ServerError> {
+    state
+        .aggregator()
+        .set_preferences(user_id, user_query.provider, &preferences)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_timezone(
+    State(state): State<ServerState>,
+    Path(user_id): Path<u64>,
+    Query(user_query): Query<UserQuery>,
+    Json(set_timezone): Json<SetTimezone>,
+) -> std::result::Result<StatusCode, ServerError> {
+    state
+        .aggregator()
+        .set_timezone(
+            user_id,
+            user_query.provider,
+            set_timezone.timezone.as_deref(),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_mangadex_filters(
+    State(state): State<ServerState>,
+    Path(user_id): Path<u64>,
+    Query(user_query): Query<UserQuery>,
+    Json(filters): Json<MangaDexFilters>,
+) -> std::result::Result<StatusCode, ServerError> {
+    state
+        .aggregator()
+        .set_mangadex_filters(user_id, user_query.provider, &filters)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn serve(config: Config) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let mongodb = MongoDB::new(&config).await;
+        let router = Server::router(config.clone(), mongodb.client);
+        tokio::spawn(async move {
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router.into_make_service())
+                .await
+                .unwrap();
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_users_require_token() {
+        let base_url = serve(Config::default()).await;
+        let url = format!("{}/users/1", base_url);
+
+        let client = reqwest::Client::new();
+        let requests = [
+            client.get(&url),
+            client.get(format!("{}?provider=kitsu", url)),
+            client.delete(&url),
+            client
+                .put(format!("{}/preferences", url))
+                .json(&UserPreferences::default()),
+            client
+                .get(format!("{}/progress", url))
+                .header(AUTHORIZATION, "Basic dGVzdDp0ZXN0"),
+            client.get(format!("{}/schedule?user_id=1", base_url)),
+        ];
+        for request in requests {
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_require_admin_token() {
+        let mut config = Config::default();
+        config.server.admin_token = Some("admin".to_owned());
+        let url = serve(config).await;
+
+        let client = reqwest::Client::new();
+        let requests = [
+            client.get(format!("{}/users", url)),
+            client
+                .post(format!("{}/users", url))
+                .bearer_auth("wrong")
+                .json(&serde_json::json!({ "name": "test" })),
+            client.post(format!("{}/overrides", url)),
+            client
+                .delete(format!("{}/overrides/subsplease/1", url))
+                .bearer_auth("wrong"),
+        ];
+        for request in requests {
+            let response = request.send().await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        }
+
+        // Without a configured token the routes stay closed.
+        let url = serve(Config::default()).await;
+        let response = client
+            .delete(format!("{}/overrides/subsplease/1", url))
+            .bearer_auth("")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_oauth_session() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(oauth_session(&headers), None);
+
+        headers.insert(
+            COOKIE,
+            "theme=dark; oshirase_oauth_session=abc-123"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(oauth_session(&headers), Some("abc-123"));
+        assert_eq!(
+            oauth_session_cookie("abc-123", 600),
+            "oshirase_oauth_session=abc-123; Path=/oauth/anilist; Max-Age=600; HttpOnly; SameSite=Lax"
+        );
+    }
+}
@@ -1,12 +1,19 @@
 pub mod alt_titles_db;
 pub mod anilist_api;
+pub mod kitsu_api;
 pub mod mangadex_api;
+pub mod mangaupdates_api;
+pub mod match_overrides_db;
+pub mod media_mappings_db;
+pub mod myanimelist_api;
+pub mod release_feed;
+pub mod simulcast_feed;
 pub mod subsplease_rss;
 pub mod subsplease_scraper;
 
 use crate::anilist_api::Media;
 use crate::anilist_api::MediaType;
-use crate::options::ExtractOptions;
+use crate::options::{ExtractOptions, TransformOptions};
 use crate::result::Result;
 
 use async_trait::async_trait;
@@ -15,18 +22,34 @@ use std::{collections::HashMap, hash::Hash};
 
 pub trait Document: DeserializeOwned + Serialize + Hash + Unpin + Send + Sync {}
 
+const ID_KEY_PREFIX: &str = "id:";
+
+pub fn id_key(site: &str, id: &str) -> String {
+    format!("{}{}:{}", ID_KEY_PREFIX, site, id)
+}
+
+pub fn is_id_key(key: &str) -> bool {
+    key.starts_with(ID_KEY_PREFIX)
+}
+
 pub struct Sources<'a> {
     pub anilist_api: anilist_api::AniListAPI<'a>,
     pub subsplease_scraper: subsplease_scraper::SubsPleaseScraper<'a>,
     pub subsplease_rss: subsplease_rss::SubsPleaseRSS<'a>,
     pub mangadex_api: mangadex_api::MangaDexAPI<'a>,
+    pub mangaupdates_api: mangaupdates_api::MangaUpdatesAPI<'a>,
     pub alt_titles_db: alt_titles_db::AltTitlesDB<'a>,
+    pub match_overrides_db: match_overrides_db::MatchOverridesDB<'a>,
+    pub media_mappings_db: media_mappings_db::MediaMappingsDB<'a>,
+    pub release_feeds: Vec<release_feed::ReleaseFeed<'a>>,
+    pub simulcast_feeds: Vec<simulcast_feed::SimulcastFeed<'a>>,
 }
 
 pub enum Extras<'a> {
     SubsPleaseScraper(subsplease_scraper::SubsPleaseScraper<'a>),
     SubsPleaseRSS(subsplease_rss::SubsPleaseRSS<'a>),
     MangaDexAPI(mangadex_api::MangaDexAPI<'a>),
+    MangaUpdatesAPI(mangaupdates_api::MangaUpdatesAPI<'a>),
 }
 
 #[async_trait]
@@ -39,35 +62,76 @@ pub trait Extract<'a> {
 pub trait Transform {
     type Extra: Clone;
 
+    fn get_source(&self) -> &str;
+
     fn set_media(media: &mut Media, extra: Option<Self::Extra>);
 
-    fn transform(&self, media: &mut Media, extras: &HashMap<String, Self::Extra>) -> Result<Media>;
+    fn transform(
+        &self,
+        media: &mut Media,
+        extras: &HashMap<String, Self::Extra>,
+        options: Option<TransformOptions>,
+    ) -> Result<Media>;
 }
 
 pub trait Similar: Transform {
     fn get_similarity_threshold(&self) -> f64;
 
+    fn get_similarity_candidates(&self) -> usize;
+
+    fn get_mapping_site(&self) -> Option<&str> {
+        None
+    }
+
+    fn get_enriched_statuses(&self) -> &[String];
+
     fn match_similar(
         &self,
         media: &mut Media,
         media_type: MediaType,
         extras: &HashMap<String, Self::Extra>,
+        options: Option<TransformOptions>,
     ) -> Result<Media> {
-        if media.status == Some("CURRENT".to_string()) && media.media_type == Some(media_type) {
-            let title = match media.title.to_owned() {
-                Some(title) => title,
-                None => String::new(),
-            };
-            let english_title = match media.english_title.to_owned() {
-                Some(english_title) => english_title,
-                None => String::new(),
-            };
+        let enriched = match &media.status {
+            Some(status) => self.get_enriched_statuses().contains(status),
+            None => false,
+        };
+
+        if enriched && media.media_type == Some(media_type) {
+            let title = media.title.to_owned().unwrap_or_default();
+            let english_title = media.english_title.to_owned().unwrap_or_default();
             let empty_vec = Vec::new();
             let alt_titles = match &media.alt_titles {
                 Some(alt_titles) => &alt_titles.alt_titles,
                 None => &empty_vec,
             };
 
+            let match_override = media
+                .match_overrides
+                .as_ref()
+                .and_then(|match_overrides| match_overrides.get(self.get_source()))
+                .cloned();
+            if let Some(key) = match_override {
+                if let Some(key) = key {
+                    Self::set_media(media, extras.get(&key).cloned());
+                }
+                return Ok(std::mem::take(media));
+            }
+
+            let anilist_id = media
+                .media_id
+                .map(|media_id| id_key("anilist", &media_id.to_string()));
+            let mapped_id = match (self.get_mapping_site(), &media.mappings) {
+                (Some(site), Some(mappings)) => mappings.get(site).map(|id| id_key(site, id)),
+                _ => None,
+            };
+            for id in [anilist_id, mapped_id].into_iter().flatten() {
+                if extras.contains_key(&id) {
+                    Self::set_media(media, extras.get(&id).cloned());
+                    return Ok(std::mem::take(media));
+                }
+            }
+
             if extras.contains_key(&title) {
                 Self::set_media(media, extras.get(&title).cloned());
                 return Ok(std::mem::take(media));
@@ -85,15 +149,35 @@ pub trait Similar: Transform {
                 }
             }
 
+            let queries: Vec<&String> = [&title, &english_title]
+                .into_iter()
+                .chain(alt_titles)
+                .filter(|query| !query.is_empty())
+                .collect();
+            let candidates: Vec<(&String, &Self::Extra)> =
+                match options.and_then(|options| options.index) {
+                    Some(index) => {
+                        let limit = self.get_similarity_candidates();
+                        let mut keys: Vec<&str> = queries
+                            .iter()
+                            .flat_map(|query| index.candidates(query, limit))
+                            .collect();
+                        keys.sort_unstable();
+                        keys.dedup();
+                        keys.into_iter()
+                            .filter_map(|key| extras.get_key_value(key))
+                            .collect()
+                    }
+                    None => extras.iter().filter(|(key, _)| !is_id_key(key)).collect(),
+                };
+
             let mut score_tuple: (f64, Option<&Self::Extra>) = (-f64::INFINITY, None);
-            for (ex_title, ex) in extras {
-                let score = strsim::normalized_levenshtein(&title, ex_title);
-                let alt_score = strsim::normalized_levenshtein(&english_title, ex_title);
-                if score > self.get_similarity_threshold() && score > score_tuple.0 {
-                    score_tuple = (score, Some(ex));
-                }
-                if alt_score > self.get_similarity_threshold() && alt_score > score_tuple.0 {
-                    score_tuple = (alt_score, Some(ex));
+            for (ex_title, ex) in candidates {
+                for query in &queries {
+                    let score = strsim::normalized_levenshtein(query, ex_title);
+                    if score > self.get_similarity_threshold() && score > score_tuple.0 {
+                        score_tuple = (score, Some(ex));
+                    }
                 }
             }
 
@@ -1,6 +1,7 @@
 use crate::anilist_api::Media;
 use crate::config::Config;
 use crate::error::CustomError;
+use crate::options::TransformOptions;
 use crate::result::Result;
 use crate::sources::Document;
 use crate::sources::{Extract, ExtractOptions, Transform};
@@ -26,7 +27,7 @@ pub struct AltTitlesDB<'a> {
 }
 
 impl AltTitlesDB<'_> {
-    pub fn new(config: &Config) -> AltTitlesDB {
+    pub fn new(config: &Config) -> AltTitlesDB<'_> {
         AltTitlesDB { config }
     }
 }
@@ -70,17 +71,43 @@ impl Extract<'_> for AltTitlesDB<'_> {
 impl Transform for AltTitlesDB<'_> {
     type Extra = AltTitlesEntry;
 
+    fn get_source(&self) -> &str {
+        "alt_titles_db"
+    }
+
     fn set_media(media: &mut Media, extra: Option<Self::Extra>) {
         media.alt_titles = extra;
     }
 
-    fn transform(&self, media: &mut Media, extra: &HashMap<String, Self::Extra>) -> Result<Media> {
-        if media.status == Some("CURRENT".to_string()) {
+    fn transform(
+        &self,
+        media: &mut Media,
+        extra: &HashMap<String, Self::Extra>,
+        _options: Option<TransformOptions>,
+    ) -> Result<Media> {
+        let enriched = match &media.status {
+            Some(status) => self.config.enriched_statuses().contains(&status),
+            None => false,
+        };
+
+        if enriched {
             let media_id = match media.media_id {
                 Some(media_id) => media_id.to_string(),
                 None => return Ok(std::mem::take(media)),
             };
 
+            let match_override = media
+                .match_overrides
+                .as_ref()
+                .and_then(|match_overrides| match_overrides.get(self.get_source()))
+                .cloned();
+            if let Some(key) = match_override {
+                if let Some(key) = key {
+                    Self::set_media(media, extra.get(&key).cloned());
+                }
+                return Ok(std::mem::take(media));
+            }
+
             if extra.contains_key(&media_id) {
                 Self::set_media(media, extra.get(&media_id).cloned());
                 return Ok(std::mem::take(media));
@@ -121,6 +148,7 @@ mod tests {
         let alt_titles_db = AltTitlesDB::new(&config);
         let options = ExtractOptions {
             mongodb_client: Some(mongodb.client.clone()),
+            ..Default::default()
         };
         let actual = alt_titles_db.extract(Some(options)).await.unwrap();
 
@@ -144,9 +172,17 @@ mod tests {
             episodes: None,
             score: None,
             progress: None,
+            updated_at: None,
             latest: None,
+            latest_by_filters: None,
+            latest_by_source: None,
+            latest_sources: None,
+            streams: None,
             schedule: None,
+            next_release_at: None,
             alt_titles: None,
+            match_overrides: None,
+            mappings: None,
         }];
         let alt_titles = HashMap::from([(
             1.to_string(),
@@ -159,7 +195,9 @@ mod tests {
         let config = Config::default();
         let alt_title_db = AltTitlesDB::new(&config);
 
-        let transformed = alt_title_db.transform(&mut media[0], &alt_titles).unwrap();
+        let transformed = alt_title_db
+            .transform(&mut media[0], &alt_titles, None)
+            .unwrap();
         assert_eq!(transformed.alt_titles, alt_titles.get("1").cloned());
     }
 }
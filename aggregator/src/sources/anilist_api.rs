@@ -1,36 +1,59 @@
 use crate::alt_titles_db::AltTitlesEntry;
-use crate::config::Config;
+use crate::config::{AniListOAuthConfig, Config, MangaDexFilters};
+use crate::crypto;
 use crate::error::CustomError;
+use crate::http::HttpClient;
+use crate::kitsu_api::KitsuAPI;
+use crate::mangadex_api::{LatestByFilters, MangaDexCredentials};
+use crate::match_overrides_db::MatchOverridesEntry;
+use crate::media_mappings_db::MediaMappingsEntry;
+use crate::myanimelist_api::MyAnimeListAPI;
+use crate::release::{ReleaseLinks, ReleaseNumber};
 use crate::result::Result;
 use crate::sources::Document;
 use crate::sources::{Extract, ExtractOptions};
-use crate::subsplease_scraper::AnimeScheduleEntry;
+use crate::subsplease_scraper::{get_timezone, AnimeScheduleEntry};
 
 use async_trait::async_trait;
 use bson::doc;
 use futures::TryStreamExt;
 use graphql_client::GraphQLQuery;
+use mongodb::options::UpdateOptions;
 use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
 
-#[derive(Debug, Default, PartialEq, Deserialize, Serialize, Hash)]
+const OAUTH_STATE_TTL: i64 = 600;
+// AniList caps the page size at 50.
+const MAL_IDS_PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize, Hash)]
 pub enum MediaType {
     #[default]
     Anime,
     Manga,
 }
 
-impl MediaType {
-    pub fn from_str(s: &str) -> Result<MediaType> {
+impl FromStr for MediaType {
+    type Err = Box<CustomError>;
+
+    fn from_str(s: &str) -> std::result::Result<MediaType, Self::Err> {
         let s = s.to_lowercase();
         if s == "anime" {
             Ok(MediaType::Anime)
-        } else if s.to_lowercase() == "manga" {
+        } else if s == "manga" {
             Ok(MediaType::Manga)
         } else {
             Err(CustomError::boxed(&format!("Invalid media type: {s}.")))
         }
     }
+}
 
+impl MediaType {
     pub fn from_option_str(s: Option<&str>) -> Option<MediaType> {
         match s {
             Some(s) => Self::from_str(s).ok(),
@@ -39,19 +62,217 @@ impl MediaType {
     }
 }
 
-#[derive(Debug, PartialEq, Deserialize, Serialize, Hash)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    #[default]
+    AniList,
+    Kitsu,
+    MyAnimeList,
+}
+
+impl FromStr for Provider {
+    type Err = Box<CustomError>;
+
+    fn from_str(provider: &str) -> std::result::Result<Provider, Self::Err> {
+        match provider.to_lowercase().as_str() {
+            "anilist" => Ok(Provider::AniList),
+            "kitsu" => Ok(Provider::Kitsu),
+            "myanimelist" | "mal" => Ok(Provider::MyAnimeList),
+            _ => Err(CustomError::boxed(&format!(
+                "Invalid provider: {}.",
+                provider
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseType {
+    #[default]
+    Fansub,
+    Official,
+}
+
+impl FromStr for ReleaseType {
+    type Err = Box<CustomError>;
+
+    fn from_str(release_type: &str) -> std::result::Result<ReleaseType, Self::Err> {
+        match release_type.to_lowercase().as_str() {
+            "fansub" => Ok(ReleaseType::Fansub),
+            "official" => Ok(ReleaseType::Official),
+            _ => Err(CustomError::boxed(&format!(
+                "Invalid release type: {}.",
+                release_type
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Hash)]
+pub struct UserPreferences {
+    #[serde(default = "UserPreferences::default_release_types")]
+    pub release_types: Vec<ReleaseType>,
+}
+
+impl UserPreferences {
+    fn default_release_types() -> Vec<ReleaseType> {
+        vec![ReleaseType::Fansub, ReleaseType::Official]
+    }
+
+    // The preferred latest, e.g. the one in the user's language, stands in for
+    // its source and wins ties over every other release.
+    pub fn latest(
+        &self,
+        preferred: Option<Latest>,
+        media: &Media,
+        config: &Config,
+    ) -> Option<Latest> {
+        let preferred_source = preferred.as_ref().map(|latest| latest.source.to_owned());
+        let releases = preferred
+            .or_else(|| media.latest.clone())
+            .into_iter()
+            .chain(
+                media
+                    .latest_by_source
+                    .iter()
+                    .flat_map(|latest_by_source| latest_by_source.values())
+                    .filter(|latest| Some(&latest.source) != preferred_source.as_ref())
+                    .cloned(),
+            )
+            .map(|latest| (config.release_type(&latest.source), latest))
+            .chain(
+                media
+                    .streams
+                    .iter()
+                    .flatten()
+                    .map(|stream| (ReleaseType::Official, Latest::from(stream))),
+            );
+
+        releases
+            .filter(|(release_type, _)| self.release_types.contains(release_type))
+            .map(|(_, latest)| latest)
+            .reduce(|latest, other| {
+                if other.number.cmp_value(&latest.number) == Ordering::Greater {
+                    other
+                } else {
+                    latest
+                }
+            })
+    }
+}
+
+impl Default for UserPreferences {
+    fn default() -> UserPreferences {
+        UserPreferences {
+            release_types: Self::default_release_types(),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize, Hash)]
 pub struct User {
     pub id: u64,
     pub name: String,
+    #[serde(default)]
+    pub provider: Provider,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub access_token: Option<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub token_expires_at: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub api_token_hash: Option<String>,
+    #[serde(default)]
+    pub mangadex: Option<MangaDexCredentials>,
+    #[serde(default)]
+    pub mangadex_filters: Option<MangaDexFilters>,
+    #[serde(default)]
+    pub preferences: Option<UserPreferences>,
 }
 
 impl Document for User {}
 
+impl User {
+    pub fn filter(user_id: u64, provider: Provider) -> Result<bson::Document> {
+        let provider = match provider {
+            // Users saved before providers were stored belong to AniList.
+            Provider::AniList => bson::bson!({ "$in": ["anilist", null] }),
+            provider => bson::to_bson(&provider)?,
+        };
+
+        Ok(doc! { "id": user_id as i64, "provider": provider })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct UserProfile {
+    pub id: u64,
+    pub name: String,
+    pub provider: Provider,
+    pub timezone: Option<String>,
+    pub has_access_token: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub token_expires_at: Option<OffsetDateTime>,
+    pub has_mangadex: bool,
+    pub preferences: UserPreferences,
+}
+
+impl From<&User> for UserProfile {
+    fn from(user: &User) -> UserProfile {
+        UserProfile {
+            id: user.id,
+            name: user.name.to_owned(),
+            provider: user.provider,
+            timezone: user.timezone.to_owned(),
+            has_access_token: user.access_token.is_some(),
+            token_expires_at: user.token_expires_at,
+            has_mangadex: user.mangadex.is_some(),
+            preferences: user.preferences.clone().unwrap_or_default(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Hash)]
 pub struct Latest {
     pub title: String,
-    pub episode: u64,
+    pub number: ReleaseNumber,
+    pub url: String,
+    #[serde(default)]
+    pub source: String,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub released_at: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub links: Option<ReleaseLinks>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Hash)]
+pub struct Stream {
+    pub platform: String,
+    pub title: String,
+    pub episode: ReleaseNumber,
     pub url: String,
+    pub region: Option<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub released_at: Option<OffsetDateTime>,
+}
+
+impl From<&Stream> for Latest {
+    fn from(stream: &Stream) -> Latest {
+        Latest {
+            title: stream.title.to_owned(),
+            number: stream.episode.clone(),
+            url: stream.url.to_owned(),
+            source: stream.platform.to_owned(),
+            released_at: stream.released_at,
+            links: Some(ReleaseLinks {
+                page: Some(stream.url.to_owned()),
+                ..Default::default()
+            }),
+        }
+    }
 }
 
 #[derive(Debug, Default, PartialEq, Deserialize, Serialize, Hash)]
@@ -68,13 +289,82 @@ pub struct Media {
     pub episodes: Option<u64>,
     pub score: Option<u64>,
     pub progress: Option<u64>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub updated_at: Option<OffsetDateTime>,
     pub schedule: Option<AnimeScheduleEntry>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub next_release_at: Option<OffsetDateTime>,
     pub latest: Option<Latest>,
+    #[serde(default)]
+    pub latest_by_filters: Option<LatestByFilters>,
+    #[serde(default)]
+    pub latest_by_source: Option<BTreeMap<String, Latest>>,
+    #[serde(default)]
+    pub latest_sources: Option<Vec<String>>,
+    #[serde(default)]
+    pub streams: Option<Vec<Stream>>,
     pub alt_titles: Option<AltTitlesEntry>,
+    pub match_overrides: Option<MatchOverridesEntry>,
+    pub mappings: Option<MediaMappingsEntry>,
 }
 
 impl Document for Media {}
 
+impl Media {
+    pub fn insert_latest(&mut self, latest: Latest) {
+        let mut latest_by_source = self.latest_by_source.take().unwrap_or_default();
+        if let Some(current) = self.latest.take() {
+            latest_by_source
+                .entry(current.source.to_owned())
+                .or_insert(current);
+        }
+        latest_by_source.insert(latest.source.to_owned(), latest);
+        self.latest_by_source = Some(latest_by_source);
+
+        self.resolve_latest(&[]);
+    }
+
+    pub fn resolve_latest(&mut self, priority: &[String]) {
+        let latest_by_source = match &self.latest_by_source {
+            Some(latest_by_source) if !latest_by_source.is_empty() => latest_by_source,
+            _ => return,
+        };
+        let rank = |source: &str| {
+            priority
+                .iter()
+                .position(|prioritized| prioritized == source)
+                .unwrap_or(priority.len())
+        };
+        let rank_cmp = |a: &Latest, b: &Latest| {
+            rank(&b.source)
+                .cmp(&rank(&a.source))
+                .then_with(|| b.source.cmp(&a.source))
+        };
+
+        let latest = latest_by_source
+            .values()
+            .max_by(|a, b| a.number.cmp_value(&b.number).then_with(|| rank_cmp(a, b)));
+        let latest = match latest {
+            Some(latest) => latest.clone(),
+            None => return,
+        };
+
+        let mut sources: Vec<&Latest> = latest_by_source
+            .values()
+            .filter(|other| other.number.cmp_value(&latest.number) == Ordering::Equal)
+            .collect();
+        sources.sort_by(|a, b| rank_cmp(b, a));
+
+        self.latest_sources = Some(
+            sources
+                .into_iter()
+                .map(|latest| latest.source.to_owned())
+                .collect(),
+        );
+        self.latest = Some(latest);
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct MediaLists {
     pub anime: Vec<Media>,
@@ -95,6 +385,42 @@ impl MediaLists {
 )]
 struct AniListListQuery;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/anilist/schema.json",
+    query_path = "graphql/anilist/save_media_list_entry.graphql"
+)]
+struct AniListSaveMediaListEntry;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/anilist/schema.json",
+    query_path = "graphql/anilist/viewer_query.graphql"
+)]
+struct AniListViewerQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/anilist/schema.json",
+    query_path = "graphql/anilist/user_query.graphql"
+)]
+struct AniListUserQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/anilist/schema.json",
+    query_path = "graphql/anilist/mal_ids_query.graphql"
+)]
+struct AniListMalIdsQuery;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SavedMediaListEntry {
+    pub media_id: u64,
+    pub progress: u64,
+    pub status: Option<String>,
+    pub dry_run: bool,
+}
+
 #[derive(Debug, Deserialize)]
 struct CoverImage {
     large: Option<String>,
@@ -126,6 +452,8 @@ struct Entry {
     status: Option<String>,
     score: Option<u64>,
     progress: Option<u64>,
+    #[serde(rename = "updatedAt")]
+    updated_at: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -149,29 +477,638 @@ struct AniListListQueryResults {
     data: AniListListQueryData,
 }
 
+#[derive(Debug, Deserialize)]
+struct SaveMediaListEntryResult {
+    #[serde(rename = "mediaId")]
+    media_id: Option<u64>,
+    status: Option<String>,
+    progress: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AniListSaveMediaListEntryData {
+    #[serde(rename = "SaveMediaListEntry")]
+    save_media_list_entry: Option<SaveMediaListEntryResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AniListError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AniListSaveMediaListEntryResults {
+    data: Option<AniListSaveMediaListEntryData>,
+    errors: Option<Vec<AniListError>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AniListProfile {
+    id: u64,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AniListViewerQueryData {
+    #[serde(rename = "Viewer")]
+    viewer: Option<AniListProfile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AniListViewerQueryResults {
+    data: Option<AniListViewerQueryData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AniListUserQueryData {
+    #[serde(rename = "User")]
+    user: Option<AniListProfile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AniListUserQueryResults {
+    data: Option<AniListUserQueryData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MalIdsMedia {
+    id: u64,
+    #[serde(rename = "idMal")]
+    id_mal: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MalIdsPage {
+    media: Vec<MalIdsMedia>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AniListMalIdsQueryData {
+    #[serde(rename = "Page")]
+    page: MalIdsPage,
+}
+
+#[derive(Debug, Deserialize)]
+struct AniListMalIdsQueryResults {
+    data: Option<AniListMalIdsQueryData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+}
+
 pub struct AniListAPI<'a> {
     config: &'a Config,
 }
 
 impl AniListAPI<'_> {
-    pub fn new(config: &Config) -> AniListAPI {
+    pub fn new(config: &Config) -> AniListAPI<'_> {
         AniListAPI { config }
     }
 
-    async fn fetch<T>(&self, body: &T) -> Result<AniListListQueryResults>
+    async fn fetch<T>(
+        &self,
+        body: &T,
+        access_token: Option<&str>,
+    ) -> Result<AniListListQueryResults>
     where
         T: Serialize,
     {
-        let client = reqwest::Client::new();
+        let client = HttpClient::new(self.config, "anilist_api");
+        let mut request = client.post(self.config.anilist_api.url.as_str());
+        if let Some(access_token) = access_token {
+            request = request.bearer_auth(access_token);
+        }
         let results = client
+            .send(request.json(&body))
+            .await?
+            .json::<AniListListQueryResults>()?;
+
+        Ok(results)
+    }
+
+    fn oauth_config(&self) -> Result<&AniListOAuthConfig> {
+        self.config
+            .anilist_api
+            .oauth
+            .as_ref()
+            .ok_or(CustomError::boxed("No AniList OAuth config provided."))
+    }
+
+    pub fn access_token(&self, user: &User) -> Result<Option<String>> {
+        let access_token = match &user.access_token {
+            Some(access_token) => access_token,
+            None => return Ok(None),
+        };
+        if let Some(token_expires_at) = user.token_expires_at {
+            if token_expires_at <= OffsetDateTime::now_utc() {
+                return Ok(None);
+            }
+        }
+
+        let access_token = crypto::decrypt(self.config.encryption_key()?, access_token)?;
+
+        Ok(Some(access_token))
+    }
+
+    fn oauth_states(
+        &self,
+        mongodb_client: &mongodb::Client,
+    ) -> mongodb::Collection<bson::Document> {
+        mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection("oauth_states")
+    }
+
+    fn oauth_state_cutoff() -> bson::DateTime {
+        bson::DateTime::from_millis(
+            bson::DateTime::now().timestamp_millis() - OAUTH_STATE_TTL * 1000,
+        )
+    }
+
+    // The state is stored hashed next to the session that started the login, so a callback is
+    // only accepted once and only from the browser that was sent to AniList.
+    pub async fn authorize_url(
+        &self,
+        mongodb_client: mongodb::Client,
+        session: &str,
+    ) -> Result<String> {
+        let oauth = self.oauth_config()?;
+        let state = crypto::generate_token();
+
+        let states = self.oauth_states(&mongodb_client);
+        states
+            .delete_many(
+                doc! { "created": { "$lt": Self::oauth_state_cutoff() } },
+                None,
+            )
+            .await?;
+        states
+            .insert_one(
+                doc! {
+                    "state": crypto::hash_token(&state),
+                    "session": crypto::hash_token(session),
+                    "created": bson::DateTime::now(),
+                },
+                None,
+            )
+            .await?;
+
+        let url = reqwest::Url::parse_with_params(
+            &oauth.authorize_url,
+            &[
+                ("client_id", oauth.client_id.as_str()),
+                ("redirect_uri", oauth.redirect_uri.as_str()),
+                ("response_type", "code"),
+                ("state", state.as_str()),
+            ],
+        )?;
+
+        Ok(url.to_string())
+    }
+
+    async fn verify_state(
+        &self,
+        mongodb_client: &mongodb::Client,
+        state: &str,
+        session: &str,
+    ) -> Result<()> {
+        let oauth_state = self
+            .oauth_states(mongodb_client)
+            .find_one_and_delete(
+                doc! {
+                    "state": crypto::hash_token(state),
+                    "session": crypto::hash_token(session),
+                },
+                None,
+            )
+            .await?
+            .ok_or(CustomError::boxed("Invalid OAuth state."))?;
+
+        if *oauth_state.get_datetime("created")? < Self::oauth_state_cutoff() {
+            return Err(CustomError::boxed("Expired OAuth state."));
+        }
+
+        Ok(())
+    }
+
+    async fn exchange_code(&self, code: &str) -> Result<(User, String)> {
+        let oauth = self.oauth_config()?;
+        let client = HttpClient::new(self.config, "anilist_api");
+
+        let request = client
+            .post(oauth.token_url.as_str())
+            .json(&serde_json::json!({
+                "grant_type": "authorization_code",
+                "client_id": &oauth.client_id,
+                "client_secret": &oauth.client_secret,
+                "redirect_uri": &oauth.redirect_uri,
+                "code": code,
+            }));
+        let token = client
+            .send(request)
+            .await?
+            .error_for_status()?
+            .json::<TokenResponse>()?;
+
+        let body = AniListViewerQuery::build_query(ani_list_viewer_query::Variables {});
+        let request = client
             .post(self.config.anilist_api.url.as_str())
-            .json(&body)
-            .send()
+            .bearer_auth(&token.access_token)
+            .json(&body);
+        let viewer = client
+            .send(request)
             .await?
-            .json::<AniListListQueryResults>()
+            .json::<AniListViewerQueryResults>()?
+            .data
+            .and_then(|data| data.viewer)
+            .ok_or(CustomError::boxed("Could not fetch AniList viewer."))?;
+
+        let user = User {
+            id: viewer.id,
+            name: viewer.name,
+            token_expires_at: token
+                .expires_in
+                .map(|expires_in| OffsetDateTime::now_utc() + Duration::seconds(expires_in)),
+            ..Default::default()
+        };
+
+        Ok((user, token.access_token))
+    }
+
+    pub async fn login(
+        &self,
+        mongodb_client: mongodb::Client,
+        code: &str,
+        state: &str,
+        session: &str,
+    ) -> Result<User> {
+        self.verify_state(&mongodb_client, state, session).await?;
+        let (user, access_token) = self.exchange_code(code).await?;
+
+        mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<User>("users")
+            .update_one(
+                User::filter(user.id, Provider::AniList)?,
+                doc! { "$set": { "name": &user.name, "provider": "anilist" } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
             .await?;
+        self.set_access_token(
+            mongodb_client,
+            user.id,
+            &access_token,
+            user.token_expires_at,
+        )
+        .await?;
 
-        Ok(results)
+        Ok(user)
+    }
+
+    pub async fn save_progress(
+        &self,
+        user: &User,
+        media_id: u64,
+        progress: u64,
+        dry_run: bool,
+    ) -> Result<SavedMediaListEntry> {
+        if user.provider != Provider::AniList {
+            return Err(CustomError::boxed(&format!(
+                "Could not save progress of user {}: only AniList users are supported.",
+                user.id
+            )));
+        }
+
+        let access_token = self.access_token(user)?.ok_or(CustomError::boxed(&format!(
+            "No valid AniList access token for user {}.",
+            user.id
+        )))?;
+
+        if dry_run || self.config.anilist_api.dry_run {
+            return Ok(SavedMediaListEntry {
+                media_id,
+                progress,
+                status: None,
+                dry_run: true,
+            });
+        }
+
+        let variables = ani_list_save_media_list_entry::Variables {
+            media_id: Some(media_id as i64),
+            progress: Some(progress as i64),
+        };
+        let body = AniListSaveMediaListEntry::build_query(variables);
+
+        let client = HttpClient::new(self.config, "anilist_api");
+        let request = client
+            .post(self.config.anilist_api.url.as_str())
+            .bearer_auth(access_token)
+            .json(&body);
+        let results = client
+            .send(request)
+            .await?
+            .json::<AniListSaveMediaListEntryResults>()?;
+
+        if let Some(errors) = results.errors {
+            let messages: Vec<String> = errors.into_iter().map(|error| error.message).collect();
+            return Err(CustomError::boxed(&format!(
+                "Could not save progress: {}.",
+                messages.join(", ")
+            )));
+        }
+
+        let entry = results
+            .data
+            .and_then(|data| data.save_media_list_entry)
+            .ok_or(CustomError::boxed("Could not save progress."))?;
+
+        Ok(SavedMediaListEntry {
+            media_id: entry.media_id.unwrap_or(media_id),
+            progress: entry.progress.unwrap_or(progress),
+            status: entry.status,
+            dry_run: false,
+        })
+    }
+
+    pub async fn set_access_token(
+        &self,
+        mongodb_client: mongodb::Client,
+        user_id: u64,
+        access_token: &str,
+        token_expires_at: Option<OffsetDateTime>,
+    ) -> Result<()> {
+        let access_token = crypto::encrypt(self.config.encryption_key()?, access_token)?;
+        let token_expires_at = match token_expires_at {
+            Some(token_expires_at) => Some(token_expires_at.format(&Rfc3339)?),
+            None => None,
+        };
+
+        let result = mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<User>("users")
+            .update_one(
+                User::filter(user_id, Provider::AniList)?,
+                doc! {
+                    "$set": {
+                        "access_token": access_token,
+                        "token_expires_at": token_expires_at,
+                    }
+                },
+                None,
+            )
+            .await?;
+
+        if result.matched_count == 0 {
+            return Err(CustomError::boxed(&format!(
+                "Could not find user {}.",
+                user_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_preferences(
+        &self,
+        mongodb_client: mongodb::Client,
+        user_id: u64,
+        provider: Provider,
+        preferences: &UserPreferences,
+    ) -> Result<()> {
+        let result = mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<User>("users")
+            .update_one(
+                User::filter(user_id, provider)?,
+                doc! { "$set": { "preferences": bson::to_bson(preferences)? } },
+                None,
+            )
+            .await?;
+
+        if result.matched_count == 0 {
+            return Err(CustomError::boxed(&format!(
+                "Could not find user {}.",
+                user_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_timezone(
+        &self,
+        mongodb_client: mongodb::Client,
+        user_id: u64,
+        provider: Provider,
+        timezone: Option<&str>,
+    ) -> Result<()> {
+        if let Some(timezone) = timezone {
+            get_timezone(timezone)?;
+        }
+
+        let result = mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<User>("users")
+            .update_one(
+                User::filter(user_id, provider)?,
+                doc! { "$set": { "timezone": timezone } },
+                None,
+            )
+            .await?;
+
+        if result.matched_count == 0 {
+            return Err(CustomError::boxed(&format!(
+                "Could not find user {}.",
+                user_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub async fn issue_api_token(
+        &self,
+        mongodb_client: mongodb::Client,
+        user_id: u64,
+        provider: Provider,
+    ) -> Result<String> {
+        let token = crypto::generate_token();
+
+        let result = mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<User>("users")
+            .update_one(
+                User::filter(user_id, provider)?,
+                doc! { "$set": { "api_token_hash": crypto::hash_token(&token) } },
+                None,
+            )
+            .await?;
+
+        if result.matched_count == 0 {
+            return Err(CustomError::boxed(&format!(
+                "Could not find user {}.",
+                user_id
+            )));
+        }
+
+        Ok(token)
+    }
+
+    pub async fn verify_api_token(
+        &self,
+        mongodb_client: mongodb::Client,
+        user_id: u64,
+        provider: Provider,
+        token: &str,
+    ) -> Result<bool> {
+        let mut filter = User::filter(user_id, provider)?;
+        filter.insert("api_token_hash", crypto::hash_token(token));
+
+        let user = mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<User>("users")
+            .find_one(filter, None)
+            .await?;
+
+        Ok(user.is_some())
+    }
+
+    pub async fn find_mal_media_ids(
+        &self,
+        media_type: MediaType,
+        ids: &[u64],
+    ) -> Result<HashMap<String, u64>> {
+        let client = HttpClient::new(self.config, "anilist_api");
+        let mut media_ids = HashMap::new();
+        for ids in ids.chunks(MAL_IDS_PAGE_SIZE) {
+            let variables = ani_list_mal_ids_query::Variables {
+                ids: Some(ids.iter().map(|id| Some(*id as i64)).collect()),
+                type_: Some(match media_type {
+                    MediaType::Anime => ani_list_mal_ids_query::MediaType::ANIME,
+                    MediaType::Manga => ani_list_mal_ids_query::MediaType::MANGA,
+                }),
+                per_page: Some(MAL_IDS_PAGE_SIZE as i64),
+            };
+            let body = AniListMalIdsQuery::build_query(variables);
+
+            let results = client
+                .send(
+                    client
+                        .post(self.config.anilist_api.url.as_str())
+                        .json(&body),
+                )
+                .await?
+                .json::<AniListMalIdsQueryResults>()?;
+            let media = results
+                .data
+                .ok_or(CustomError::boxed("Could not look up MyAnimeList ids."))?
+                .page
+                .media;
+            for media in media {
+                if let Some(id_mal) = media.id_mal {
+                    media_ids.insert(id_mal.to_string(), media.id);
+                }
+            }
+        }
+
+        Ok(media_ids)
+    }
+
+    pub async fn fetch_profile(&self, name: &str) -> Result<User> {
+        let variables = ani_list_user_query::Variables {
+            name: Some(name.to_owned()),
+        };
+        let body = AniListUserQuery::build_query(variables);
+
+        let client = HttpClient::new(self.config, "anilist_api");
+        let profile = client
+            .send(
+                client
+                    .post(self.config.anilist_api.url.as_str())
+                    .json(&body),
+            )
+            .await?
+            .json::<AniListUserQueryResults>()?
+            .data
+            .and_then(|data| data.user)
+            .ok_or(CustomError::boxed(&format!(
+                "Could not find AniList user {}.",
+                name
+            )))?;
+
+        Ok(User {
+            id: profile.id,
+            name: profile.name,
+            ..Default::default()
+        })
+    }
+
+    pub async fn add_user(&self, mongodb_client: mongodb::Client, name: &str) -> Result<User> {
+        let user = self.fetch_profile(name).await?;
+        if let Err(err) = self.fetch_lists(&user).await {
+            return Err(CustomError::boxed(&format!(
+                "Could not fetch lists of AniList user {}, log in if they are private: {}",
+                user.name, err
+            )));
+        }
+
+        self.save_user(mongodb_client, &user).await?;
+
+        Ok(user)
+    }
+
+    pub async fn save_user(&self, mongodb_client: mongodb::Client, user: &User) -> Result<()> {
+        mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<User>("users")
+            .update_one(
+                User::filter(user.id, user.provider)?,
+                doc! { "$set": { "name": &user.name, "provider": bson::to_bson(&user.provider)? } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_user(
+        &self,
+        mongodb_client: mongodb::Client,
+        user_id: u64,
+        provider: Provider,
+    ) -> Result<()> {
+        let result = mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<User>("users")
+            .delete_one(User::filter(user_id, provider)?, None)
+            .await?;
+
+        if result.deleted_count == 0 {
+            return Err(CustomError::boxed(&format!(
+                "Could not find user {}.",
+                user_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub async fn fetch_user(
+        &self,
+        mongodb_client: mongodb::Client,
+        user_id: u64,
+        provider: Provider,
+    ) -> Result<Option<User>> {
+        let user = mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<User>("users")
+            .find_one(User::filter(user_id, provider)?, None)
+            .await?;
+
+        Ok(user)
     }
 
     pub async fn fetch_users(&self, mongodb_client: mongodb::Client) -> Result<Vec<User>> {
@@ -206,9 +1143,19 @@ impl AniListAPI<'_> {
                         episodes: entry.media.episodes,
                         score: entry.score,
                         progress: entry.progress,
+                        updated_at: entry.updated_at.and_then(|updated_at| {
+                            OffsetDateTime::from_unix_timestamp(updated_at).ok()
+                        }),
                         schedule: None,
+                        next_release_at: None,
                         latest: None,
+                        latest_by_filters: None,
+                        latest_by_source: None,
+                        latest_sources: None,
+                        streams: None,
                         alt_titles: None,
+                        match_overrides: None,
+                        mappings: None,
                     };
 
                     acc.push(media);
@@ -220,9 +1167,9 @@ impl AniListAPI<'_> {
         Ok(list)
     }
 
-    pub async fn fetch_lists(&self, user_id: u64) -> Result<MediaLists> {
+    pub async fn fetch_lists(&self, user: &User) -> Result<MediaLists> {
         let variables = ani_list_list_query::Variables {
-            user_id: Some(user_id as i64),
+            user_id: Some(user.id as i64),
             status_in: Some(vec![
                 Some(ani_list_list_query::MediaListStatus::CURRENT),
                 Some(ani_list_list_query::MediaListStatus::PLANNING),
@@ -234,7 +1181,8 @@ impl AniListAPI<'_> {
         };
         let body = AniListListQuery::build_query(variables);
 
-        let results = self.fetch(&body).await?;
+        let access_token = self.access_token(user)?;
+        let results = self.fetch(&body, access_token.as_deref()).await?;
 
         let anime = self.transform(&results.data.anime.lists)?;
 
@@ -244,6 +1192,22 @@ impl AniListAPI<'_> {
 
         Ok(lists)
     }
+
+    pub async fn fetch_user_lists(
+        &self,
+        mongodb_client: mongodb::Client,
+        user: &User,
+    ) -> Result<MediaLists> {
+        match user.provider {
+            Provider::AniList => self.fetch_lists(user).await,
+            Provider::Kitsu => KitsuAPI::new(self.config).fetch_lists(user).await,
+            Provider::MyAnimeList => {
+                MyAnimeListAPI::new(self.config)
+                    .fetch_lists(mongodb_client, user)
+                    .await
+            }
+        }
+    }
 }
 
 #[async_trait]
@@ -251,67 +1215,560 @@ impl Extract<'_> for AniListAPI<'_> {
     type Data = MediaLists;
 
     async fn extract(&self, options: Option<ExtractOptions>) -> Result<Self::Data> {
-        let mut data = Vec::new();
-
-        let mongodb_client = match options {
-            Some(options) => match options.mongodb_client {
-                Some(mongodb_client) => mongodb_client,
-                None => return Err(CustomError::boxed("No mongodb client provided.")),
-            },
-            None => return Err(CustomError::boxed("No options provided.")),
-        };
+        let mut data = MediaLists::default();
+
+        let options = options.ok_or(CustomError::boxed("No options provided."))?;
+        let mongodb_client = options
+            .mongodb_client
+            .clone()
+            .ok_or(CustomError::boxed("No mongodb client provided."))?;
 
-        let users = self.fetch_users(mongodb_client).await?;
+        let users = self.fetch_users(mongodb_client.clone()).await?;
         for user in users {
-            data.push(self.fetch_lists(user.id).await?);
+            match self.fetch_user_lists(mongodb_client.clone(), &user).await {
+                Ok(mut lists) => data.append(&mut lists),
+                Err(err) => options.add_issue(
+                    "anilist_api",
+                    &format!("Could not fetch lists of user {}: {}", user.name, err),
+                ),
+            }
         }
 
-        let data = data
-            .iter_mut()
-            .reduce(|acc, d| {
-                acc.append(d);
-                acc
-            })
-            .ok_or(CustomError::boxed("Could not reduce lists."))?;
-
-        Ok(std::mem::take(data))
+        Ok(data)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::Config;
+    use crate::config::{AniListOAuthConfig, Config, FeedConfig};
+    use crate::crypto;
     use crate::db::MongoDB;
-    use crate::test::helpers::{init, reset_db, ONCE};
+    use crate::test::helpers::{fixture_config, init, reset_db, ONCE};
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    const ENCRYPTION_KEY: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+    fn config(url: &str) -> Config {
+        let mut config = Config::default();
+        config.anilist_api.url = url.to_owned();
+        config.anilist_api.oauth = Some(AniListOAuthConfig {
+            client_id: "1".to_owned(),
+            client_secret: "secret".to_owned(),
+            redirect_uri: "http://localhost:8080/oauth/anilist/callback".to_owned(),
+            authorize_url: AniListOAuthConfig::default_authorize_url(),
+            token_url: format!("{}/oauth/token", url),
+            encryption_key: None,
+        });
+        config.encryption_key = Some(ENCRYPTION_KEY.to_owned());
+        config
+    }
+
+    fn user() -> User {
+        User {
+            id: 1,
+            name: "test".to_owned(),
+            access_token: Some(crypto::encrypt(ENCRYPTION_KEY, "token").unwrap()),
+            ..Default::default()
+        }
+    }
 
     #[tokio::test]
     async fn test_fetch_lists() {
         ONCE.get_or_init(init).await;
         reset_db().await;
 
-        let config = Config::default();
+        let config = fixture_config();
         let mongodb = MongoDB::new(&config).await;
         let api = AniListAPI::new(&config);
         let users = api.fetch_users(mongodb.client).await.unwrap();
-        let actual = api.fetch_lists(users[0].id).await.unwrap();
+        let actual = api.fetch_lists(&users[0]).await.unwrap();
         assert!(!actual.anime.is_empty());
         assert!(!actual.manga.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_lists_fixture() {
+        let config = fixture_config();
+        let api = AniListAPI::new(&config);
+        let actual = api
+            .fetch_lists(&User {
+                id: 1,
+                name: "fixture".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(actual.anime.len(), 3);
+        assert_eq!(actual.manga.len(), 3);
+        let media = actual
+            .anime
+            .iter()
+            .find(|media| media.media_id == Some(150672))
+            .unwrap();
+        assert_eq!(media.title.as_deref(), Some("Oshi no Ko"));
+        assert_eq!(media.status.as_deref(), Some("CURRENT"));
+        assert_eq!(media.progress, Some(5));
+        assert_eq!(media.media_type, Some(MediaType::Anime));
+    }
+
     #[tokio::test]
     async fn test_extract() {
         ONCE.get_or_init(init).await;
         reset_db().await;
 
-        let config = Config::default();
+        let config = fixture_config();
         let mongodb = MongoDB::new(&config).await;
         let api = AniListAPI::new(&config);
         let options = ExtractOptions {
             mongodb_client: Some(mongodb.client),
+            ..Default::default()
         };
         let actual = api.extract(Some(options)).await.unwrap();
         assert!(!actual.anime.is_empty());
         assert!(!actual.manga.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_extract_skips_failing_user() {
+        ONCE.get_or_init(init).await;
+        reset_db().await;
+
+        let config = fixture_config();
+        let mongodb = MongoDB::new(&config).await;
+        let users = mongodb
+            .client
+            .database(&config.db.mongodb.database)
+            .collection::<User>("users");
+        users
+            .insert_one(
+                User {
+                    id: 999999,
+                    name: "unreachable".to_owned(),
+                    provider: Provider::Kitsu,
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        let api = AniListAPI::new(&config);
+        let options = ExtractOptions {
+            mongodb_client: Some(mongodb.client.clone()),
+            ..Default::default()
+        };
+        let actual = api.extract(Some(options.clone())).await;
+        users
+            .delete_one(doc! { "id": 999999_i64 }, None)
+            .await
+            .unwrap();
+
+        let actual = actual.unwrap();
+        assert!(!actual.anime.is_empty());
+        let report = options.take_report();
+        assert_eq!(report.issues.len(), 1);
+        assert!(report.issues[0].message.contains("unreachable"));
+    }
+
+    #[tokio::test]
+    async fn test_save_user_keeps_other_providers() {
+        ONCE.get_or_init(init).await;
+        reset_db().await;
+
+        let config = fixture_config();
+        let mongodb = MongoDB::init(&config).await;
+        let users = mongodb
+            .client
+            .database(&config.db.mongodb.database)
+            .collection::<User>("users");
+        users
+            .insert_one(
+                User {
+                    id: 999998,
+                    name: "kitsu".to_owned(),
+                    provider: Provider::Kitsu,
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        let api = AniListAPI::new(&config);
+        let saved = api
+            .save_user(
+                mongodb.client.clone(),
+                &User {
+                    id: 999998,
+                    name: "anilist".to_owned(),
+                    ..Default::default()
+                },
+            )
+            .await;
+        let timezone = api
+            .set_timezone(
+                mongodb.client.clone(),
+                999998,
+                Provider::AniList,
+                Some("Europe/Berlin"),
+            )
+            .await;
+        let removed = api
+            .remove_user(mongodb.client.clone(), 999998, Provider::AniList)
+            .await;
+        let kitsu = api
+            .fetch_user(mongodb.client.clone(), 999998, Provider::Kitsu)
+            .await
+            .unwrap()
+            .unwrap();
+        users
+            .delete_many(doc! { "id": 999998_i64 }, None)
+            .await
+            .unwrap();
+
+        saved.unwrap();
+        timezone.unwrap();
+        removed.unwrap();
+        assert_eq!(kitsu.name, "kitsu");
+        assert_eq!(kitsu.provider, Provider::Kitsu);
+        assert_eq!(kitsu.timezone, None);
+    }
+
+    #[tokio::test]
+    async fn test_save_progress_requires_anilist_user() {
+        let config = Config::default();
+        let api = AniListAPI::new(&config);
+        let user = User {
+            id: 1,
+            name: "test".to_owned(),
+            provider: Provider::Kitsu,
+            ..Default::default()
+        };
+
+        let err = api.save_progress(&user, 1, 1, true).await.unwrap_err();
+        assert!(err.to_string().contains("only AniList users"));
+    }
+
+    #[tokio::test]
+    async fn test_save_progress() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("authorization", "Bearer token"))
+            .and(body_partial_json(serde_json::json!({
+                "variables": { "media_id": 918, "progress": 12 }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": {
+                    "SaveMediaListEntry": {
+                        "id": 1,
+                        "mediaId": 918,
+                        "status": "CURRENT",
+                        "progress": 12
+                    }
+                }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = config(&server.uri());
+        let api = AniListAPI::new(&config);
+
+        let actual = api.save_progress(&user(), 918, 12, false).await.unwrap();
+        assert_eq!(
+            actual,
+            SavedMediaListEntry {
+                media_id: 918,
+                progress: 12,
+                status: Some("CURRENT".to_owned()),
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_save_progress_dry_run() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let config = config(&server.uri());
+        let api = AniListAPI::new(&config);
+
+        let actual = api.save_progress(&user(), 918, 12, true).await.unwrap();
+        assert!(actual.dry_run);
+
+        let user = User {
+            access_token: None,
+            ..user()
+        };
+        assert!(api.save_progress(&user, 918, 12, true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lists_with_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header("authorization", "Bearer token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": { "anime": { "lists": [] }, "manga": { "lists": [] } }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = config(&server.uri());
+        let api = AniListAPI::new(&config);
+        api.fetch_lists(&user()).await.unwrap();
+
+        let expired = User {
+            token_expires_at: Some(OffsetDateTime::now_utc() - Duration::days(1)),
+            ..user()
+        };
+        assert_eq!(api.access_token(&expired).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_exchange_code() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/oauth/token"))
+            .and(body_partial_json(serde_json::json!({ "code": "code" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "token_type": "Bearer",
+                "expires_in": 31536000,
+                "access_token": "token"
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/"))
+            .and(header("authorization", "Bearer token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": { "Viewer": { "id": 1, "name": "test" } }
+            })))
+            .mount(&server)
+            .await;
+
+        let config = config(&server.uri());
+        let api = AniListAPI::new(&config);
+
+        let (user, access_token) = api.exchange_code("code").await.unwrap();
+        assert_eq!(user.id, 1);
+        assert_eq!(user.name, "test");
+        assert!(user.token_expires_at.unwrap() > OffsetDateTime::now_utc());
+        assert_eq!(access_token, "token");
+    }
+
+    #[tokio::test]
+    async fn test_verify_state() {
+        ONCE.get_or_init(init).await;
+
+        let config = config("http://localhost");
+        let mongodb = MongoDB::new(&config).await;
+        let api = AniListAPI::new(&config);
+
+        let state = |url: String| {
+            let url = reqwest::Url::parse(&url).unwrap();
+            let (_, state) = url.query_pairs().find(|(key, _)| key == "state").unwrap();
+            state.into_owned()
+        };
+        let first = state(
+            api.authorize_url(mongodb.client.clone(), "session")
+                .await
+                .unwrap(),
+        );
+        let second = state(
+            api.authorize_url(mongodb.client.clone(), "session")
+                .await
+                .unwrap(),
+        );
+
+        assert!(api
+            .verify_state(&mongodb.client, &first, "other")
+            .await
+            .is_err());
+        assert!(api
+            .verify_state(&mongodb.client, &first, "session")
+            .await
+            .is_ok());
+        assert!(api
+            .verify_state(&mongodb.client, &first, "session")
+            .await
+            .is_err());
+        assert!(api
+            .verify_state(&mongodb.client, "state", "session")
+            .await
+            .is_err());
+
+        api.oauth_states(&mongodb.client)
+            .update_one(
+                doc! { "state": crypto::hash_token(&second) },
+                doc! { "$set": { "created": bson::DateTime::from_millis(0) } },
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(api
+            .verify_state(&mongodb.client, &second, "session")
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_resolve_latest() {
+        let latest = |source: &str, number: &str| Latest {
+            title: "Chainsaw Man".to_owned(),
+            number: ReleaseNumber::parse(number, None),
+            url: format!("http://localhost/{}/{}", source, number),
+            source: source.to_owned(),
+            released_at: None,
+            links: None,
+        };
+
+        let mut media = Media::default();
+        media.insert_latest(latest("mangadex_api", "130"));
+        media.insert_latest(latest("mangaupdates_api", "131"));
+        media.insert_latest(latest("release_feed", "130"));
+        assert_eq!(media.latest, Some(latest("mangaupdates_api", "131")));
+        assert_eq!(
+            media.latest_sources,
+            Some(vec!["mangaupdates_api".to_owned()])
+        );
+        assert_eq!(media.latest_by_source.as_ref().unwrap().len(), 3);
+
+        media.insert_latest(latest("mangadex_api", "131"));
+        media.resolve_latest(&["mangadex_api".to_owned(), "mangaupdates_api".to_owned()]);
+        assert_eq!(media.latest, Some(latest("mangadex_api", "131")));
+        assert_eq!(
+            media.latest_sources,
+            Some(vec![
+                "mangadex_api".to_owned(),
+                "mangaupdates_api".to_owned()
+            ])
+        );
+        assert_eq!(
+            media.latest_by_source.unwrap().get("release_feed"),
+            Some(&latest("release_feed", "130"))
+        );
+    }
+
+    #[test]
+    fn test_preferences() {
+        let fansub = Latest {
+            title: "Oshi no Ko".to_owned(),
+            number: ReleaseNumber::parse("5", None),
+            url: "http://localhost/5".to_owned(),
+            source: "subsplease_rss".to_owned(),
+            released_at: None,
+            links: None,
+        };
+        let media = Media {
+            streams: Some(vec![Stream {
+                platform: "crunchyroll".to_owned(),
+                title: "Oshi no Ko".to_owned(),
+                episode: ReleaseNumber::parse("6", None),
+                url: "http://localhost/watch/6".to_owned(),
+                region: None,
+                released_at: None,
+            }]),
+            ..Default::default()
+        };
+
+        let mut config = Config::default();
+        let latest = UserPreferences::default()
+            .latest(Some(fansub.clone()), &media, &config)
+            .unwrap();
+        assert_eq!(latest.source, "crunchyroll");
+
+        let fansub_only = UserPreferences {
+            release_types: vec![ReleaseType::Fansub],
+        };
+        assert_eq!(
+            fansub_only.latest(Some(fansub.clone()), &media, &config),
+            Some(fansub.clone())
+        );
+
+        let official_only = UserPreferences {
+            release_types: vec![ReleaseType::Official],
+        };
+        let latest = official_only
+            .latest(Some(fansub.clone()), &media, &config)
+            .unwrap();
+        assert_eq!(latest.number, ReleaseNumber::parse("6", None));
+        assert_eq!(official_only.latest(None, &Media::default(), &config), None);
+        assert!("simulcast".parse::<ReleaseType>().is_err());
+
+        let publisher = Latest {
+            number: ReleaseNumber::parse("7", None),
+            url: "http://localhost/publisher/7".to_owned(),
+            source: "publisher".to_owned(),
+            ..fansub.clone()
+        };
+        let media = Media {
+            latest: Some(publisher.clone()),
+            latest_by_source: Some(BTreeMap::from([
+                ("publisher".to_owned(), publisher.clone()),
+                ("subsplease_rss".to_owned(), fansub.clone()),
+            ])),
+            ..media
+        };
+        assert_eq!(
+            fansub_only.latest(None, &media, &config),
+            Some(publisher.clone())
+        );
+
+        config.feeds = vec![FeedConfig {
+            name: "publisher".to_owned(),
+            url: "http://localhost/publisher".to_owned(),
+            format: Default::default(),
+            title_regex: "(?P<title>.+) (?P<episode>\\d+)".to_owned(),
+            media_type: "anime".to_owned(),
+            link_field: Default::default(),
+            release_type: ReleaseType::Official,
+            enriched_statuses: None,
+        }];
+        assert_eq!(
+            fansub_only.latest(None, &media, &config),
+            Some(fansub.clone())
+        );
+        assert_eq!(official_only.latest(None, &media, &config), Some(publisher));
+    }
+
+    #[tokio::test]
+    async fn test_profile() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(serde_json::json!({
+                "variables": { "name": "test" }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": { "User": { "id": 1, "name": "test" } }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "errors": [{ "message": "Not Found.", "status": 404 }],
+                "data": { "User": null }
+            })))
+            .mount(&server)
+            .await;
+
+        let config = config(&server.uri());
+        let api = AniListAPI::new(&config);
+
+        let actual = api.fetch_profile("test").await.unwrap();
+        assert_eq!(actual.id, 1);
+        assert_eq!(actual.name, "test");
+        assert!(api.fetch_profile("missing").await.is_err());
+    }
 }
@@ -0,0 +1,584 @@
+use crate::anilist_api::{AniListAPI, Media, MediaLists, MediaType, Provider, User};
+use crate::config::Config;
+use crate::error::CustomError;
+use crate::http::HttpClient;
+use crate::result::Result;
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use time::OffsetDateTime;
+
+const PAGE_LIMIT: usize = 500;
+
+#[derive(Debug, Deserialize)]
+struct KitsuIdentifier {
+    id: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct KitsuToOne {
+    data: Option<KitsuIdentifier>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct KitsuToMany {
+    #[serde(default)]
+    data: Vec<KitsuIdentifier>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibraryEntryAttributes {
+    status: String,
+    #[serde(default)]
+    reconsuming: bool,
+    progress: Option<u64>,
+    #[serde(rename = "ratingTwenty")]
+    rating_twenty: Option<u64>,
+    #[serde(
+        rename = "progressedAt",
+        default,
+        with = "time::serde::rfc3339::option"
+    )]
+    progressed_at: Option<OffsetDateTime>,
+    #[serde(rename = "updatedAt", default, with = "time::serde::rfc3339::option")]
+    updated_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct LibraryEntryRelationships {
+    #[serde(default)]
+    anime: KitsuToOne,
+    #[serde(default)]
+    manga: KitsuToOne,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibraryEntry {
+    attributes: LibraryEntryAttributes,
+    #[serde(default)]
+    relationships: LibraryEntryRelationships,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct KitsuTitles {
+    en: Option<String>,
+    en_jp: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct KitsuImage {
+    large: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KitsuMediaAttributes {
+    #[serde(rename = "canonicalTitle")]
+    canonical_title: Option<String>,
+    #[serde(default)]
+    titles: KitsuTitles,
+    subtype: Option<String>,
+    #[serde(rename = "startDate")]
+    start_date: Option<String>,
+    #[serde(rename = "episodeCount")]
+    episode_count: Option<u64>,
+    #[serde(rename = "posterImage", default)]
+    poster_image: Option<KitsuImage>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct KitsuMediaRelationships {
+    #[serde(default)]
+    mappings: KitsuToMany,
+}
+
+#[derive(Debug, Deserialize)]
+struct KitsuMedia {
+    id: String,
+    attributes: KitsuMediaAttributes,
+    #[serde(default)]
+    relationships: KitsuMediaRelationships,
+}
+
+#[derive(Debug, Deserialize)]
+struct KitsuMappingAttributes {
+    #[serde(rename = "externalSite")]
+    external_site: String,
+    #[serde(rename = "externalId")]
+    external_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct KitsuMapping {
+    id: String,
+    attributes: KitsuMappingAttributes,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum KitsuIncluded {
+    #[serde(rename = "anime")]
+    Anime(KitsuMedia),
+    #[serde(rename = "manga")]
+    Manga(KitsuMedia),
+    #[serde(rename = "mappings")]
+    Mapping(KitsuMapping),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct KitsuLinks {
+    next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibraryEntries {
+    data: Vec<LibraryEntry>,
+    #[serde(default)]
+    included: Vec<KitsuIncluded>,
+    #[serde(default)]
+    links: KitsuLinks,
+}
+
+#[derive(Debug, Deserialize)]
+struct KitsuUserAttributes {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct KitsuUser {
+    id: String,
+    attributes: KitsuUserAttributes,
+}
+
+#[derive(Debug, Deserialize)]
+struct KitsuUsers {
+    data: Vec<KitsuUser>,
+}
+
+fn status(status: &str, reconsuming: bool) -> Option<String> {
+    let status = match status {
+        "current" if reconsuming => "REPEATING",
+        "current" => "CURRENT",
+        "planned" => "PLANNING",
+        "completed" => "COMPLETED",
+        "on_hold" => "PAUSED",
+        "dropped" => "DROPPED",
+        _ => return None,
+    };
+
+    Some(status.to_owned())
+}
+
+fn format(subtype: &str) -> String {
+    match subtype.to_lowercase().as_str() {
+        "oneshot" => "ONE_SHOT".to_owned(),
+        "manhwa" | "manhua" | "oel" => "MANGA".to_owned(),
+        subtype => subtype.to_uppercase(),
+    }
+}
+
+fn season(start_date: &str) -> Option<(String, u64)> {
+    let mut parts = start_date.split('-');
+    let year = parts.next()?.parse().ok()?;
+    let season = match parts.next()?.parse::<u8>().ok()? {
+        1..=3 => "WINTER",
+        4..=6 => "SPRING",
+        7..=9 => "SUMMER",
+        10..=12 => "FALL",
+        _ => return None,
+    };
+
+    Some((season.to_owned(), year))
+}
+
+pub struct KitsuAPI<'a> {
+    config: &'a Config,
+}
+
+impl KitsuAPI<'_> {
+    pub fn new(config: &Config) -> KitsuAPI<'_> {
+        KitsuAPI { config }
+    }
+
+    pub async fn fetch_profile(&self, name: &str) -> Result<User> {
+        let client = HttpClient::new(self.config, "kitsu_api");
+        let request = client
+            .get(format!("{}/users", self.config.kitsu_api.url))
+            .query(&[("filter[name]", name)])
+            .header("accept", "application/vnd.api+json");
+        let user = client
+            .send(request)
+            .await?
+            .error_for_status()?
+            .json::<KitsuUsers>()?
+            .data
+            .into_iter()
+            .next()
+            .ok_or(CustomError::boxed(&format!(
+                "Could not find Kitsu user {}.",
+                name
+            )))?;
+
+        Ok(User {
+            id: user.id.parse()?,
+            name: user.attributes.name,
+            provider: Provider::Kitsu,
+            ..Default::default()
+        })
+    }
+
+    pub async fn add_user(&self, mongodb_client: mongodb::Client, name: &str) -> Result<User> {
+        let user = self.fetch_profile(name).await?;
+        if let Err(err) = self.fetch_lists(&user).await {
+            return Err(CustomError::boxed(&format!(
+                "Could not fetch lists of Kitsu user {}: {}",
+                user.name, err
+            )));
+        }
+
+        AniListAPI::new(self.config)
+            .save_user(mongodb_client, &user)
+            .await?;
+
+        Ok(user)
+    }
+
+    fn transform(&self, media_type: MediaType, entries: LibraryEntries) -> Vec<Media> {
+        let (kind, site) = match media_type {
+            MediaType::Anime => ("anime", "anilist/anime"),
+            MediaType::Manga => ("manga", "anilist/manga"),
+        };
+
+        let mut media_by_id = HashMap::new();
+        let mut mappings = HashMap::new();
+        for included in entries.included {
+            match included {
+                KitsuIncluded::Anime(media) | KitsuIncluded::Manga(media) => {
+                    media_by_id.insert(media.id.to_owned(), media);
+                }
+                KitsuIncluded::Mapping(mapping) => {
+                    if mapping.attributes.external_site == site {
+                        mappings.insert(mapping.id, mapping.attributes.external_id);
+                    }
+                }
+                KitsuIncluded::Other => {}
+            }
+        }
+
+        entries
+            .data
+            .into_iter()
+            .filter_map(|entry| {
+                let relationship = match media_type {
+                    MediaType::Anime => entry.relationships.anime,
+                    MediaType::Manga => entry.relationships.manga,
+                };
+                let media = media_by_id.get(&relationship.data?.id)?;
+                let media_id = media
+                    .relationships
+                    .mappings
+                    .data
+                    .iter()
+                    .find_map(|mapping| mappings.get(&mapping.id))
+                    .and_then(|media_id| media_id.parse().ok());
+                if media_id.is_none() {
+                    eprintln!("Could not find AniList id of Kitsu {} {}.", kind, media.id);
+                    return None;
+                }
+
+                let attributes = &media.attributes;
+                let (season, season_year) = match (&media_type, &attributes.start_date) {
+                    (MediaType::Anime, Some(start_date)) => match season(start_date) {
+                        Some((season, year)) => (Some(season), Some(year)),
+                        None => (None, None),
+                    },
+                    _ => (None, None),
+                };
+
+                Some(Media {
+                    media_id,
+                    status: status(&entry.attributes.status, entry.attributes.reconsuming),
+                    format: attributes.subtype.as_deref().map(format),
+                    season,
+                    season_year,
+                    title: attributes
+                        .titles
+                        .en_jp
+                        .clone()
+                        .or(attributes.canonical_title.clone()),
+                    english_title: attributes.titles.en.clone(),
+                    image: attributes
+                        .poster_image
+                        .as_ref()
+                        .and_then(|image| image.large.clone()),
+                    episodes: match media_type {
+                        MediaType::Anime => attributes.episode_count,
+                        MediaType::Manga => None,
+                    },
+                    score: entry.attributes.rating_twenty.map(|rating| rating / 2),
+                    progress: entry.attributes.progress,
+                    updated_at: entry
+                        .attributes
+                        .progressed_at
+                        .or(entry.attributes.updated_at),
+                    media_type: Some(media_type),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    async fn fetch_entries(&self, user: &User, media_type: MediaType) -> Result<Vec<Media>> {
+        let kind = match media_type {
+            MediaType::Anime => "anime",
+            MediaType::Manga => "manga",
+        };
+        let include = format!("{kind},{kind}.mappings");
+        let user_id = user.id.to_string();
+        let limit = PAGE_LIMIT.to_string();
+
+        let client = HttpClient::new(self.config, "kitsu_api");
+        let mut media = Vec::new();
+        let mut offset = 0;
+        loop {
+            let offset_param = offset.to_string();
+            let request = client
+                .get(format!("{}/library-entries", self.config.kitsu_api.url))
+                .query(&[
+                    ("filter[userId]", user_id.as_str()),
+                    ("filter[kind]", kind),
+                    ("include", include.as_str()),
+                    ("page[limit]", limit.as_str()),
+                    ("page[offset]", offset_param.as_str()),
+                ])
+                .header("accept", "application/vnd.api+json");
+            let entries = client
+                .send(request)
+                .await?
+                .error_for_status()?
+                .json::<LibraryEntries>()?;
+
+            let count = entries.data.len();
+            let has_next = entries.links.next.is_some();
+            media.append(&mut self.transform(media_type, entries));
+
+            if !has_next || count == 0 {
+                break;
+            }
+            offset += count;
+        }
+
+        Ok(media)
+    }
+
+    pub async fn fetch_lists(&self, user: &User) -> Result<MediaLists> {
+        let anime = self.fetch_entries(user, MediaType::Anime).await?;
+        let manga = self.fetch_entries(user, MediaType::Manga).await?;
+
+        Ok(MediaLists { anime, manga })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn config(url: &str) -> Config {
+        let mut config = Config::default();
+        config.kitsu_api.url = url.to_owned();
+        config
+    }
+
+    fn user() -> User {
+        User {
+            id: 1,
+            name: "test".to_owned(),
+            provider: Provider::Kitsu,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lists() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/library-entries"))
+            .and(query_param("filter[userId]", "1"))
+            .and(query_param("filter[kind]", "anime"))
+            .and(query_param("page[offset]", "0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [
+                    {
+                        "id": "10",
+                        "type": "libraryEntries",
+                        "attributes": {
+                            "status": "current",
+                            "reconsuming": false,
+                            "progress": 5,
+                            "ratingTwenty": 16,
+                            "progressedAt": "2023-05-17T16:01:10.000Z",
+                            "updatedAt": "2023-05-18T16:01:10.000Z"
+                        },
+                        "relationships": { "anime": { "data": { "type": "anime", "id": "100" } } }
+                    },
+                    {
+                        "id": "11",
+                        "type": "libraryEntries",
+                        "attributes": { "status": "planned", "reconsuming": false, "progress": 0 },
+                        "relationships": { "anime": { "data": { "type": "anime", "id": "101" } } }
+                    }
+                ],
+                "included": [
+                    {
+                        "id": "100",
+                        "type": "anime",
+                        "attributes": {
+                            "canonicalTitle": "Oshi no Ko",
+                            "titles": { "en": "Oshi No Ko", "en_jp": "Oshi no Ko" },
+                            "subtype": "TV",
+                            "startDate": "2023-04-12",
+                            "episodeCount": 11,
+                            "posterImage": { "large": "http://localhost/100.jpg" }
+                        },
+                        "relationships": {
+                            "mappings": { "data": [
+                                { "type": "mappings", "id": "1000" },
+                                { "type": "mappings", "id": "1001" }
+                            ] }
+                        }
+                    },
+                    {
+                        "id": "101",
+                        "type": "anime",
+                        "attributes": { "canonicalTitle": "Unmapped" },
+                        "relationships": { "mappings": { "data": [] } }
+                    },
+                    {
+                        "id": "1000",
+                        "type": "mappings",
+                        "attributes": { "externalSite": "myanimelist/anime", "externalId": "52034" }
+                    },
+                    {
+                        "id": "1001",
+                        "type": "mappings",
+                        "attributes": { "externalSite": "anilist/anime", "externalId": "150672" }
+                    }
+                ],
+                "links": { "next": format!("{}/library-entries?page[offset]=2", server.uri()) }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/library-entries"))
+            .and(query_param("filter[kind]", "anime"))
+            .and(query_param("page[offset]", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [], "links": {}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/library-entries"))
+            .and(query_param("filter[kind]", "manga"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [
+                    {
+                        "id": "12",
+                        "type": "libraryEntries",
+                        "attributes": { "status": "current", "reconsuming": true, "progress": 120 },
+                        "relationships": { "manga": { "data": { "type": "manga", "id": "200" } } }
+                    }
+                ],
+                "included": [
+                    {
+                        "id": "200",
+                        "type": "manga",
+                        "attributes": { "canonicalTitle": "Oshi no Ko", "subtype": "manga" },
+                        "relationships": { "mappings": { "data": [{ "type": "mappings", "id": "2000" }] } }
+                    },
+                    {
+                        "id": "2000",
+                        "type": "mappings",
+                        "attributes": { "externalSite": "anilist/manga", "externalId": "107237" }
+                    },
+                    { "id": "1", "type": "genres", "attributes": {} }
+                ],
+                "links": {}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = config(&server.uri());
+        let actual = KitsuAPI::new(&config).fetch_lists(&user()).await.unwrap();
+
+        assert_eq!(actual.anime.len(), 1);
+        let anime = &actual.anime[0];
+        assert_eq!(anime.media_id, Some(150672));
+        assert_eq!(anime.media_type, Some(MediaType::Anime));
+        assert_eq!(anime.status.as_deref(), Some("CURRENT"));
+        assert_eq!(anime.format.as_deref(), Some("TV"));
+        assert_eq!(anime.season.as_deref(), Some("SPRING"));
+        assert_eq!(anime.season_year, Some(2023));
+        assert_eq!(anime.title.as_deref(), Some("Oshi no Ko"));
+        assert_eq!(anime.english_title.as_deref(), Some("Oshi No Ko"));
+        assert_eq!(anime.episodes, Some(11));
+        assert_eq!(anime.score, Some(8));
+        assert_eq!(anime.progress, Some(5));
+        assert_eq!(
+            anime.updated_at,
+            Some(time::macros::datetime!(2023-05-17 16:01:10 UTC))
+        );
+
+        assert_eq!(actual.manga.len(), 1);
+        let manga = &actual.manga[0];
+        assert_eq!(manga.media_id, Some(107237));
+        assert_eq!(manga.status.as_deref(), Some("REPEATING"));
+        assert_eq!(manga.format.as_deref(), Some("MANGA"));
+        assert_eq!(manga.season, None);
+    }
+
+    #[tokio::test]
+    async fn test_profile() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/users"))
+            .and(query_param("filter[name]", "test"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [{ "id": "1", "type": "users", "attributes": { "name": "test" } }]
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/users"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": []
+            })))
+            .mount(&server)
+            .await;
+
+        let config = config(&server.uri());
+        let api = KitsuAPI::new(&config);
+
+        let actual = api.fetch_profile("test").await.unwrap();
+        assert_eq!(actual.id, 1);
+        assert_eq!(actual.provider, Provider::Kitsu);
+        assert!(api.fetch_profile("missing").await.is_err());
+    }
+
+    #[test]
+    fn test_status() {
+        assert_eq!(status("on_hold", false).as_deref(), Some("PAUSED"));
+        assert_eq!(status("current", true).as_deref(), Some("REPEATING"));
+        assert_eq!(status("unknown", false), None);
+        assert_eq!(format("oneshot"), "ONE_SHOT");
+        assert_eq!(season("2023-10-01"), Some(("FALL".to_owned(), 2023)));
+    }
+}
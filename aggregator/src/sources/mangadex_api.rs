@@ -1,21 +1,27 @@
-use crate::config::Config;
+use crate::config::{Config, MangaDexFilters};
+use crate::crypto;
 use crate::error::CustomError;
-use crate::options::ExtractOptions;
+use crate::http::HttpClient;
+use crate::options::{ExtractOptions, TransformOptions};
+use crate::release::ReleaseNumber;
+use crate::report::RunReport;
 use crate::result::Result;
-use crate::sources::anilist_api::{Latest, Media, MediaType};
-use crate::sources::{Extract, Similar, Transform};
+use crate::sources::anilist_api::{AniListAPI, Latest, Media, MediaType, Provider, User};
+use crate::sources::{id_key, Extract, Similar, Transform};
 
 use async_trait::async_trait;
+use bson::doc;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tokio::{
-    task::JoinSet,
-    time::{sleep, Duration},
-};
+use std::collections::{BTreeMap, HashMap};
+use time::{OffsetDateTime, UtcOffset};
+use tokio::{task::JoinSet, time::Duration};
 
 #[derive(Debug, Deserialize)]
 struct MangaListAttributes {
     title: HashMap<String, String>,
+    #[serde(rename = "altTitles", default)]
+    alt_titles: Vec<HashMap<String, String>>,
+    links: Option<HashMap<String, String>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -36,9 +42,18 @@ struct MangaList {
     data: MangaListData,
 }
 
+#[derive(Debug, Deserialize)]
+struct MangaPage {
+    result: String,
+    data: Vec<MangaListRelationship>,
+    total: usize,
+}
+
 #[derive(Debug, Deserialize)]
 struct MangaAggregateChapter {
     id: String,
+    #[serde(default)]
+    others: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -52,8 +67,183 @@ struct MangaAggregate {
     volumes: HashMap<String, MangaAggregateVolumes>,
 }
 
+#[derive(Debug, Deserialize)]
+struct ChapterAttributes {
+    #[serde(rename = "publishAt", with = "time::serde::rfc3339")]
+    publish_at: OffsetDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChapterData {
+    id: String,
+    attributes: ChapterAttributes,
+    #[serde(default)]
+    relationships: Vec<MangaListRelationship>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChapterList {
+    result: String,
+    data: Vec<ChapterData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeedChapterAttributes {
+    volume: Option<String>,
+    chapter: Option<String>,
+    #[serde(rename = "translatedLanguage")]
+    translated_language: Option<String>,
+    #[serde(rename = "publishAt", with = "time::serde::rfc3339")]
+    publish_at: OffsetDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeedChapter {
+    id: String,
+    attributes: FeedChapterAttributes,
+    relationships: Vec<MangaListRelationship>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChapterFeed {
+    result: String,
+    data: Vec<FeedChapter>,
+    total: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Hash)]
+pub struct MangaDexCredentials {
+    pub username: String,
+    pub password: String,
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+#[derive(Debug, Clone)]
+struct MangaListEntry {
+    id: String,
+    title: String,
+    alt_titles: Vec<String>,
+    anilist_id: Option<String>,
+}
+
+// The newest chapter per language, kept apart for every set of group filters
+// so users only see chapters their own filters allow.
+pub type LatestByFilters = BTreeMap<String, BTreeMap<String, Latest>>;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MangaDexLatest {
+    pub latest: Latest,
+    pub by_filters: LatestByFilters,
+}
+
+impl MangaDexLatest {
+    pub fn new(filters_key: &str, language: &str, latest: Latest) -> MangaDexLatest {
+        MangaDexLatest {
+            by_filters: BTreeMap::from([(
+                filters_key.to_owned(),
+                BTreeMap::from([(language.to_owned(), latest.clone())]),
+            )]),
+            latest,
+        }
+    }
+
+    pub fn preferred(&self, languages: &[String]) -> Option<&Latest> {
+        languages.iter().find_map(|language| {
+            self.by_filters
+                .values()
+                .filter_map(|by_language| by_language.get(language))
+                .max_by(|a, b| a.number.cmp(&b.number))
+        })
+    }
+
+    fn merge(&mut self, other: MangaDexLatest, languages: &[String]) {
+        for (filters_key, by_language) in other.by_filters {
+            let current_by_language = self.by_filters.entry(filters_key).or_default();
+            for (language, latest) in by_language {
+                match current_by_language.get(&language) {
+                    Some(current) if current.number >= latest.number => (),
+                    _ => {
+                        current_by_language.insert(language, latest);
+                    }
+                }
+            }
+        }
+
+        let latest = self.preferred(languages).or_else(|| {
+            self.by_filters
+                .values()
+                .flat_map(|by_language| by_language.values())
+                .max_by(|a, b| a.number.cmp(&b.number))
+        });
+        if let Some(latest) = latest {
+            self.latest = latest.clone();
+        }
+    }
+}
+
 #[derive(Debug, Default, Deserialize, Serialize)]
-pub struct MangaLatest(pub HashMap<String, Latest>);
+pub struct MangaLatest(pub HashMap<String, MangaDexLatest>);
+
+impl MangaLatest {
+    fn insert_newer(&mut self, key: String, latest: MangaDexLatest, languages: &[String]) {
+        match self.0.get_mut(&key) {
+            Some(current) => current.merge(latest, languages),
+            None => {
+                self.0.insert(key, latest);
+            }
+        }
+    }
+
+    fn insert_entry(
+        &mut self,
+        entry: MangaListEntry,
+        latest: MangaDexLatest,
+        languages: &[String],
+    ) {
+        if let Some(anilist_id) = entry.anilist_id {
+            self.insert_newer(id_key("anilist", &anilist_id), latest.clone(), languages);
+        }
+        self.insert_newer(id_key("mangadex", &entry.id), latest.clone(), languages);
+        for alt_title in entry.alt_titles {
+            self.0.entry(alt_title).or_insert_with(|| latest.clone());
+        }
+        self.insert_newer(entry.title, latest, languages);
+    }
+
+    fn merge(&mut self, other: MangaLatest, languages: &[String]) {
+        for (key, latest) in other.0 {
+            self.insert_newer(key, latest, languages);
+        }
+    }
+}
+
+// A chapter number with the ids of every upload of it, newest chapter numbers first.
+type ChapterCandidates = (ReleaseNumber, Vec<String>);
+
+fn groups_as_str(groups: &[String]) -> Vec<&str> {
+    groups.iter().map(|group| group.as_str()).collect()
+}
+
+fn group_ids(relationships: &[MangaListRelationship]) -> Vec<&str> {
+    relationships
+        .iter()
+        .filter(|rel| rel.r#type == "scanlation_group")
+        .map(|rel| rel.id.as_str())
+        .collect()
+}
+
+const MANGA_BATCH_SIZE: usize = 100;
+
+// How many of the newest chapter numbers to check when group filters may reject the newest.
+const FALLBACK_CHAPTERS: usize = 10;
+
+const ALT_TITLE_LANGUAGES: [&str; 2] = ["en", "ja-ro"];
 
 #[derive(Debug, Clone)]
 pub struct MangaDexAPI<'a> {
@@ -61,48 +251,416 @@ pub struct MangaDexAPI<'a> {
 }
 
 impl MangaDexAPI<'_> {
-    pub fn new(config: &Config) -> MangaDexAPI {
+    pub fn new(config: &Config) -> MangaDexAPI<'_> {
         MangaDexAPI { config }
     }
 
-    pub async fn fetch(&self) -> Result<MangaLatest> {
-        let client = reqwest::Client::new();
-        let results = client
-            .get(self.config.mangadex_api.url.as_str())
-            .send()
-            .await?
-            .json::<MangaList>()
+    fn parse_manga(rel: MangaListRelationship) -> Option<MangaListEntry> {
+        if rel.r#type != "manga" {
+            return None;
+        }
+        let attributes = rel.attributes?;
+
+        let title = if let Some(ro_title) = attributes.title.get("ja-ro") {
+            ro_title.to_owned()
+        } else if let Some(en_title) = attributes.title.get("en") {
+            en_title.to_owned()
+        } else {
+            return None;
+        };
+        if title.is_empty() {
+            return None;
+        }
+
+        Some(MangaListEntry {
+            id: rel.id,
+            title,
+            alt_titles: attributes
+                .alt_titles
+                .into_iter()
+                .flat_map(|alt_title| alt_title.into_iter())
+                .filter(|(language, _)| ALT_TITLE_LANGUAGES.contains(&language.as_str()))
+                .map(|(_, alt_title)| alt_title)
+                .collect(),
+            anilist_id: attributes.links.and_then(|mut links| links.remove("al")),
+        })
+    }
+
+    pub async fn set_credentials(
+        &self,
+        mongodb_client: mongodb::Client,
+        user_id: u64,
+        provider: Provider,
+        credentials: &MangaDexCredentials,
+    ) -> Result<()> {
+        let encryption_key = self.config.encryption_key()?;
+        let credentials = MangaDexCredentials {
+            password: crypto::encrypt(encryption_key, &credentials.password)?,
+            client_secret: crypto::encrypt(encryption_key, &credentials.client_secret)?,
+            ..credentials.clone()
+        };
+
+        let result = mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<User>("users")
+            .update_one(
+                User::filter(user_id, provider)?,
+                doc! { "$set": { "mangadex": bson::to_bson(&credentials)? } },
+                None,
+            )
             .await?;
 
-        let mut manga_latest: HashMap<String, Latest> = HashMap::new();
-        let mut batches: Vec<Vec<(String, String)>> = Vec::new();
-        let mut current_batch: Vec<(String, String)> = Vec::new();
+        if result.matched_count == 0 {
+            return Err(CustomError::boxed(&format!(
+                "Could not find user {}.",
+                user_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_filters(
+        &self,
+        mongodb_client: mongodb::Client,
+        user_id: u64,
+        provider: Provider,
+        filters: &MangaDexFilters,
+    ) -> Result<()> {
+        let result = mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<User>("users")
+            .update_one(
+                User::filter(user_id, provider)?,
+                doc! { "$set": { "mangadex_filters": bson::to_bson(filters)? } },
+                None,
+            )
+            .await?;
+
+        if result.matched_count == 0 {
+            return Err(CustomError::boxed(&format!(
+                "Could not find user {}.",
+                user_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn fetch_access_token(
+        &self,
+        client: &HttpClient,
+        credentials: &MangaDexCredentials,
+    ) -> Result<String> {
+        let encryption_key = self.config.encryption_key()?;
+        let password = crypto::decrypt(encryption_key, &credentials.password)?;
+        let client_secret = crypto::decrypt(encryption_key, &credentials.client_secret)?;
+
+        let request = client
+            .post(self.config.mangadex_api.auth_url.as_str())
+            .form(&[
+                ("grant_type", "password"),
+                ("username", credentials.username.as_str()),
+                ("password", password.as_str()),
+                ("client_id", credentials.client_id.as_str()),
+                ("client_secret", client_secret.as_str()),
+            ]);
+        let token = client
+            .send(request)
+            .await?
+            .error_for_status()?
+            .json::<TokenResponse>()?;
+
+        Ok(token.access_token)
+    }
+
+    pub async fn fetch_follows(&self, user: &User) -> Result<MangaLatest> {
+        let credentials = user.mangadex.as_ref().ok_or(CustomError::boxed(&format!(
+            "No MangaDex credentials for user {}.",
+            user.id
+        )))?;
+
+        let filters = self
+            .config
+            .mangadex_api
+            .filters()
+            .with_override(user.mangadex_filters.as_ref());
+        let languages = &filters.translated_language;
+        let filters_key = filters.key();
+
+        let client = HttpClient::new(self.config, "mangadex_api");
+        let access_token = self.fetch_access_token(&client, credentials).await?;
 
-        if results.result != "ok" {
+        let mut chapters: HashMap<String, (MangaListEntry, MangaDexLatest)> = HashMap::new();
+        let mut offset = 0;
+        loop {
+            let mut query: Vec<(&str, String)> = languages
+                .iter()
+                .map(|language| ("translatedLanguage[]", language.to_owned()))
+                .chain(
+                    filters
+                        .excluded_groups
+                        .iter()
+                        .map(|group| ("excludedGroups[]", group.to_owned())),
+                )
+                .collect();
+            query.extend([
+                ("includes[]", "manga".to_owned()),
+                ("order[publishAt]", "desc".to_owned()),
+                ("limit", "100".to_owned()),
+                ("offset", offset.to_string()),
+            ]);
+
+            let request = client
+                .get(self.config.mangadex_api.follows_feed_url.as_str())
+                .bearer_auth(&access_token)
+                .query(&query);
+            let feed = client.send(request).await?.json::<ChapterFeed>()?;
+
+            if feed.result != "ok" {
+                return Err(CustomError::boxed("Could not fetch follows feed."));
+            }
+
+            let page_size = feed.data.len();
+            for chapter in feed.data {
+                if !filters.allows(&group_ids(&chapter.relationships)) {
+                    continue;
+                }
+                // Oneshots and other chapterless uploads are listed under "none" in
+                // the aggregate, so number them the same way here.
+                let number = ReleaseNumber::parse(
+                    chapter.attributes.chapter.as_deref().unwrap_or("none"),
+                    chapter.attributes.volume,
+                );
+                let language = chapter.attributes.translated_language.unwrap_or_default();
+                let entry = match chapter
+                    .relationships
+                    .into_iter()
+                    .find_map(Self::parse_manga)
+                {
+                    Some(entry) => entry,
+                    None => continue,
+                };
+
+                let latest = MangaDexLatest::new(
+                    &filters_key,
+                    &language,
+                    Latest {
+                        title: entry.title.to_owned(),
+                        number,
+                        url: format!("https://mangadex.org/chapter/{}", chapter.id),
+                        source: "mangadex_api".to_owned(),
+                        released_at: Some(chapter.attributes.publish_at.to_offset(UtcOffset::UTC)),
+                        links: None,
+                    },
+                );
+                match chapters.get_mut(&entry.id) {
+                    Some((_, current)) => current.merge(latest, languages),
+                    None => {
+                        chapters.insert(entry.id.to_owned(), (entry, latest));
+                    }
+                }
+            }
+
+            offset += page_size;
+            if page_size == 0
+                || offset >= feed.total
+                || offset >= self.config.mangadex_api.feed_limit
+            {
+                break;
+            }
+
+            client
+                .throttle(Duration::from_millis(
+                    1000 / self.config.mangadex_api.rate_limit.max(1) as u64,
+                ))
+                .await;
+        }
+
+        let mut manga_latest = MangaLatest::default();
+        for (entry, latest) in chapters.into_values() {
+            manga_latest.insert_entry(entry, latest, languages);
+        }
+
+        Ok(manga_latest)
+    }
+
+    async fn fetch_list(
+        &self,
+        client: &HttpClient,
+        report: &mut RunReport,
+    ) -> Result<Vec<MangaListEntry>> {
+        let list = client
+            .send(client.get(self.config.mangadex_api.url.as_str()))
+            .await?
+            .json::<MangaList>()?;
+
+        if list.result != "ok" {
             return Err(CustomError::boxed("Could not fetch manga list."));
         }
 
-        for rel in results.data.relationships {
-            if rel.r#type == "manga" {
-                if let Some(attributes) = rel.attributes {
-                    let id = rel.id;
-
-                    let title = if let Some(ro_title) = attributes.title.get("ja-ro") {
-                        ro_title.to_owned()
-                    } else if let Some(en_title) = attributes.title.get("en") {
-                        en_title.to_owned()
-                    } else {
-                        String::new()
-                    };
-
-                    if !title.is_empty() {
-                        if current_batch.len() < self.config.mangadex_api.rate_limit {
-                            current_batch.push((title.to_owned(), id.to_owned()));
-                        } else {
-                            batches.push(current_batch);
-                            current_batch = vec![(title.to_owned(), id.to_owned())];
-                        }
+        let ids: Vec<String> = list
+            .data
+            .relationships
+            .into_iter()
+            .filter(|rel| rel.r#type == "manga")
+            .map(|rel| rel.id)
+            .collect();
+
+        let mut entries = Vec::new();
+        for (index, batch) in ids.chunks(MANGA_BATCH_SIZE).enumerate() {
+            match self.fetch_manga(client, batch).await {
+                Ok(manga) => {
+                    if manga.len() < batch.len() {
+                        report.add(
+                            "mangadex_api",
+                            &format!(
+                                "Manga batch {} was truncated: received {} of {} manga.",
+                                index + 1,
+                                manga.len(),
+                                batch.len()
+                            ),
+                        );
                     }
+                    entries.extend(manga.into_iter().filter_map(Self::parse_manga));
+                }
+                Err(err) => report.add(
+                    "mangadex_api",
+                    &format!("Could not fetch manga batch {}: {}", index + 1, err),
+                ),
+            }
+
+            client
+                .throttle(Duration::from_millis(
+                    1000 / self.config.mangadex_api.rate_limit.max(1) as u64,
+                ))
+                .await;
+        }
+
+        Ok(entries)
+    }
+
+    async fn fetch_manga(
+        &self,
+        client: &HttpClient,
+        ids: &[String],
+    ) -> Result<Vec<MangaListRelationship>> {
+        let mut manga = Vec::new();
+        loop {
+            let mut query: Vec<(&str, String)> =
+                ids.iter().map(|id| ("ids[]", id.to_owned())).collect();
+            query.extend(
+                ["safe", "suggestive", "erotica", "pornographic"]
+                    .into_iter()
+                    .map(|rating| ("contentRating[]", rating.to_owned())),
+            );
+            query.extend([
+                ("limit", MANGA_BATCH_SIZE.to_string()),
+                ("offset", manga.len().to_string()),
+            ]);
+
+            let request = client
+                .get(self.config.mangadex_api.manga_url.as_str())
+                .query(&query);
+            let page = client
+                .send(request)
+                .await?
+                .error_for_status()?
+                .json::<MangaPage>()?;
+
+            if page.result != "ok" {
+                return Err(CustomError::boxed("Could not fetch manga."));
+            }
+
+            let page_size = page.data.len();
+            manga.extend(page.data);
+            if page_size == 0 || manga.len() >= page.total {
+                break;
+            }
+        }
+
+        Ok(manga)
+    }
+
+    fn filter_sets(&self, users: &[User]) -> Vec<MangaDexFilters> {
+        let filters = self.config.mangadex_api.filters();
+
+        let mut filter_sets = Vec::new();
+        if users.iter().any(|user| user.mangadex_filters.is_none()) || users.is_empty() {
+            filter_sets.push(filters.clone());
+        }
+        for user in users {
+            if user.mangadex_filters.is_some() {
+                let user_filters = filters.with_override(user.mangadex_filters.as_ref());
+                if !filter_sets.contains(&user_filters) {
+                    filter_sets.push(user_filters);
+                }
+            }
+        }
+
+        filter_sets
+    }
+
+    fn languages(filter_sets: &[MangaDexFilters]) -> Vec<String> {
+        let mut languages: Vec<String> = Vec::new();
+        for language in filter_sets
+            .iter()
+            .flat_map(|filters| &filters.translated_language)
+        {
+            if !languages.contains(language) {
+                languages.push(language.to_owned());
+            }
+        }
+
+        languages
+    }
+
+    pub async fn fetch(
+        &self,
+        filter_sets: &[MangaDexFilters],
+        report: &mut RunReport,
+    ) -> Result<MangaLatest> {
+        let client = HttpClient::new(self.config, "mangadex_api");
+        let entries = self.fetch_list(&client, report).await?;
+
+        let languages = Self::languages(filter_sets);
+        let mut manga_latest = MangaLatest::default();
+        for filters in filter_sets {
+            let latest = self
+                .fetch_latest(&client, &entries, filters, report)
+                .await?;
+            manga_latest.merge(latest, &languages);
+        }
+
+        Ok(manga_latest)
+    }
+
+    async fn fetch_latest(
+        &self,
+        client: &HttpClient,
+        entries: &[MangaListEntry],
+        filters: &MangaDexFilters,
+        report: &mut RunReport,
+    ) -> Result<MangaLatest> {
+        let languages = &filters.translated_language;
+        let filters_key = filters.key();
+        let depth = if filters.has_group_filters() {
+            FALLBACK_CHAPTERS
+        } else {
+            1
+        };
+        let mut chapters: Vec<(MangaListEntry, String, Vec<ChapterCandidates>)> = Vec::new();
+        let mut batches: Vec<Vec<(MangaListEntry, String)>> = Vec::new();
+        let mut current_batch: Vec<(MangaListEntry, String)> = Vec::new();
+
+        for entry in entries {
+            for language in languages {
+                let job = (entry.clone(), language.to_owned());
+                if current_batch.len() < self.config.mangadex_api.rate_limit {
+                    current_batch.push(job);
+                } else {
+                    batches.push(current_batch);
+                    current_batch = vec![job];
                 }
             }
         }
@@ -114,63 +672,197 @@ impl MangaDexAPI<'_> {
         for batch in batches {
             let mut futures = JoinSet::new();
 
-            for (title, id) in batch {
-                let url = self.config.mangadex_api.manga_agg_url.replace("{id}", &id);
-                let builder = client.get(url);
-                let future = || async {
-                    let res = builder.send().await;
-                    (title, res)
+            for (entry, language) in batch {
+                let url = self
+                    .config
+                    .mangadex_api
+                    .manga_agg_url
+                    .replace("{id}", &entry.id);
+                let query: Vec<(&str, &str)> = [("translatedLanguage[]", language.as_str())]
+                    .into_iter()
+                    .chain(
+                        filters
+                            .included_groups
+                            .iter()
+                            .map(|group| ("groups[]", group.as_str())),
+                    )
+                    .collect();
+                let builder = client.get(url).query(&query);
+                let client = client.clone();
+                let future = || async move {
+                    let res = client.send(builder).await;
+                    (entry, language, res)
                 };
                 futures.spawn(async move { future().await });
             }
 
             while let Some(future) = futures.join_next().await {
-                let (title, res) = future?;
-                let manga_agg = res?.json::<MangaAggregate>().await?;
+                let (entry, language, res) = future?;
+                let manga_agg = match res {
+                    Ok(res) => res.json::<MangaAggregate>(),
+                    Err(err) => Err(err),
+                };
+                let manga_agg = match manga_agg {
+                    Ok(manga_agg) => manga_agg,
+                    Err(err) => {
+                        report.add(
+                            "mangadex_api",
+                            &format!(
+                                "Could not fetch {} chapters for {}: {}",
+                                language, entry.title, err
+                            ),
+                        );
+                        continue;
+                    }
+                };
 
-                let mut latest: (u64, String) = (0, String::new());
+                let mut numbered: Vec<(ReleaseNumber, MangaAggregateChapter)> = Vec::new();
                 if manga_agg.result == "ok" {
-                    for volume in manga_agg.volumes.iter() {
-                        let chapters = &volume.1.chapters;
-
-                        for manga_chapter in chapters {
-                            let chapter = manga_chapter.0.parse::<f64>();
-                            match chapter {
-                                Ok(chapter) => {
-                                    let chapter = chapter as u64;
-                                    if chapter >= latest.0 {
-                                        latest = (
-                                            chapter,
-                                            format!(
-                                                "https://mangadex.org/chapter/{}",
-                                                manga_chapter.1.id.to_owned()
-                                            ),
-                                        );
-                                    }
-                                }
-                                Err(err) => {
-                                    eprintln!("Could not parse chapter: {}", err);
-                                }
-                            }
+                    for (volume, manga_volume) in manga_agg.volumes {
+                        let volume = if volume == "none" { None } else { Some(volume) };
+
+                        for (chapter, manga_chapter) in manga_volume.chapters {
+                            numbered.push((
+                                ReleaseNumber::parse(&chapter, volume.clone()),
+                                manga_chapter,
+                            ));
                         }
                     }
                 }
+                numbered.sort_by(|a, b| b.0.cmp(&a.0));
+                numbered.truncate(depth);
 
-                manga_latest.insert(
-                    title.to_owned(),
-                    Latest {
-                        title: title.to_owned(),
-                        episode: latest.0,
-                        url: latest.1,
-                    },
-                );
+                if numbered.is_empty() {
+                    eprintln!("Could not find {} chapters for {}.", language, entry.title);
+                    continue;
+                }
+                let candidates = numbered
+                    .into_iter()
+                    .map(|(number, chapter)| {
+                        let mut ids = vec![chapter.id];
+                        ids.extend(chapter.others);
+                        (number, ids)
+                    })
+                    .collect();
+                chapters.push((entry, language, candidates));
             }
 
             // Rate limited to 5 requests per second
-            sleep(Duration::from_secs(1)).await;
+            client.throttle(Duration::from_secs(1)).await;
+        }
+
+        let chapter_ids: Vec<&String> = chapters
+            .iter()
+            .flat_map(|(_, _, candidates)| candidates)
+            .flat_map(|(_, ids)| ids)
+            .collect();
+        let chapter_details = match self.fetch_chapters(client, &chapter_ids).await {
+            Ok(chapter_details) => Some(chapter_details),
+            Err(err) => {
+                report.add(
+                    "mangadex_api",
+                    &format!("Could not fetch chapter details: {}", err),
+                );
+                None
+            }
+        };
+
+        let mut entries: HashMap<String, (MangaListEntry, MangaDexLatest)> = HashMap::new();
+        for (entry, language, candidates) in chapters {
+            let chapter = match &chapter_details {
+                Some(chapter_details) => candidates.iter().find_map(|(number, ids)| {
+                    ids.iter().find_map(|id| {
+                        chapter_details
+                            .get(id)
+                            .filter(|(_, groups)| filters.allows(&groups_as_str(groups)))
+                            .map(|(publish_at, _)| (number, id, Some(*publish_at)))
+                    })
+                }),
+                // Without chapter details the groups are unknown, so only an unfiltered
+                // newest chapter can be used.
+                None if !filters.has_group_filters() => candidates
+                    .first()
+                    .map(|(number, ids)| (number, &ids[0], None)),
+                None => continue,
+            };
+            let (number, chapter_id, released_at) = match chapter {
+                Some(chapter) => chapter,
+                None => {
+                    eprintln!(
+                        "Could not find {} chapters for {} from allowed groups.",
+                        language, entry.title
+                    );
+                    continue;
+                }
+            };
+
+            let latest = MangaDexLatest::new(
+                &filters_key,
+                &language,
+                Latest {
+                    title: entry.title.to_owned(),
+                    number: number.clone(),
+                    url: format!("https://mangadex.org/chapter/{}", chapter_id),
+                    source: "mangadex_api".to_owned(),
+                    released_at,
+                    links: None,
+                },
+            );
+            match entries.get_mut(&entry.id) {
+                Some((_, current)) => current.merge(latest, languages),
+                None => {
+                    entries.insert(entry.id.to_owned(), (entry, latest));
+                }
+            }
+        }
+
+        let mut manga_latest = MangaLatest::default();
+        for (entry, latest) in entries.into_values() {
+            manga_latest.insert_entry(entry, latest, languages);
+        }
+
+        Ok(manga_latest)
+    }
+
+    async fn fetch_chapters(
+        &self,
+        client: &HttpClient,
+        chapter_ids: &[&String],
+    ) -> Result<HashMap<String, (OffsetDateTime, Vec<String>)>> {
+        let mut chapters = HashMap::new();
+
+        for batch in chapter_ids.chunks(100) {
+            let mut query: Vec<(&str, &str)> =
+                batch.iter().map(|id| ("ids[]", id.as_str())).collect();
+            query.push(("limit", "100"));
+
+            let request = client
+                .get(self.config.mangadex_api.chapter_url.as_str())
+                .query(&query);
+            let chapter_list = client.send(request).await?.json::<ChapterList>()?;
+
+            if chapter_list.result != "ok" {
+                return Err(CustomError::boxed("Could not fetch chapters."));
+            }
+
+            for chapter in chapter_list.data {
+                let groups = group_ids(&chapter.relationships)
+                    .into_iter()
+                    .map(|group| group.to_owned())
+                    .collect();
+                chapters.insert(
+                    chapter.id,
+                    (
+                        chapter.attributes.publish_at.to_offset(UtcOffset::UTC),
+                        groups,
+                    ),
+                );
+            }
+
+            client.throttle(Duration::from_secs(1)).await;
         }
 
-        Ok(MangaLatest(manga_latest))
+        Ok(chapters)
     }
 }
 
@@ -178,22 +870,71 @@ impl MangaDexAPI<'_> {
 impl Extract<'_> for MangaDexAPI<'_> {
     type Data = MangaLatest;
 
-    async fn extract(&self, _options: Option<ExtractOptions>) -> Result<Self::Data> {
-        let manga_latest = self.fetch().await?;
+    async fn extract(&self, options: Option<ExtractOptions>) -> Result<Self::Data> {
+        let users = match options
+            .as_ref()
+            .and_then(|options| options.mongodb_client.clone())
+        {
+            Some(mongodb_client) => {
+                AniListAPI::new(self.config)
+                    .fetch_users(mongodb_client)
+                    .await?
+            }
+            None => Vec::new(),
+        };
+
+        let filter_sets = self.filter_sets(&users);
+        let languages = Self::languages(&filter_sets);
+
+        let mut report = RunReport::default();
+        let mut manga_latest = if self.config.mangadex_api.url.is_empty() {
+            MangaLatest::default()
+        } else {
+            self.fetch(&filter_sets, &mut report).await?
+        };
+
+        for user in users.iter().filter(|user| user.mangadex.is_some()) {
+            match self.fetch_follows(user).await {
+                Ok(follows) => manga_latest.merge(follows, &languages),
+                Err(err) => report.add(
+                    "mangadex_api",
+                    &format!(
+                        "Could not fetch MangaDex follows for {}: {}",
+                        user.name, err
+                    ),
+                ),
+            }
+        }
+
+        if let Some(options) = &options {
+            options.merge_report(report);
+        }
 
         Ok(manga_latest)
     }
 }
 
 impl Transform for MangaDexAPI<'_> {
-    type Extra = Latest;
+    type Extra = MangaDexLatest;
+
+    fn get_source(&self) -> &str {
+        "mangadex_api"
+    }
 
-    fn set_media(mut media: &mut Media, extra: Option<Self::Extra>) {
-        media.latest = extra;
+    fn set_media(media: &mut Media, extra: Option<Self::Extra>) {
+        if let Some(extra) = extra {
+            media.latest_by_filters = Some(extra.by_filters);
+            media.insert_latest(extra.latest);
+        }
     }
 
-    fn transform(&self, media: &mut Media, extras: &HashMap<String, Self::Extra>) -> Result<Media> {
-        self.match_similar(media, MediaType::Manga, extras)
+    fn transform(
+        &self,
+        media: &mut Media,
+        extras: &HashMap<String, Self::Extra>,
+        options: Option<TransformOptions>,
+    ) -> Result<Media> {
+        self.match_similar(media, MediaType::Manga, extras, options)
     }
 }
 
@@ -201,18 +942,52 @@ impl Similar for MangaDexAPI<'_> {
     fn get_similarity_threshold(&self) -> f64 {
         self.config.transform.similarity_threshold
     }
+
+    fn get_similarity_candidates(&self) -> usize {
+        self.config.transform.similarity_candidates
+    }
+
+    fn get_enriched_statuses(&self) -> &[String] {
+        match &self.config.mangadex_api.enriched_statuses {
+            Some(enriched_statuses) => enriched_statuses,
+            None => &self.config.transform.enriched_statuses,
+        }
+    }
+
+    fn get_mapping_site(&self) -> Option<&str> {
+        Some("mangadex")
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test::helpers::fixture_config;
+    use time::macros::datetime;
+    use wiremock::{
+        matchers::{body_string_contains, header, method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
 
     #[tokio::test]
     async fn test_extract() {
-        let config = Config::default();
+        let config = fixture_config();
         let mangadex_api = MangaDexAPI::new(&config);
-        let latest = mangadex_api.extract(None).await.unwrap();
-        assert!(!latest.0.is_empty());
+        let options = ExtractOptions::default();
+        let latest = mangadex_api.extract(Some(options.clone())).await.unwrap();
+
+        let chapter = &latest.0.get("Chainsaw Man").unwrap().latest;
+        assert_eq!(chapter.number, ReleaseNumber::parse("130", None));
+        assert_eq!(
+            chapter.url,
+            "https://mangadex.org/chapter/c1300000-0000-4000-8000-000000000130"
+        );
+        assert_eq!(
+            chapter.released_at,
+            Some(datetime!(2023-05-16 16:00:00 UTC))
+        );
+        assert!(latest.0.contains_key(&id_key("anilist", "166610")));
+        assert!(options.take_report().is_empty());
     }
 
     #[test]
@@ -230,23 +1005,547 @@ mod tests {
             episodes: None,
             score: None,
             progress: None,
+            updated_at: None,
             latest: None,
+            latest_by_filters: None,
+            latest_by_source: None,
+            latest_sources: None,
+            streams: None,
             schedule: None,
+            next_release_at: None,
             alt_titles: None,
+            match_overrides: None,
+            mappings: None,
         }];
         let latest = HashMap::from([(
             "gintama".to_owned(),
-            Latest {
-                title: "gintama".to_owned(),
-                episode: 1,
-                url: "http://www.test.nyaa".to_owned(),
-            },
+            MangaDexLatest::new(
+                "all",
+                "en",
+                Latest {
+                    title: "gintama".to_owned(),
+                    number: ReleaseNumber::parse("1", None),
+                    url: "http://www.test.nyaa".to_owned(),
+                    source: "test".to_owned(),
+                    released_at: None,
+                    links: None,
+                },
+            ),
         )]);
 
         let config = Config::default();
         let subsplease_rss = MangaDexAPI::new(&config);
 
-        let transformed = subsplease_rss.transform(&mut media[0], &latest).unwrap();
-        assert_eq!(transformed.latest, latest.get("gintama").cloned());
+        let transformed = subsplease_rss
+            .transform(&mut media[0], &latest, None)
+            .unwrap();
+        assert_eq!(
+            transformed.latest,
+            latest.get("gintama").map(|l| l.latest.clone())
+        );
+        assert_eq!(
+            transformed.latest_by_filters,
+            latest.get("gintama").map(|l| l.by_filters.clone())
+        );
+    }
+
+    #[test]
+    fn test_transform_anilist_id() {
+        let mut media = Media {
+            media_id: Some(30044),
+            status: Some("CURRENT".to_owned()),
+            title: Some("Gintama".to_owned()),
+            media_type: Some(MediaType::Manga),
+            ..Default::default()
+        };
+        let latest = HashMap::from([
+            (
+                "Gintama".to_owned(),
+                MangaDexLatest::new(
+                    "all",
+                    "en",
+                    Latest {
+                        title: "Gintama".to_owned(),
+                        number: ReleaseNumber::parse("1", None),
+                        url: "http://www.test.nyaa".to_owned(),
+                        source: "test".to_owned(),
+                        released_at: None,
+                        links: None,
+                    },
+                ),
+            ),
+            (
+                id_key("anilist", "30044"),
+                MangaDexLatest::new(
+                    "all",
+                    "en",
+                    Latest {
+                        title: "Gin Tama".to_owned(),
+                        number: ReleaseNumber::parse("704", None),
+                        url: "http://www.test.nyaa".to_owned(),
+                        source: "test".to_owned(),
+                        released_at: None,
+                        links: None,
+                    },
+                ),
+            ),
+        ]);
+
+        let config = Config::default();
+        let mangadex_api = MangaDexAPI::new(&config);
+
+        let transformed = mangadex_api.transform(&mut media, &latest, None).unwrap();
+        assert_eq!(
+            transformed.latest,
+            latest
+                .get(&id_key("anilist", "30044"))
+                .map(|l| l.latest.clone())
+        );
+    }
+
+    #[test]
+    fn test_merge_languages() {
+        let chapter = |number: &str| Latest {
+            title: "Gintama".to_owned(),
+            number: ReleaseNumber::parse(number, None),
+            url: "http://www.test.nyaa".to_owned(),
+            source: "test".to_owned(),
+            released_at: None,
+            links: None,
+        };
+        let languages = vec!["en".to_owned(), "es-la".to_owned()];
+
+        let mut latest = MangaDexLatest::new("all", "es-la", chapter("12"));
+        latest.merge(MangaDexLatest::new("all", "en", chapter("10")), &languages);
+        latest.merge(MangaDexLatest::new("all", "en", chapter("11")), &languages);
+        latest.merge(
+            MangaDexLatest::new("all", "es-la", chapter("9")),
+            &languages,
+        );
+        assert_eq!(latest.latest, chapter("11"));
+        assert_eq!(latest.by_filters["all"].get("es-la"), Some(&chapter("12")));
+
+        latest.merge(MangaDexLatest::new("all", "fr", chapter("20")), &[]);
+        assert_eq!(latest.latest, chapter("20"));
+        assert_eq!(
+            latest.preferred(&["es-la".to_owned()]),
+            Some(&chapter("12"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_batches() {
+        let server = MockServer::start().await;
+        let ids: Vec<String> = (0..150).map(|id| format!("uuid-{}", id)).collect();
+        let manga = |id: &String| {
+            serde_json::json!({
+                "id": id,
+                "type": "manga",
+                "attributes": { "title": { "en": id } }
+            })
+        };
+        Mock::given(method("GET"))
+            .and(path("/list"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "result": "ok",
+                "data": {
+                    "relationships": ids
+                        .iter()
+                        .map(|id| serde_json::json!({ "id": id, "type": "manga" }))
+                        .chain([serde_json::json!({ "id": "user", "type": "user" })])
+                        .collect::<Vec<_>>()
+                }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/manga"))
+            .and(query_param("ids[]", "uuid-0"))
+            .and(query_param("offset", "0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "result": "ok",
+                "data": ids[..60].iter().map(manga).collect::<Vec<_>>(),
+                "total": 99
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/manga"))
+            .and(query_param("ids[]", "uuid-0"))
+            .and(query_param("offset", "60"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "result": "ok",
+                "data": ids[60..99].iter().map(manga).collect::<Vec<_>>(),
+                "total": 99
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/manga"))
+            .and(query_param("ids[]", "uuid-100"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let mut config = Config::default();
+        config.mangadex_api.url = format!("{}/list", server.uri());
+        config.mangadex_api.manga_url = format!("{}/manga", server.uri());
+
+        let mangadex_api = MangaDexAPI::new(&config);
+        let mut report = RunReport::default();
+        let entries = mangadex_api
+            .fetch_list(&HttpClient::new(&config, "mangadex_api"), &mut report)
+            .await
+            .unwrap();
+
+        assert_eq!(entries.len(), 99);
+        assert_eq!(entries[98].title, "uuid-98");
+        assert_eq!(report.issues.len(), 2);
+        assert_eq!(
+            report.issues[0].message,
+            "Manga batch 1 was truncated: received 99 of 100 manga."
+        );
+        assert!(report.issues[1]
+            .message
+            .starts_with("Could not fetch manga batch 2:"));
+    }
+
+    async fn mock_list(server: &MockServer, chapters: serde_json::Value) -> Config {
+        Mock::given(method("GET"))
+            .and(path("/list"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "result": "ok",
+                "data": { "relationships": [{ "id": "uuid", "type": "manga" }] }
+            })))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/manga"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "result": "ok",
+                "data": [{
+                    "id": "uuid",
+                    "type": "manga",
+                    "attributes": { "title": { "ja-ro": "Gintama" } }
+                }],
+                "total": 1
+            })))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/chapter"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(chapters))
+            .mount(server)
+            .await;
+
+        let mut config = Config::default();
+        config.mangadex_api.url = format!("{}/list", server.uri());
+        config.mangadex_api.manga_url = format!("{}/manga", server.uri());
+        config.mangadex_api.manga_agg_url = format!("{}/manga/{{id}}/aggregate", server.uri());
+        config.mangadex_api.chapter_url = format!("{}/chapter", server.uri());
+        config
+    }
+
+    async fn mock_aggregate(server: &MockServer, language: &str, chapters: &[(&str, &str)]) {
+        let chapters: serde_json::Map<String, serde_json::Value> = chapters
+            .iter()
+            .map(|(number, id)| {
+                (
+                    number.to_string(),
+                    serde_json::json!({ "chapter": number, "id": id, "others": [] }),
+                )
+            })
+            .collect();
+        Mock::given(method("GET"))
+            .and(path("/manga/uuid/aggregate"))
+            .and(query_param("translatedLanguage[]", language))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "result": "ok",
+                "volumes": { "none": { "chapters": chapters } }
+            })))
+            .mount(server)
+            .await;
+    }
+
+    fn chapter_details(chapters: &[(&str, &str)]) -> serde_json::Value {
+        serde_json::json!({
+            "result": "ok",
+            "data": chapters
+                .iter()
+                .map(|(id, group)| serde_json::json!({
+                    "id": id,
+                    "attributes": { "publishAt": "2023-05-20T12:00:00+00:00" },
+                    "relationships": [{ "id": group, "type": "scanlation_group" }]
+                }))
+                .collect::<Vec<_>>()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_fallback_to_allowed_chapter() {
+        let server = MockServer::start().await;
+        let mut config = mock_list(
+            &server,
+            chapter_details(&[("chapter-10", "bad"), ("chapter-9", "good")]),
+        )
+        .await;
+        mock_aggregate(&server, "en", &[("10", "chapter-10"), ("9", "chapter-9")]).await;
+        config.mangadex_api.translated_language = vec!["en".to_owned()];
+        config.mangadex_api.excluded_groups = vec!["bad".to_owned()];
+
+        let mangadex_api = MangaDexAPI::new(&config);
+        let filter_sets = mangadex_api.filter_sets(&[]);
+        let mut report = RunReport::default();
+        let actual = mangadex_api.fetch(&filter_sets, &mut report).await.unwrap();
+
+        let latest = &actual.0.get("Gintama").unwrap().latest;
+        assert_eq!(latest.number, ReleaseNumber::parse("9", None));
+        assert_eq!(latest.url, "https://mangadex.org/chapter/chapter-9");
+        assert!(report.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_chapter_details_failure() {
+        let server = MockServer::start().await;
+        let mut config = mock_list(&server, serde_json::json!({ "result": "error" })).await;
+        mock_aggregate(&server, "en", &[("10", "chapter-10"), ("9", "chapter-9")]).await;
+        config.mangadex_api.translated_language = vec!["en".to_owned()];
+
+        let mangadex_api = MangaDexAPI::new(&config);
+        let mut report = RunReport::default();
+        let actual = mangadex_api
+            .fetch(&mangadex_api.filter_sets(&[]), &mut report)
+            .await
+            .unwrap();
+        let latest = &actual.0.get("Gintama").unwrap().latest;
+        assert_eq!(latest.url, "https://mangadex.org/chapter/chapter-10");
+        assert_eq!(latest.released_at, None);
+
+        config.mangadex_api.excluded_groups = vec!["bad".to_owned()];
+        let mangadex_api = MangaDexAPI::new(&config);
+        let mut report = RunReport::default();
+        let actual = mangadex_api
+            .fetch(&mangadex_api.filter_sets(&[]), &mut report)
+            .await
+            .unwrap();
+        assert!(actual.0.is_empty());
+        assert_eq!(report.issues.len(), 1);
+        assert!(report.issues[0]
+            .message
+            .starts_with("Could not fetch chapter details:"));
+    }
+
+    #[tokio::test]
+    async fn test_user_filters() {
+        let server = MockServer::start().await;
+        let mut config = mock_list(
+            &server,
+            chapter_details(&[
+                ("chapter-10", "fan"),
+                ("chapter-9", "official"),
+                ("chapter-5", "fan"),
+                ("chapter-4", "official"),
+            ]),
+        )
+        .await;
+        mock_aggregate(&server, "en", &[("10", "chapter-10"), ("9", "chapter-9")]).await;
+        mock_aggregate(&server, "es-la", &[("5", "chapter-5"), ("4", "chapter-4")]).await;
+        config.mangadex_api.translated_language = vec!["en".to_owned()];
+
+        let users = [
+            User {
+                id: 1,
+                ..Default::default()
+            },
+            User {
+                id: 2,
+                mangadex_filters: Some(MangaDexFilters {
+                    translated_language: vec!["es-la".to_owned()],
+                    included_groups: vec!["official".to_owned()],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            // Shares "en" with the default filters but only allows official chapters.
+            User {
+                id: 3,
+                mangadex_filters: Some(MangaDexFilters {
+                    included_groups: vec!["official".to_owned()],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ];
+        let mangadex_api = MangaDexAPI::new(&config);
+        let filter_sets = mangadex_api.filter_sets(&users);
+        assert_eq!(filter_sets.len(), 3);
+        assert_eq!(filter_sets[1].included_groups, vec!["official"]);
+        assert_eq!(mangadex_api.filter_sets(&users[1..2]).len(), 1);
+
+        let actual = mangadex_api
+            .fetch(&filter_sets, &mut RunReport::default())
+            .await
+            .unwrap();
+        let latest = actual.0.get("Gintama").unwrap();
+        assert_eq!(latest.latest.url, "https://mangadex.org/chapter/chapter-10");
+        assert_eq!(
+            latest.by_filters["include:official"]
+                .get("es-la")
+                .map(|l| l.url.as_str()),
+            Some("https://mangadex.org/chapter/chapter-4")
+        );
+        assert_eq!(latest.by_filters["all"].get("es-la"), None);
+        assert_eq!(
+            latest.by_filters["all"].get("en").map(|l| l.url.as_str()),
+            Some("https://mangadex.org/chapter/chapter-10")
+        );
+        assert_eq!(
+            latest.by_filters["include:official"]
+                .get("en")
+                .map(|l| l.url.as_str()),
+            Some("https://mangadex.org/chapter/chapter-9")
+        );
+    }
+
+    #[test]
+    fn test_parse_manga() {
+        let rel: MangaListRelationship = serde_json::from_value(serde_json::json!({
+            "id": "uuid",
+            "type": "manga",
+            "attributes": {
+                "title": { "ja-ro": "Gintama" },
+                "altTitles": [
+                    { "en": "Gin Tama" },
+                    { "ja": "銀魂" },
+                    { "ko": "은혼" },
+                    { "ja-ro": "Gintama." }
+                ],
+                "links": { "al": "30044" }
+            }
+        }))
+        .unwrap();
+
+        let entry = MangaDexAPI::parse_manga(rel).unwrap();
+        assert_eq!(entry.title, "Gintama");
+        assert_eq!(entry.alt_titles, vec!["Gin Tama", "Gintama."]);
+        assert_eq!(entry.anilist_id.as_deref(), Some("30044"));
+    }
+
+    #[tokio::test]
+    async fn test_follows_feed() {
+        let server = MockServer::start().await;
+        let chapter = |id: &str, number: &str, language: &str, group: &str| {
+            serde_json::json!({
+                "id": id,
+                "attributes": {
+                    "volume": null,
+                    "chapter": number,
+                    "translatedLanguage": language,
+                    "publishAt": "2023-05-20T12:00:00+00:00"
+                },
+                "relationships": [{
+                    "id": "uuid",
+                    "type": "manga",
+                    "attributes": {
+                        "title": { "ja-ro": "Gintama" },
+                        "altTitles": [{ "en": "Gin Tama" }],
+                        "links": { "al": "30044" }
+                    }
+                }, {
+                    "id": group,
+                    "type": "scanlation_group"
+                }]
+            })
+        };
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_string_contains("grant_type=password"))
+            .and(body_string_contains("password=password"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "access_token": "token" })),
+            )
+            .mount(&server)
+            .await;
+        let mut chapterless = chapter("chapter-6", "", "en", "good");
+        chapterless["attributes"]["chapter"] = serde_json::Value::Null;
+        chapterless["relationships"][0] = serde_json::json!({
+            "id": "uuid-2",
+            "type": "manga",
+            "attributes": { "title": { "en": "Oneshot" }, "links": { "al": "30045" } }
+        });
+        Mock::given(method("GET"))
+            .and(path("/feed"))
+            .and(header("authorization", "Bearer token"))
+            .and(query_param("translatedLanguage[]", "es-la"))
+            .and(query_param("excludedGroups[]", "bad"))
+            .and(query_param("offset", "0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "result": "ok",
+                "data": [chapter("chapter-1", "10", "en", "good")],
+                "total": 150
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/feed"))
+            .and(query_param("offset", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "result": "ok",
+                "data": [
+                    chapter("chapter-2", "11", "en", "good"),
+                    chapter("chapter-3", "Oneshot", "en", "good"),
+                    chapter("chapter-4", "12", "es-la", "good"),
+                    chapter("chapter-5", "13", "en", "bad"),
+                    chapterless
+                ],
+                "total": 6
+            })))
+            .mount(&server)
+            .await;
+
+        let mut config = Config::default();
+        config.mangadex_api.auth_url = format!("{}/token", server.uri());
+        config.mangadex_api.follows_feed_url = format!("{}/feed", server.uri());
+        config.mangadex_api.excluded_groups = vec!["bad".to_owned()];
+        config.encryption_key = Some(base64::Engine::encode(
+            &base64::engine::general_purpose::STANDARD,
+            [1u8; 32],
+        ));
+        let encryption_key = config.encryption_key().unwrap();
+        let user = User {
+            id: 1,
+            name: "test".to_owned(),
+            mangadex: Some(MangaDexCredentials {
+                username: "test".to_owned(),
+                password: crypto::encrypt(encryption_key, "password").unwrap(),
+                client_id: "client".to_owned(),
+                client_secret: crypto::encrypt(encryption_key, "secret").unwrap(),
+            }),
+            mangadex_filters: Some(MangaDexFilters {
+                translated_language: vec!["en".to_owned(), "es-la".to_owned()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let mangadex_api = MangaDexAPI::new(&config);
+        let actual = mangadex_api.fetch_follows(&user).await.unwrap();
+
+        let latest = actual.0.get(&id_key("anilist", "30044")).unwrap();
+        assert_eq!(latest.latest.number, ReleaseNumber::parse("11", None));
+        assert_eq!(latest.latest.url, "https://mangadex.org/chapter/chapter-2");
+        assert_eq!(
+            latest.by_filters["exclude:bad"]
+                .get("es-la")
+                .map(|l| l.url.as_str()),
+            Some("https://mangadex.org/chapter/chapter-4")
+        );
+        assert_eq!(actual.0.get("Gin Tama"), Some(latest));
+        assert_eq!(actual.0.get("Gintama"), Some(latest));
+
+        let latest = actual.0.get(&id_key("anilist", "30045")).unwrap();
+        assert_eq!(latest.latest.number, ReleaseNumber::parse("none", None));
+        assert_eq!(latest.latest.url, "https://mangadex.org/chapter/chapter-6");
     }
 }
@@ -0,0 +1,503 @@
+use crate::anilist_api::{Latest, Media, MediaType};
+use crate::config::Config;
+use crate::db::MongoDB;
+use crate::error::CustomError;
+use crate::http::HttpClient;
+use crate::options::{ExtractOptions, TransformOptions};
+use crate::release::{ReleaseLinks, ReleaseNumber};
+use crate::result::Result;
+use crate::sources::{id_key, Document, Extract, Similar, Transform};
+
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, UtcOffset};
+
+#[derive(Debug, Deserialize)]
+struct ReleaseTime {
+    as_rfc3339: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseRecord {
+    id: u64,
+    title: String,
+    volume: Option<String>,
+    chapter: Option<String>,
+    time_added: Option<ReleaseTime>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseSeries {
+    series_id: u64,
+    title: Option<String>,
+    url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseMetadata {
+    series: Option<ReleaseSeries>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseResult {
+    record: ReleaseRecord,
+    metadata: Option<ReleaseMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleasePage {
+    #[serde(default)]
+    results: Vec<ReleaseResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Hash)]
+pub struct MangaUpdatesLatestEntry {
+    pub key: String,
+    pub latest: Latest,
+}
+
+impl Document for MangaUpdatesLatestEntry {}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct MangaUpdatesLatest(pub HashMap<String, Latest>);
+
+impl MangaUpdatesLatest {
+    fn insert_newer(&mut self, key: String, latest: Latest) -> bool {
+        let newer = match self.0.get(&key) {
+            Some(current) => current.number < latest.number,
+            None => true,
+        };
+        if newer {
+            self.0.insert(key, latest);
+        }
+
+        newer
+    }
+}
+
+fn parse_chapter(chapter: &str, volume: Option<String>) -> Option<ReleaseNumber> {
+    let chapter = chapter.trim();
+    if chapter.is_empty() {
+        return None;
+    }
+
+    match chapter.split_once('-') {
+        Some((first, last)) if !first.trim().is_empty() && !last.trim().is_empty() => {
+            Some(ReleaseNumber {
+                label: chapter.to_owned(),
+                ..ReleaseNumber::parse(last, volume)
+            })
+        }
+        _ => Some(ReleaseNumber::parse(chapter, volume)),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MangaUpdatesAPI<'a> {
+    config: &'a Config,
+}
+
+impl MangaUpdatesAPI<'_> {
+    pub fn new(config: &Config) -> MangaUpdatesAPI<'_> {
+        MangaUpdatesAPI { config }
+    }
+
+    fn parse(&self, results: Vec<ReleaseResult>) -> MangaUpdatesLatest {
+        let mut latest = MangaUpdatesLatest::default();
+        for result in results {
+            let record = result.record;
+            let number = match record
+                .chapter
+                .as_deref()
+                .and_then(|chapter| parse_chapter(chapter, record.volume.clone()))
+            {
+                Some(number) => number,
+                None => continue,
+            };
+            let series = result.metadata.and_then(|metadata| metadata.series);
+            let title = series
+                .as_ref()
+                .and_then(|series| series.title.clone())
+                .unwrap_or(record.title);
+
+            let release = Latest {
+                title: title.clone(),
+                number,
+                url: format!(
+                    "{}/releases/{}",
+                    self.config.mangaupdates_api.url, record.id
+                ),
+                source: "mangaupdates_api".to_owned(),
+                released_at: record
+                    .time_added
+                    .and_then(|time_added| time_added.as_rfc3339)
+                    .and_then(|date| OffsetDateTime::parse(&date, &Rfc3339).ok())
+                    .map(|date| date.to_offset(UtcOffset::UTC)),
+                links: series
+                    .as_ref()
+                    .and_then(|series| series.url.clone())
+                    .map(|page| ReleaseLinks {
+                        page: Some(page),
+                        ..Default::default()
+                    }),
+            };
+
+            if let Some(series) = &series {
+                latest.insert_newer(
+                    id_key("mangaupdates", &series.series_id.to_string()),
+                    release.clone(),
+                );
+            }
+            latest.insert_newer(title, release);
+        }
+
+        latest
+    }
+
+    pub async fn fetch(&self) -> Result<MangaUpdatesLatest> {
+        let client = HttpClient::new(self.config, "mangaupdates_api");
+        let mut results = Vec::new();
+        for page in 1..=self.config.mangaupdates_api.pages {
+            let request = client
+                .get(format!(
+                    "{}/releases/days",
+                    self.config.mangaupdates_api.url
+                ))
+                .query(&[
+                    ("include_metadata", "true".to_owned()),
+                    ("page", page.to_string()),
+                ]);
+            let mut releases = client
+                .send(request)
+                .await?
+                .error_for_status()?
+                .json::<ReleasePage>()?;
+            if releases.results.is_empty() {
+                break;
+            }
+            results.append(&mut releases.results);
+        }
+
+        Ok(self.parse(results))
+    }
+
+    async fn fetch_stored(&self, mongodb_client: mongodb::Client) -> Result<MangaUpdatesLatest> {
+        let entries: Vec<MangaUpdatesLatestEntry> = mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection("mangaupdates_latest")
+            .find(None, None)
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(MangaUpdatesLatest(
+            entries
+                .into_iter()
+                .map(|entry| (entry.key, entry.latest))
+                .collect(),
+        ))
+    }
+}
+
+#[async_trait]
+impl Extract<'_> for MangaUpdatesAPI<'_> {
+    type Data = MangaUpdatesLatest;
+
+    // Only the last days of releases are paged, so the latest chapters are
+    // stored to keep series that had no release in that window.
+    async fn extract(&self, options: Option<ExtractOptions>) -> Result<Self::Data> {
+        let options = options.ok_or(CustomError::boxed("No options provided."))?;
+        let mongodb_client = options
+            .mongodb_client
+            .clone()
+            .ok_or(CustomError::boxed("No mongodb client provided."))?;
+
+        let mut latest = self.fetch_stored(mongodb_client.clone()).await?;
+        let fetched = match self.fetch().await {
+            Ok(fetched) => fetched,
+            Err(err) => {
+                options.add_issue(
+                    self.get_source(),
+                    &format!("Could not fetch MangaUpdates releases: {}", err),
+                );
+                return Ok(latest);
+            }
+        };
+
+        let mut entries = Vec::new();
+        for (key, release) in fetched.0 {
+            if latest.insert_newer(key.clone(), release.clone()) {
+                entries.push(MangaUpdatesLatestEntry {
+                    key,
+                    latest: release,
+                });
+            }
+        }
+        MongoDB {
+            client: mongodb_client,
+            config: self.config,
+        }
+        .upsert_documents("mangaupdates_latest", &entries, "key")
+        .await?;
+
+        Ok(latest)
+    }
+}
+
+impl Transform for MangaUpdatesAPI<'_> {
+    type Extra = Latest;
+
+    fn get_source(&self) -> &str {
+        "mangaupdates_api"
+    }
+
+    fn set_media(media: &mut Media, extra: Option<Self::Extra>) {
+        if let Some(extra) = extra {
+            media.insert_latest(extra);
+        }
+    }
+
+    fn transform(
+        &self,
+        media: &mut Media,
+        extras: &HashMap<String, Self::Extra>,
+        options: Option<TransformOptions>,
+    ) -> Result<Media> {
+        self.match_similar(media, MediaType::Manga, extras, options)
+    }
+}
+
+impl Similar for MangaUpdatesAPI<'_> {
+    fn get_similarity_threshold(&self) -> f64 {
+        self.config.transform.similarity_threshold
+    }
+
+    fn get_similarity_candidates(&self) -> usize {
+        self.config.transform.similarity_candidates
+    }
+
+    fn get_mapping_site(&self) -> Option<&str> {
+        Some("mangaupdates")
+    }
+
+    fn get_enriched_statuses(&self) -> &[String] {
+        match &self.config.mangaupdates_api.enriched_statuses {
+            Some(enriched_statuses) => enriched_statuses,
+            None => &self.config.transform.enriched_statuses,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alt_titles_db::AltTitlesEntry;
+    use crate::test::helpers::{init, reset_db, ONCE};
+    use time::macros::datetime;
+    use wiremock::{
+        matchers::{method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn release(id: u64, series_id: u64, title: &str, chapter: &str) -> serde_json::Value {
+        serde_json::json!({
+            "record": {
+                "id": id,
+                "title": title,
+                "volume": null,
+                "chapter": chapter,
+                "groups": [{ "name": "Official", "group_id": 1 }],
+                "release_date": "2023-05-17",
+                "time_added": {
+                    "timestamp": 1684339270,
+                    "as_rfc3339": "2023-05-17T16:01:10+00:00",
+                    "as_string": "May 17th 2023, 4:01pm UTC"
+                }
+            },
+            "metadata": {
+                "series": {
+                    "series_id": series_id,
+                    "title": title,
+                    "url": format!("https://www.mangaupdates.com/series/{}", series_id)
+                }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_releases() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/releases/days"))
+            .and(query_param("page", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "total_hits": 3,
+                "page": 1,
+                "per_page": 3,
+                "results": [
+                    release(1, 100, "Chainsaw Man", "130"),
+                    release(2, 100, "Chainsaw Man", "128-129"),
+                    release(3, 200, "Kagurabachi", "")
+                ]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/releases/days"))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "results": []
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut config = Config::default();
+        config.mangaupdates_api.url = server.uri();
+        let latest = MangaUpdatesAPI::new(&config).fetch().await.unwrap();
+
+        assert_eq!(latest.0.len(), 2);
+        let chapter = latest.0.get("Chainsaw Man").unwrap();
+        assert_eq!(chapter.number, ReleaseNumber::parse("130", None));
+        assert_eq!(chapter.url, format!("{}/releases/1", server.uri()));
+        assert_eq!(
+            chapter.released_at,
+            Some(datetime!(2023-05-17 16:01:10 UTC))
+        );
+        assert_eq!(
+            chapter
+                .links
+                .as_ref()
+                .and_then(|links| links.page.as_deref()),
+            Some("https://www.mangaupdates.com/series/100")
+        );
+        assert_eq!(latest.0.get(&id_key("mangaupdates", "100")), Some(chapter));
+    }
+
+    #[tokio::test]
+    async fn test_extract() {
+        ONCE.get_or_init(init).await;
+        reset_db().await;
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/releases/days"))
+            .and(query_param("page", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "results": [release(1, 100, "Chainsaw Man", "130")]
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/releases/days"))
+            .and(query_param("page", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "results": [release(2, 200, "Kagurabachi", "12")]
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/releases/days"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "results": []
+            })))
+            .mount(&server)
+            .await;
+
+        let mut config = Config::default();
+        config.mangaupdates_api.url = server.uri();
+        let mongodb = MongoDB::init(&config).await;
+        let options = ExtractOptions {
+            mongodb_client: Some(mongodb.client.clone()),
+            ..Default::default()
+        };
+        let mangaupdates_api = MangaUpdatesAPI::new(&config);
+
+        let first = mangaupdates_api
+            .extract(Some(options.clone()))
+            .await
+            .unwrap();
+        assert!(first.0.contains_key("Chainsaw Man"));
+        assert!(!first.0.contains_key("Kagurabachi"));
+
+        let second = mangaupdates_api.extract(Some(options)).await.unwrap();
+        assert_eq!(
+            second.0.get("Chainsaw Man").map(|latest| &latest.number),
+            Some(&ReleaseNumber::parse("130", None))
+        );
+        assert!(second.0.contains_key("Kagurabachi"));
+        assert!(second.0.contains_key(&id_key("mangaupdates", "100")));
+    }
+
+    #[test]
+    fn test_parse_chapter() {
+        let number = parse_chapter("128-129", None).unwrap();
+        assert_eq!(number.label, "128-129");
+        assert_eq!(number.value, Some(129.0));
+        assert_eq!(parse_chapter(" ", None), None);
+    }
+
+    #[test]
+    fn test_match_similar() {
+        let config = Config::default();
+        let mangaupdates_api = MangaUpdatesAPI::new(&config);
+        let latest = |number: &str| Latest {
+            title: "Chainsaw Man".to_owned(),
+            number: ReleaseNumber::parse(number, None),
+            url: format!("http://localhost/releases/{}", number),
+            source: "mangaupdates_api".to_owned(),
+            released_at: None,
+            links: None,
+        };
+        let extras = HashMap::from([("Chainsaw Man".to_owned(), latest("130"))]);
+        let media = || Media {
+            media_id: Some(105778),
+            status: Some("CURRENT".to_owned()),
+            title: Some("Chainsaw Man".to_owned()),
+            media_type: Some(MediaType::Manga),
+            alt_titles: Some(AltTitlesEntry::default()),
+            ..Default::default()
+        };
+
+        let actual = mangaupdates_api
+            .transform(&mut media(), &extras, None)
+            .unwrap();
+        assert_eq!(actual.latest, Some(latest("130")));
+
+        let newer = Latest {
+            source: "mangadex_api".to_owned(),
+            ..latest("131")
+        };
+        let actual = mangaupdates_api
+            .transform(
+                &mut Media {
+                    latest: Some(newer.clone()),
+                    ..media()
+                },
+                &extras,
+                None,
+            )
+            .unwrap();
+        assert_eq!(actual.latest, Some(newer));
+
+        let older = Latest {
+            source: "mangadex_api".to_owned(),
+            ..latest("129")
+        };
+        let actual = mangaupdates_api
+            .transform(
+                &mut Media {
+                    latest: Some(older),
+                    ..media()
+                },
+                &extras,
+                None,
+            )
+            .unwrap();
+        assert_eq!(actual.latest, Some(latest("130")));
+    }
+}
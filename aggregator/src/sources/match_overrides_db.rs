@@ -0,0 +1,338 @@
+use crate::anilist_api::Media;
+use crate::config::Config;
+use crate::error::CustomError;
+use crate::options::TransformOptions;
+use crate::result::Result;
+use crate::sources::Document;
+use crate::sources::{Extract, ExtractOptions, Transform};
+
+use async_trait::async_trait;
+use bson::doc;
+use futures::StreamExt;
+use mongodb::options::FindOneAndUpdateOptions;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize, Hash)]
+pub struct MatchOverride {
+    pub source: String,
+    pub media_id: u64,
+    pub key: Option<String>,
+}
+
+impl Document for MatchOverride {}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize, Hash)]
+pub struct MatchOverridesEntry {
+    pub media_id: u64,
+    pub overrides: BTreeMap<String, Option<String>>,
+}
+
+impl MatchOverridesEntry {
+    pub fn get(&self, source: &str) -> Option<&Option<String>> {
+        self.overrides.get(source)
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct MatchOverrides(pub HashMap<String, MatchOverridesEntry>);
+
+pub struct MatchOverridesDB<'a> {
+    config: &'a Config,
+}
+
+impl MatchOverridesDB<'_> {
+    pub fn new(config: &Config) -> MatchOverridesDB<'_> {
+        MatchOverridesDB { config }
+    }
+
+    fn collection(&self, mongodb_client: &mongodb::Client) -> mongodb::Collection<MatchOverride> {
+        mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<MatchOverride>("match_overrides")
+    }
+
+    pub async fn add(
+        &self,
+        mongodb_client: &mongodb::Client,
+        match_override: &MatchOverride,
+    ) -> Result<()> {
+        let filter = doc! {
+            "source": &match_override.source,
+            "media_id": match_override.media_id as i64,
+        };
+        let update = doc! { "$set": { "key": &match_override.key } };
+
+        self.collection(mongodb_client)
+            .find_one_and_update(
+                filter,
+                update,
+                FindOneAndUpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove(
+        &self,
+        mongodb_client: &mongodb::Client,
+        source: &str,
+        media_id: u64,
+    ) -> Result<()> {
+        let filter = doc! { "source": source, "media_id": media_id as i64 };
+        let result = self
+            .collection(mongodb_client)
+            .delete_one(filter, None)
+            .await?;
+
+        if result.deleted_count == 0 {
+            return Err(CustomError::boxed(&format!(
+                "Could not find match override for {} in {}.",
+                media_id, source
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Extract<'_> for MatchOverridesDB<'_> {
+    type Data = MatchOverrides;
+
+    async fn extract(&self, options: Option<ExtractOptions>) -> Result<Self::Data> {
+        let mongodb_client = match options {
+            Some(options) => match options.mongodb_client {
+                Some(mongodb_client) => mongodb_client,
+                None => return Err(CustomError::boxed("No mongodb client provided.")),
+            },
+            None => return Err(CustomError::boxed("No options provided.")),
+        };
+
+        let mut cursor = self.collection(&mongodb_client).find(None, None).await?;
+        let mut match_overrides = MatchOverrides(HashMap::new());
+
+        while let Some(item) = cursor.next().await {
+            match item {
+                Ok(match_override) => {
+                    let entry = match_overrides
+                        .0
+                        .entry(match_override.media_id.to_string())
+                        .or_insert_with(|| MatchOverridesEntry {
+                            media_id: match_override.media_id,
+                            overrides: BTreeMap::new(),
+                        });
+                    entry
+                        .overrides
+                        .insert(match_override.source, match_override.key);
+                }
+                Err(err) => {
+                    eprintln!("Could not get match override: {}", err);
+                }
+            }
+        }
+
+        Ok(match_overrides)
+    }
+}
+
+impl Transform for MatchOverridesDB<'_> {
+    type Extra = MatchOverridesEntry;
+
+    fn get_source(&self) -> &str {
+        "match_overrides_db"
+    }
+
+    fn set_media(media: &mut Media, extra: Option<Self::Extra>) {
+        media.match_overrides = extra;
+    }
+
+    fn transform(
+        &self,
+        media: &mut Media,
+        extras: &HashMap<String, Self::Extra>,
+        _options: Option<TransformOptions>,
+    ) -> Result<Media> {
+        let media_id = match media.media_id {
+            Some(media_id) => media_id.to_string(),
+            None => return Ok(std::mem::take(media)),
+        };
+
+        if extras.contains_key(&media_id) {
+            Self::set_media(media, extras.get(&media_id).cloned());
+        }
+
+        Ok(std::mem::take(media))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anilist_api::{Latest, MediaType};
+    use crate::config::Config;
+    use crate::db::MongoDB;
+    use crate::release::ReleaseNumber;
+    use crate::subsplease_rss::SubsPleaseRSS;
+    use crate::test::helpers::{init, reset_db, ONCE};
+
+    #[tokio::test]
+    async fn test_extract() {
+        ONCE.get_or_init(init).await;
+        reset_db().await;
+
+        let config = Config::default();
+        let mongodb = MongoDB::new(&config).await;
+        let match_overrides_db = MatchOverridesDB::new(&config);
+
+        match_overrides_db
+            .add(
+                &mongodb.client,
+                &MatchOverride {
+                    source: "subsplease_rss".to_owned(),
+                    media_id: 1,
+                    key: Some("Gintama".to_owned()),
+                },
+            )
+            .await
+            .unwrap();
+        match_overrides_db
+            .add(
+                &mongodb.client,
+                &MatchOverride {
+                    source: "subsplease_scraper".to_owned(),
+                    media_id: 1,
+                    key: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let options = ExtractOptions {
+            mongodb_client: Some(mongodb.client.clone()),
+            ..Default::default()
+        };
+        let actual = match_overrides_db.extract(Some(options)).await.unwrap();
+
+        assert_eq!(actual.0.len(), 1);
+        let entry = actual.0.get("1").unwrap();
+        assert_eq!(
+            entry.get("subsplease_rss"),
+            Some(&Some("Gintama".to_owned()))
+        );
+        assert_eq!(entry.get("subsplease_scraper"), Some(&None));
+
+        match_overrides_db
+            .remove(&mongodb.client, "subsplease_scraper", 1)
+            .await
+            .unwrap();
+        assert!(match_overrides_db
+            .remove(&mongodb.client, "subsplease_scraper", 1)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_transform() {
+        let mut media = Media {
+            media_id: Some(1),
+            status: Some("CURRENT".to_owned()),
+            title: Some("Gintama".to_owned()),
+            english_title: Some("Gin Tama".to_owned()),
+            media_type: Some(MediaType::Anime),
+            format: None,
+            season: None,
+            season_year: None,
+            image: None,
+            episodes: None,
+            score: None,
+            progress: None,
+            updated_at: None,
+            latest: None,
+            latest_by_filters: None,
+            latest_by_source: None,
+            latest_sources: None,
+            streams: None,
+            schedule: None,
+            next_release_at: None,
+            alt_titles: None,
+            match_overrides: None,
+            mappings: None,
+        };
+        let match_overrides = HashMap::from([(
+            1.to_string(),
+            MatchOverridesEntry {
+                media_id: 1,
+                overrides: BTreeMap::from([("subsplease_rss".to_owned(), None)]),
+            },
+        )]);
+
+        let config = Config::default();
+        let match_overrides_db = MatchOverridesDB::new(&config);
+
+        let transformed = match_overrides_db
+            .transform(&mut media, &match_overrides, None)
+            .unwrap();
+        assert_eq!(
+            transformed.match_overrides,
+            match_overrides.get("1").cloned()
+        );
+    }
+
+    #[test]
+    fn test_match_similar() {
+        let config = Config::default();
+        let subsplease_rss = SubsPleaseRSS::new(&config);
+
+        let latest = HashMap::from([
+            (
+                "Gintama".to_owned(),
+                Latest {
+                    title: "Gintama".to_owned(),
+                    number: ReleaseNumber::parse("1", None),
+                    url: "http://www.test.nyaa".to_owned(),
+                    source: "test".to_owned(),
+                    released_at: None,
+                    links: None,
+                },
+            ),
+            (
+                "Gintama'".to_owned(),
+                Latest {
+                    title: "Gintama'".to_owned(),
+                    number: ReleaseNumber::parse("2", None),
+                    url: "http://www.test.nyaa".to_owned(),
+                    source: "test".to_owned(),
+                    released_at: None,
+                    links: None,
+                },
+            ),
+        ]);
+
+        let media = |key: Option<String>| Media {
+            media_id: Some(1),
+            status: Some("CURRENT".to_owned()),
+            title: Some("Gintama".to_owned()),
+            media_type: Some(MediaType::Anime),
+            match_overrides: Some(MatchOverridesEntry {
+                media_id: 1,
+                overrides: BTreeMap::from([("subsplease_rss".to_owned(), key)]),
+            }),
+            ..Default::default()
+        };
+
+        let transformed = subsplease_rss
+            .transform(&mut media(None), &latest, None)
+            .unwrap();
+        assert_eq!(transformed.latest, None);
+
+        let transformed = subsplease_rss
+            .transform(&mut media(Some("Gintama'".to_owned())), &latest, None)
+            .unwrap();
+        assert_eq!(transformed.latest, latest.get("Gintama'").cloned());
+    }
+}
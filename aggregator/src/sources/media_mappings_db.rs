@@ -0,0 +1,483 @@
+use crate::anilist_api::Media;
+use crate::config::Config;
+use crate::db::MongoDB;
+use crate::error::CustomError;
+use crate::options::TransformOptions;
+use crate::result::Result;
+use crate::sources::Document;
+use crate::sources::{Extract, ExtractOptions, Transform};
+
+use async_trait::async_trait;
+use bson::doc;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    hash::Hash,
+};
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize, Hash)]
+pub struct MediaMappingsEntry {
+    pub media_id: u64,
+    pub ids: BTreeMap<String, String>,
+}
+
+impl Document for MediaMappingsEntry {}
+
+impl MediaMappingsEntry {
+    pub fn get(&self, site: &str) -> Option<&String> {
+        self.ids.get(site)
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct MediaMappings(pub HashMap<String, MediaMappingsEntry>);
+
+#[derive(Debug, Deserialize)]
+struct OfflineDatabaseEntry {
+    sources: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MappingsFile {
+    OfflineDatabase { data: Vec<OfflineDatabaseEntry> },
+    Entries(Vec<BTreeMap<String, serde_json::Value>>),
+}
+
+pub struct MediaMappingsDB<'a> {
+    config: &'a Config,
+}
+
+impl MediaMappingsDB<'_> {
+    pub fn new(config: &Config) -> MediaMappingsDB<'_> {
+        MediaMappingsDB { config }
+    }
+
+    fn parse_source_url(re: &regex::Regex, url: &str) -> Option<(String, String)> {
+        let caps = re.captures(url)?;
+
+        let id = match (&caps["host"], &caps["kind"]) {
+            // MangaUpdates series pages use the base 36 form of the API's numeric series id.
+            ("mangaupdates.com", "series") => {
+                u64::from_str_radix(&caps["id"], 36).ok()?.to_string()
+            }
+            _ => caps["id"].to_owned(),
+        };
+        let site = match &caps["host"] {
+            "anilist.co" => "anilist",
+            // MAL and Kitsu number anime and manga separately.
+            "myanimelist.net" => match &caps["kind"] {
+                "manga" => "myanimelist/manga",
+                _ => "myanimelist/anime",
+            },
+            "kitsu.io" | "kitsu.app" => match &caps["kind"] {
+                "manga" => "kitsu/manga",
+                _ => "kitsu/anime",
+            },
+            "anidb.net" => "anidb",
+            "anime-planet.com" => "animeplanet",
+            "mangadex.org" => "mangadex",
+            "mangaupdates.com" => "mangaupdates",
+            host => host.split('.').next().unwrap_or(host),
+        };
+
+        Some((site.to_owned(), id))
+    }
+
+    fn parse(&self, mappings: &str) -> Result<Vec<MediaMappingsEntry>> {
+        let mappings: MappingsFile = serde_json::from_str(mappings)?;
+        let re = regex::Regex::new(
+            r#"^https?://(?:www\.)?(?P<host>[^/]+)/(?P<kind>anime|manga|title|series)/(?P<id>[^/?#]+)"#,
+        )?;
+
+        let entries = match mappings {
+            MappingsFile::OfflineDatabase { data } => data
+                .into_iter()
+                .map(|entry| {
+                    entry
+                        .sources
+                        .iter()
+                        .filter_map(|url| Self::parse_source_url(&re, url))
+                        .collect::<BTreeMap<String, String>>()
+                })
+                .collect::<Vec<BTreeMap<String, String>>>(),
+            MappingsFile::Entries(entries) => entries
+                .into_iter()
+                .map(|entry| {
+                    entry
+                        .into_iter()
+                        .filter_map(|(site, id)| match id {
+                            serde_json::Value::String(id) => Some((site, id)),
+                            serde_json::Value::Number(id) => Some((site, id.to_string())),
+                            _ => None,
+                        })
+                        .collect::<BTreeMap<String, String>>()
+                })
+                .collect(),
+        };
+
+        let entries = entries
+            .into_iter()
+            .filter_map(|mut ids| {
+                let media_id = ids.remove("anilist")?.parse::<u64>().ok()?;
+                if ids.is_empty() {
+                    return None;
+                }
+                Some(MediaMappingsEntry { media_id, ids })
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    pub async fn import(&self, mongodb: &MongoDB<'_>, filename: &str) -> Result<usize> {
+        let mappings = fs::read_to_string(filename)?;
+        let entries = self.parse(&mappings)?;
+
+        mongodb
+            .upsert_documents("media_mappings", &entries, "media_id")
+            .await?;
+
+        Ok(entries.len())
+    }
+
+    pub async fn find_media_ids(
+        &self,
+        mongodb_client: mongodb::Client,
+        site: &str,
+        ids: &[String],
+    ) -> Result<HashMap<String, u64>> {
+        let mut cursor = mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<MediaMappingsEntry>("media_mappings")
+            .find(doc! { format!("ids.{}", site): { "$in": ids } }, None)
+            .await?;
+
+        let mut media_ids = HashMap::new();
+        while let Some(entry) = cursor.next().await {
+            let entry = entry?;
+            if let Some(id) = entry.get(site) {
+                media_ids.insert(id.to_owned(), entry.media_id);
+            }
+        }
+
+        Ok(media_ids)
+    }
+}
+
+#[async_trait]
+impl Extract<'_> for MediaMappingsDB<'_> {
+    type Data = MediaMappings;
+
+    async fn extract(&self, options: Option<ExtractOptions>) -> Result<Self::Data> {
+        let mongodb_client = match options {
+            Some(options) => match options.mongodb_client {
+                Some(mongodb_client) => mongodb_client,
+                None => return Err(CustomError::boxed("No mongodb client provided.")),
+            },
+            None => return Err(CustomError::boxed("No options provided.")),
+        };
+        let collection = mongodb_client
+            .database(&self.config.db.mongodb.database)
+            .collection::<MediaMappingsEntry>("media_mappings");
+
+        let mut cursor = collection.find(None, None).await?;
+        let mut media_mappings = MediaMappings(HashMap::new());
+
+        while let Some(item) = cursor.next().await {
+            match item {
+                Ok(entry) => {
+                    media_mappings.0.insert(entry.media_id.to_string(), entry);
+                }
+                Err(err) => {
+                    eprintln!("Could not get media mappings entry: {}", err);
+                }
+            }
+        }
+
+        Ok(media_mappings)
+    }
+}
+
+impl Transform for MediaMappingsDB<'_> {
+    type Extra = MediaMappingsEntry;
+
+    fn get_source(&self) -> &str {
+        "media_mappings_db"
+    }
+
+    fn set_media(media: &mut Media, extra: Option<Self::Extra>) {
+        media.mappings = extra;
+    }
+
+    fn transform(
+        &self,
+        media: &mut Media,
+        extras: &HashMap<String, Self::Extra>,
+        _options: Option<TransformOptions>,
+    ) -> Result<Media> {
+        let media_id = match media.media_id {
+            Some(media_id) => media_id.to_string(),
+            None => return Ok(std::mem::take(media)),
+        };
+
+        if extras.contains_key(&media_id) {
+            Self::set_media(media, extras.get(&media_id).cloned());
+        }
+
+        Ok(std::mem::take(media))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anilist_api::{Latest, MediaType};
+    use crate::config::Config;
+    use crate::mangadex_api::{MangaDexAPI, MangaDexLatest};
+    use crate::mangaupdates_api::MangaUpdatesAPI;
+    use crate::release::ReleaseNumber;
+    use crate::sources::id_key;
+    use crate::test::helpers::{init, reset_db, ONCE};
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[test]
+    fn test_parse() {
+        let config = Config::default();
+        let media_mappings_db = MediaMappingsDB::new(&config);
+
+        let offline_database = r#"{
+            "data": [
+                {
+                    "sources": [
+                        "https://anidb.net/anime/3468",
+                        "https://anilist.co/anime/918",
+                        "https://kitsu.io/anime/818",
+                        "https://myanimelist.net/anime/918"
+                    ],
+                    "title": "Gintama"
+                },
+                {
+                    "sources": ["https://myanimelist.net/anime/1"],
+                    "title": "Cowboy Bebop"
+                }
+            ]
+        }"#;
+        let actual = media_mappings_db.parse(offline_database).unwrap();
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].media_id, 918);
+        assert_eq!(actual[0].get("kitsu/anime"), Some(&"818".to_owned()));
+        assert_eq!(actual[0].get("myanimelist/anime"), Some(&"918".to_owned()));
+        assert_eq!(actual[0].get("anidb"), Some(&"3468".to_owned()));
+
+        let offline_database = r#"{
+            "data": [
+                {
+                    "sources": [
+                        "https://anilist.co/manga/105778",
+                        "https://myanimelist.net/manga/116778",
+                        "https://www.mangaupdates.com/series/pb8uwds/chainsaw-man",
+                        "https://mangadex.org/title/a77742b1-befd-49a4-bff5-1ad4e6b0ef7b"
+                    ]
+                }
+            ]
+        }"#;
+        let actual = media_mappings_db.parse(offline_database).unwrap();
+        assert_eq!(actual[0].media_id, 105778);
+        assert_eq!(
+            actual[0].get("myanimelist/manga"),
+            Some(&"116778".to_owned())
+        );
+        assert_eq!(
+            actual[0].get("mangaupdates"),
+            Some(&"55099564912".to_owned())
+        );
+        assert_eq!(
+            actual[0].get("mangadex"),
+            Some(&"a77742b1-befd-49a4-bff5-1ad4e6b0ef7b".to_owned())
+        );
+
+        let offline_database = r#"{
+            "data": [
+                {
+                    "sources": ["https://anilist.co/anime/918", "https://kitsu.app/anime/818"]
+                },
+                {
+                    "sources": ["https://anilist.co/manga/30818", "https://kitsu.app/manga/818"]
+                }
+            ]
+        }"#;
+        let actual = media_mappings_db.parse(offline_database).unwrap();
+        assert_eq!(actual.len(), 2);
+        assert_eq!(actual[0].get("kitsu/anime"), Some(&"818".to_owned()));
+        assert_eq!(actual[0].get("kitsu/manga"), None);
+        assert_eq!(actual[1].media_id, 30818);
+        assert_eq!(actual[1].get("kitsu/manga"), Some(&"818".to_owned()));
+        assert_eq!(actual[1].get("kitsu/anime"), None);
+
+        let entries = r#"[
+            { "anilist": 30044, "mangadex": "a1c7c817-4e59-43b7-9365-09675a149a6f" }
+        ]"#;
+        let actual = media_mappings_db.parse(entries).unwrap();
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].media_id, 30044);
+        assert_eq!(
+            actual[0].get("mangadex"),
+            Some(&"a1c7c817-4e59-43b7-9365-09675a149a6f".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract() {
+        ONCE.get_or_init(init).await;
+        reset_db().await;
+
+        let config = Config::default();
+        let mongodb = MongoDB::init(&config).await;
+        let media_mappings_db = MediaMappingsDB::new(&config);
+
+        let filename = std::env::temp_dir().join("oshirase-media-mappings.json");
+        fs::write(&filename, r#"[{ "anilist": 918, "kitsu/anime": 818 }]"#).unwrap();
+        let imported = media_mappings_db
+            .import(&mongodb, filename.to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(imported, 1);
+
+        let options = ExtractOptions {
+            mongodb_client: Some(mongodb.client.clone()),
+            ..Default::default()
+        };
+        let actual = media_mappings_db.extract(Some(options)).await.unwrap();
+        assert_eq!(actual.0.len(), 1);
+        assert_eq!(
+            actual.0.get("918").unwrap().get("kitsu/anime"),
+            Some(&"818".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_match_similar() {
+        let mut media = Media {
+            media_id: Some(30044),
+            status: Some("CURRENT".to_owned()),
+            title: Some("Gintama".to_owned()),
+            media_type: Some(MediaType::Manga),
+            mappings: Some(MediaMappingsEntry {
+                media_id: 30044,
+                ids: BTreeMap::from([("mangadex".to_owned(), "uuid".to_owned())]),
+            }),
+            ..Default::default()
+        };
+        let latest = HashMap::from([
+            (
+                "Gintama".to_owned(),
+                MangaDexLatest::new(
+                    "all",
+                    "en",
+                    Latest {
+                        title: "Gintama".to_owned(),
+                        number: ReleaseNumber::parse("1", None),
+                        url: "http://www.test.nyaa".to_owned(),
+                        source: "test".to_owned(),
+                        released_at: None,
+                        links: None,
+                    },
+                ),
+            ),
+            (
+                id_key("mangadex", "uuid"),
+                MangaDexLatest::new(
+                    "all",
+                    "en",
+                    Latest {
+                        title: "Gin Tama".to_owned(),
+                        number: ReleaseNumber::parse("2", None),
+                        url: "http://www.test.nyaa".to_owned(),
+                        source: "test".to_owned(),
+                        released_at: None,
+                        links: None,
+                    },
+                ),
+            ),
+        ]);
+
+        let config = Config::default();
+        let mangadex_api = MangaDexAPI::new(&config);
+
+        let transformed = mangadex_api.transform(&mut media, &latest, None).unwrap();
+        assert_eq!(
+            transformed.latest,
+            latest
+                .get(&id_key("mangadex", "uuid"))
+                .map(|l| l.latest.clone())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mangaupdates_series() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/releases/days"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "results": [{
+                    "record": {
+                        "id": 1,
+                        "title": "Chainsaw Man (Official)",
+                        "volume": null,
+                        "chapter": "130"
+                    },
+                    "metadata": {
+                        "series": {
+                            "series_id": 55099564912_u64,
+                            "title": "Chainsaw Man (Official)",
+                            "url": "https://www.mangaupdates.com/series/pb8uwds/chainsaw-man"
+                        }
+                    }
+                }]
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+
+        let mut config = Config::default();
+        config.mangaupdates_api.url = server.uri();
+        config.mangaupdates_api.pages = 1;
+
+        let mappings = MediaMappingsDB::new(&config)
+            .parse(
+                r#"{
+                    "data": [{
+                        "sources": [
+                            "https://anilist.co/manga/105778",
+                            "https://www.mangaupdates.com/series/pb8uwds/chainsaw-man"
+                        ]
+                    }]
+                }"#,
+            )
+            .unwrap();
+        let mangaupdates_api = MangaUpdatesAPI::new(&config);
+        let latest = mangaupdates_api.fetch().await.unwrap();
+
+        let mut media = Media {
+            media_id: Some(105778),
+            status: Some("CURRENT".to_owned()),
+            title: Some("Chensawman".to_owned()),
+            media_type: Some(MediaType::Manga),
+            mappings: mappings.into_iter().next(),
+            ..Default::default()
+        };
+        let transformed = mangaupdates_api
+            .transform(&mut media, &latest.0, None)
+            .unwrap();
+
+        let latest = transformed.latest.unwrap();
+        assert_eq!(latest.number, ReleaseNumber::parse("130", None));
+        assert_eq!(latest.url, format!("{}/releases/1", server.uri()));
+    }
+}
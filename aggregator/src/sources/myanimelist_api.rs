@@ -0,0 +1,509 @@
+use crate::anilist_api::{AniListAPI, Media, MediaLists, MediaType, Provider, User};
+use crate::config::Config;
+use crate::error::CustomError;
+use crate::http::{fnv1a, HttpClient};
+use crate::media_mappings_db::MediaMappingsDB;
+use crate::result::Result;
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use time::OffsetDateTime;
+
+const PAGE_LIMIT: usize = 1000;
+
+// MAL only exposes user ids through OAuth, so users get a stable id derived
+// from their name that stays above the id range of AniList.
+const USER_ID_OFFSET: u64 = 1 << 52;
+
+#[derive(Debug, Default, Deserialize)]
+struct MyAnimeListPicture {
+    large: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MyAnimeListAlternativeTitles {
+    en: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MyAnimeListSeason {
+    year: u64,
+    season: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MyAnimeListNode {
+    id: u64,
+    title: String,
+    main_picture: Option<MyAnimeListPicture>,
+    #[serde(default)]
+    alternative_titles: MyAnimeListAlternativeTitles,
+    media_type: Option<String>,
+    start_season: Option<MyAnimeListSeason>,
+    num_episodes: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MyAnimeListStatus {
+    status: String,
+    score: Option<u64>,
+    num_episodes_watched: Option<u64>,
+    num_chapters_read: Option<u64>,
+    #[serde(default)]
+    is_rewatching: bool,
+    #[serde(default)]
+    is_rereading: bool,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    updated_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MyAnimeListEntry {
+    node: MyAnimeListNode,
+    list_status: MyAnimeListStatus,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MyAnimeListPaging {
+    next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MyAnimeListPage {
+    data: Vec<MyAnimeListEntry>,
+    #[serde(default)]
+    paging: MyAnimeListPaging,
+}
+
+fn status(status: &str, repeating: bool) -> Option<String> {
+    let status = match status {
+        "watching" | "reading" if repeating => "REPEATING",
+        "watching" | "reading" => "CURRENT",
+        "plan_to_watch" | "plan_to_read" => "PLANNING",
+        "completed" => "COMPLETED",
+        "on_hold" => "PAUSED",
+        "dropped" => "DROPPED",
+        _ => return None,
+    };
+
+    Some(status.to_owned())
+}
+
+fn format(media_type: &str) -> Option<String> {
+    let format = match media_type {
+        "tv" => "TV",
+        "ova" => "OVA",
+        "ona" => "ONA",
+        "movie" => "MOVIE",
+        "special" => "SPECIAL",
+        "music" => "MUSIC",
+        "manga" | "manhwa" | "manhua" | "oel" | "doujinshi" => "MANGA",
+        "novel" | "light_novel" => "NOVEL",
+        "one_shot" => "ONE_SHOT",
+        _ => return None,
+    };
+
+    Some(format.to_owned())
+}
+
+fn user_id(name: &str) -> u64 {
+    let hash = fnv1a(name.to_lowercase().as_bytes());
+
+    USER_ID_OFFSET + hash % USER_ID_OFFSET
+}
+
+pub struct MyAnimeListAPI<'a> {
+    config: &'a Config,
+}
+
+impl MyAnimeListAPI<'_> {
+    pub fn new(config: &Config) -> MyAnimeListAPI<'_> {
+        MyAnimeListAPI { config }
+    }
+
+    fn client_id(&self) -> Result<&str> {
+        self.config
+            .myanimelist_api
+            .client_id
+            .as_deref()
+            .ok_or(CustomError::boxed("No MyAnimeList client id provided."))
+    }
+
+    pub async fn add_user(&self, mongodb_client: mongodb::Client, name: &str) -> Result<User> {
+        let user = User {
+            id: user_id(name),
+            name: name.to_owned(),
+            provider: Provider::MyAnimeList,
+            ..Default::default()
+        };
+        if let Err(err) = self.fetch_entries(&user, MediaType::Anime).await {
+            return Err(CustomError::boxed(&format!(
+                "Could not fetch lists of MyAnimeList user {}: {}",
+                user.name, err
+            )));
+        }
+
+        AniListAPI::new(self.config)
+            .save_user(mongodb_client, &user)
+            .await?;
+
+        Ok(user)
+    }
+
+    async fn fetch_entries(
+        &self,
+        user: &User,
+        media_type: MediaType,
+    ) -> Result<Vec<MyAnimeListEntry>> {
+        let (list, fields) = match media_type {
+            MediaType::Anime => (
+                "animelist",
+                "list_status,alternative_titles,media_type,start_season,num_episodes",
+            ),
+            MediaType::Manga => ("mangalist", "list_status,alternative_titles,media_type"),
+        };
+        let client_id = self.client_id()?;
+        let limit = PAGE_LIMIT.to_string();
+
+        let client = HttpClient::new(self.config, "myanimelist_api");
+        let mut entries = Vec::new();
+        let mut request = client
+            .get(format!(
+                "{}/users/{}/{}",
+                self.config.myanimelist_api.url, user.name, list
+            ))
+            .query(&[
+                ("fields", fields),
+                ("limit", limit.as_str()),
+                ("nsfw", "true"),
+            ]);
+        loop {
+            let mut page = client
+                .send(request.header("X-MAL-CLIENT-ID", client_id))
+                .await?
+                .error_for_status()?
+                .json::<MyAnimeListPage>()?;
+            entries.append(&mut page.data);
+
+            request = match page.paging.next {
+                Some(next) => client.get(next),
+                None => break,
+            };
+        }
+
+        Ok(entries)
+    }
+
+    fn transform(
+        &self,
+        media_type: MediaType,
+        entries: Vec<MyAnimeListEntry>,
+        media_ids: &HashMap<String, u64>,
+    ) -> Vec<Media> {
+        entries
+            .into_iter()
+            .filter_map(|entry| {
+                let media_id = match media_ids.get(&entry.node.id.to_string()) {
+                    Some(media_id) => *media_id,
+                    None => {
+                        eprintln!(
+                            "Could not find AniList id of MyAnimeList entry {} ({}).",
+                            entry.node.id, entry.node.title
+                        );
+                        return None;
+                    }
+                };
+
+                let (status, progress, episodes) = match media_type {
+                    MediaType::Anime => (
+                        status(&entry.list_status.status, entry.list_status.is_rewatching),
+                        entry.list_status.num_episodes_watched,
+                        entry.node.num_episodes.filter(|episodes| *episodes > 0),
+                    ),
+                    MediaType::Manga => (
+                        status(&entry.list_status.status, entry.list_status.is_rereading),
+                        entry.list_status.num_chapters_read,
+                        None,
+                    ),
+                };
+
+                Some(Media {
+                    media_id: Some(media_id),
+                    media_type: Some(media_type),
+                    status,
+                    format: entry.node.media_type.as_deref().and_then(format),
+                    season: entry
+                        .node
+                        .start_season
+                        .as_ref()
+                        .map(|season| season.season.to_uppercase()),
+                    season_year: entry.node.start_season.as_ref().map(|season| season.year),
+                    title: Some(entry.node.title),
+                    english_title: entry
+                        .node
+                        .alternative_titles
+                        .en
+                        .filter(|title| !title.is_empty()),
+                    image: entry.node.main_picture.and_then(|picture| picture.large),
+                    episodes,
+                    score: entry.list_status.score,
+                    progress,
+                    updated_at: entry.list_status.updated_at,
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    pub async fn fetch_lists(
+        &self,
+        mongodb_client: mongodb::Client,
+        user: &User,
+    ) -> Result<MediaLists> {
+        let anime = self.fetch_entries(user, MediaType::Anime).await?;
+        let manga = self.fetch_entries(user, MediaType::Manga).await?;
+
+        let anime_ids = self
+            .find_media_ids(mongodb_client.clone(), MediaType::Anime, &anime)
+            .await?;
+        let manga_ids = self
+            .find_media_ids(mongodb_client, MediaType::Manga, &manga)
+            .await?;
+
+        Ok(MediaLists {
+            anime: self.transform(MediaType::Anime, anime, &anime_ids),
+            manga: self.transform(MediaType::Manga, manga, &manga_ids),
+        })
+    }
+
+    // MAL ids are only unique per media type. The imported mappings mostly
+    // cover anime, so ids they miss are looked up on AniList.
+    async fn find_media_ids(
+        &self,
+        mongodb_client: mongodb::Client,
+        media_type: MediaType,
+        entries: &[MyAnimeListEntry],
+    ) -> Result<HashMap<String, u64>> {
+        let site = match media_type {
+            MediaType::Anime => "myanimelist/anime",
+            MediaType::Manga => "myanimelist/manga",
+        };
+        let ids: Vec<String> = entries
+            .iter()
+            .map(|entry| entry.node.id.to_string())
+            .collect();
+        let mut media_ids = MediaMappingsDB::new(self.config)
+            .find_media_ids(mongodb_client, site, &ids)
+            .await?;
+
+        let missing: Vec<u64> = entries
+            .iter()
+            .map(|entry| entry.node.id)
+            .filter(|id| !media_ids.contains_key(&id.to_string()))
+            .collect();
+        if !missing.is_empty() {
+            media_ids.extend(
+                AniListAPI::new(self.config)
+                    .find_mal_media_ids(media_type, &missing)
+                    .await?,
+            );
+        }
+
+        Ok(media_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn config(url: &str) -> Config {
+        let mut config = Config::default();
+        config.myanimelist_api.url = url.to_owned();
+        config.myanimelist_api.client_id = Some("client".to_owned());
+        config
+    }
+
+    fn user() -> User {
+        User {
+            id: user_id("test"),
+            name: "test".to_owned(),
+            provider: Provider::MyAnimeList,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lists() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/users/test/animelist"))
+            .and(header("X-MAL-CLIENT-ID", "client"))
+            .and(query_param("limit", "1000"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [
+                    {
+                        "node": {
+                            "id": 52034,
+                            "title": "Oshi no Ko",
+                            "main_picture": { "large": "http://localhost/52034.jpg" },
+                            "alternative_titles": { "en": "[Oshi No Ko]" },
+                            "media_type": "tv",
+                            "start_season": { "year": 2023, "season": "spring" },
+                            "num_episodes": 11
+                        },
+                        "list_status": {
+                            "status": "watching",
+                            "score": 8,
+                            "num_episodes_watched": 5,
+                            "is_rewatching": false,
+                            "updated_at": "2023-05-17T16:01:10+00:00"
+                        }
+                    }
+                ],
+                "paging": { "next": format!("{}/users/test/animelist?offset=1", server.uri()) }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/users/test/animelist"))
+            .and(header("X-MAL-CLIENT-ID", "client"))
+            .and(query_param("offset", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [
+                    {
+                        "node": { "id": 1, "title": "Cowboy Bebop", "media_type": "tv" },
+                        "list_status": { "status": "plan_to_watch", "score": 0 }
+                    }
+                ],
+                "paging": {}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/users/test/mangalist"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [
+                    {
+                        "node": { "id": 126146, "title": "Oshi no Ko", "media_type": "manga" },
+                        "list_status": {
+                            "status": "reading",
+                            "score": 9,
+                            "num_chapters_read": 120,
+                            "is_rereading": true
+                        }
+                    }
+                ],
+                "paging": {}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = config(&server.uri());
+        let api = MyAnimeListAPI::new(&config);
+        let anime = api.fetch_entries(&user(), MediaType::Anime).await.unwrap();
+        let manga = api.fetch_entries(&user(), MediaType::Manga).await.unwrap();
+        assert_eq!(anime.len(), 2);
+
+        let media_ids =
+            HashMap::from([("52034".to_owned(), 150672), ("126146".to_owned(), 107237)]);
+        let anime = api.transform(MediaType::Anime, anime, &media_ids);
+        let manga = api.transform(MediaType::Manga, manga, &media_ids);
+
+        assert_eq!(anime.len(), 1);
+        let anime = &anime[0];
+        assert_eq!(anime.media_id, Some(150672));
+        assert_eq!(anime.status.as_deref(), Some("CURRENT"));
+        assert_eq!(anime.format.as_deref(), Some("TV"));
+        assert_eq!(anime.season.as_deref(), Some("SPRING"));
+        assert_eq!(anime.season_year, Some(2023));
+        assert_eq!(anime.english_title.as_deref(), Some("[Oshi No Ko]"));
+        assert_eq!(anime.episodes, Some(11));
+        assert_eq!(anime.score, Some(8));
+        assert_eq!(anime.progress, Some(5));
+
+        assert_eq!(manga.len(), 1);
+        let manga = &manga[0];
+        assert_eq!(manga.media_id, Some(107237));
+        assert_eq!(manga.status.as_deref(), Some("REPEATING"));
+        assert_eq!(manga.progress, Some(120));
+        assert_eq!(manga.episodes, None);
+    }
+
+    #[tokio::test]
+    async fn test_media_ids_per_type() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/anilist"))
+            .and(body_partial_json(serde_json::json!({
+                "variables": { "ids": [1], "type": "ANIME" }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": { "Page": { "media": [{ "id": 1, "idMal": 1 }] } }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/anilist"))
+            .and(body_partial_json(serde_json::json!({
+                "variables": { "ids": [1], "type": "MANGA" }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": { "Page": { "media": [{ "id": 30001, "idMal": 1 }] } }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut config = config(&server.uri());
+        config.anilist_api.url = format!("{}/anilist", server.uri());
+        let api = MyAnimeListAPI::new(&config);
+        let anilist_api = AniListAPI::new(&config);
+        let anime_ids = anilist_api
+            .find_mal_media_ids(MediaType::Anime, &[1])
+            .await
+            .unwrap();
+        let manga_ids = anilist_api
+            .find_mal_media_ids(MediaType::Manga, &[1])
+            .await
+            .unwrap();
+
+        let entry = |title: &str| -> MyAnimeListEntry {
+            serde_json::from_value(serde_json::json!({
+                "node": { "id": 1, "title": title },
+                "list_status": { "status": "completed" }
+            }))
+            .unwrap()
+        };
+        let anime = api.transform(MediaType::Anime, vec![entry("Cowboy Bebop")], &anime_ids);
+        let manga = api.transform(MediaType::Manga, vec![entry("Monster")], &manga_ids);
+
+        assert_eq!(anime[0].media_id, Some(1));
+        assert_eq!(manga[0].media_id, Some(30001));
+    }
+
+    #[tokio::test]
+    async fn test_lists_without_client_id() {
+        let mut config = config("http://localhost");
+        config.myanimelist_api.client_id = None;
+        let api = MyAnimeListAPI::new(&config);
+
+        assert!(api.fetch_entries(&user(), MediaType::Anime).await.is_err());
+    }
+
+    #[test]
+    fn test_user_id() {
+        assert_eq!(user_id("Test"), user_id("test"));
+        assert_ne!(user_id("test"), user_id("other"));
+        assert!(user_id("test") >= USER_ID_OFFSET);
+        assert!(user_id("test") < USER_ID_OFFSET * 2);
+    }
+}
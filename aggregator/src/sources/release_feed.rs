@@ -0,0 +1,359 @@
+use crate::anilist_api::{Latest, Media, MediaType};
+use crate::config::{Config, FeedConfig, FeedFormat, FeedLinkField};
+use crate::error::CustomError;
+use crate::http::HttpClient;
+use crate::options::{ExtractOptions, TransformOptions};
+use crate::release::{ReleaseLinks, ReleaseNumber};
+use crate::result::Result;
+use crate::sources::{Extract, Similar, Transform};
+
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_xml_rs::from_str;
+use std::collections::HashMap;
+use time::{
+    format_description::well_known::{Rfc2822, Rfc3339},
+    OffsetDateTime, UtcOffset,
+};
+
+#[derive(Debug, Deserialize)]
+struct RssItem {
+    title: String,
+    link: String,
+    #[serde(rename = "pubDate")]
+    pub_date: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RssChannel {
+    #[serde(rename = "item", default)]
+    items: Vec<RssItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Rss {
+    channel: RssChannel,
+}
+
+#[derive(Debug, Deserialize)]
+struct AtomLink {
+    href: String,
+    rel: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AtomEntry {
+    title: String,
+    #[serde(rename = "link", default)]
+    links: Vec<AtomLink>,
+    published: Option<String>,
+    updated: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Atom {
+    #[serde(rename = "entry", default)]
+    entries: Vec<AtomEntry>,
+}
+
+#[derive(Debug, PartialEq)]
+struct FeedItem {
+    title: String,
+    link: String,
+    released_at: Option<OffsetDateTime>,
+}
+
+fn parse_date(date: &str) -> Option<OffsetDateTime> {
+    OffsetDateTime::parse(date.trim(), &Rfc2822)
+        .or_else(|_| OffsetDateTime::parse(date.trim(), &Rfc3339))
+        .map(|date| date.to_offset(UtcOffset::UTC))
+        .ok()
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct FeedLatest(pub HashMap<String, Latest>);
+
+#[derive(Debug, Clone)]
+pub struct ReleaseFeed<'a> {
+    config: &'a Config,
+    feed: &'a FeedConfig,
+}
+
+impl<'a> ReleaseFeed<'a> {
+    pub fn new(config: &'a Config, feed: &'a FeedConfig) -> ReleaseFeed<'a> {
+        ReleaseFeed { config, feed }
+    }
+
+    pub fn all(config: &'a Config) -> Vec<ReleaseFeed<'a>> {
+        config
+            .feeds
+            .iter()
+            .map(|feed| ReleaseFeed::new(config, feed))
+            .collect()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.feed.name
+    }
+
+    pub fn media_type(&self) -> Result<MediaType> {
+        Ok(self.feed.media_type.parse()?)
+    }
+
+    fn parse_items(&self, xml: &str) -> Result<Vec<FeedItem>> {
+        let items = match self.feed.format {
+            FeedFormat::Rss => from_str::<Rss>(xml)?
+                .channel
+                .items
+                .into_iter()
+                .map(|item| FeedItem {
+                    title: item.title,
+                    link: item.link,
+                    released_at: item.pub_date.as_deref().and_then(parse_date),
+                })
+                .collect(),
+            FeedFormat::Atom => from_str::<Atom>(xml)?
+                .entries
+                .into_iter()
+                .filter_map(|entry| {
+                    let link = entry
+                        .links
+                        .iter()
+                        .find(|link| matches!(link.rel.as_deref(), None | Some("alternate")))
+                        .or(entry.links.first())?
+                        .href
+                        .to_owned();
+                    Some(FeedItem {
+                        title: entry.title,
+                        link,
+                        released_at: entry
+                            .published
+                            .or(entry.updated)
+                            .as_deref()
+                            .and_then(parse_date),
+                    })
+                })
+                .collect(),
+        };
+
+        Ok(items)
+    }
+
+    fn parse(&self, xml: &str) -> Result<FeedLatest> {
+        self.media_type()?;
+        let re = Regex::new(&self.feed.title_regex)?;
+        if !re.capture_names().flatten().any(|name| name == "title")
+            || !re.capture_names().flatten().any(|name| name == "episode")
+        {
+            return Err(CustomError::boxed(&format!(
+                "Title regex for feed {} needs title and episode groups.",
+                self.feed.name
+            )));
+        }
+
+        let mut latest: HashMap<String, Latest> = HashMap::new();
+        for item in self.parse_items(xml)? {
+            let (title, number) = match re.captures(&item.title) {
+                Some(caps) => (
+                    caps["title"].trim().to_owned(),
+                    ReleaseNumber::parse(&caps["episode"], None),
+                ),
+                None => {
+                    eprintln!("Could not parse feed item title: {}", item.title);
+                    continue;
+                }
+            };
+
+            let newer = match latest.get(&title) {
+                Some(current) => current.number < number,
+                None => true,
+            };
+            if newer {
+                let link = Some(item.link.clone());
+                let links = match self.feed.link_field {
+                    FeedLinkField::Url => None,
+                    FeedLinkField::Page => Some(ReleaseLinks {
+                        page: link,
+                        ..Default::default()
+                    }),
+                    FeedLinkField::Magnet => Some(ReleaseLinks {
+                        magnet: link,
+                        ..Default::default()
+                    }),
+                    FeedLinkField::Torrent => Some(ReleaseLinks {
+                        torrent: link,
+                        ..Default::default()
+                    }),
+                };
+                latest.insert(
+                    title.clone(),
+                    Latest {
+                        title,
+                        number,
+                        url: item.link,
+                        source: self.feed.name.to_owned(),
+                        released_at: item.released_at,
+                        links,
+                    },
+                );
+            }
+        }
+
+        Ok(FeedLatest(latest))
+    }
+
+    pub async fn fetch(&self) -> Result<FeedLatest> {
+        let client = HttpClient::new(self.config, &self.feed.name);
+        let xml = client
+            .send(client.get(self.feed.url.as_str()))
+            .await?
+            .error_for_status()?
+            .text();
+
+        self.parse(&xml)
+    }
+}
+
+#[async_trait]
+impl Extract<'_> for ReleaseFeed<'_> {
+    type Data = FeedLatest;
+
+    async fn extract(&self, _options: Option<ExtractOptions>) -> Result<Self::Data> {
+        self.fetch().await
+    }
+}
+
+impl Transform for ReleaseFeed<'_> {
+    type Extra = Latest;
+
+    fn get_source(&self) -> &str {
+        &self.feed.name
+    }
+
+    fn set_media(media: &mut Media, extra: Option<Self::Extra>) {
+        if let Some(extra) = extra {
+            media.insert_latest(extra);
+        }
+    }
+
+    fn transform(
+        &self,
+        media: &mut Media,
+        extras: &HashMap<String, Self::Extra>,
+        options: Option<TransformOptions>,
+    ) -> Result<Media> {
+        self.match_similar(media, self.media_type()?, extras, options)
+    }
+}
+
+impl Similar for ReleaseFeed<'_> {
+    fn get_similarity_threshold(&self) -> f64 {
+        self.config.transform.similarity_threshold
+    }
+
+    fn get_similarity_candidates(&self) -> usize {
+        self.config.transform.similarity_candidates
+    }
+
+    fn get_enriched_statuses(&self) -> &[String] {
+        match &self.feed.enriched_statuses {
+            Some(enriched_statuses) => enriched_statuses,
+            None => &self.config.transform.enriched_statuses,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anilist_api::ReleaseType;
+    use time::macros::datetime;
+
+    fn feed(format: FeedFormat, link_field: FeedLinkField) -> FeedConfig {
+        FeedConfig {
+            name: "erai_raws".to_owned(),
+            url: "http://localhost/feed".to_owned(),
+            format,
+            title_regex: r"^\[Erai-raws\] (?P<title>.+) - (?P<episode>\d+(?:v\d+)?) ".to_owned(),
+            media_type: "anime".to_owned(),
+            link_field,
+            release_type: ReleaseType::Fansub,
+            enriched_statuses: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_rss() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <rss version="2.0">
+                <channel>
+                    <title>Erai-raws</title>
+                    <item>
+                        <title>[Erai-raws] Oshi no Ko - 06 [1080p][Multiple Subtitle]</title>
+                        <link>magnet:?xt=6</link>
+                        <pubDate>Wed, 17 May 2023 16:01:10 +0000</pubDate>
+                    </item>
+                    <item>
+                        <title>[Erai-raws] Oshi no Ko - 05 [1080p][Multiple Subtitle]</title>
+                        <link>magnet:?xt=5</link>
+                        <pubDate>Wed, 10 May 2023 16:01:10 +0000</pubDate>
+                    </item>
+                    <item>
+                        <title>Unrelated post</title>
+                        <link>http://localhost/post</link>
+                    </item>
+                </channel>
+            </rss>"#;
+
+        let config = Config::default();
+        let feed = feed(FeedFormat::Rss, FeedLinkField::Magnet);
+        let latest = ReleaseFeed::new(&config, &feed).parse(xml).unwrap();
+
+        assert_eq!(latest.0.len(), 1);
+        let latest = latest.0.get("Oshi no Ko").unwrap();
+        assert_eq!(latest.number, ReleaseNumber::parse("06", None));
+        assert_eq!(latest.url, "magnet:?xt=6");
+        assert_eq!(latest.source, "erai_raws");
+        assert_eq!(latest.released_at, Some(datetime!(2023-05-17 16:01:10 UTC)));
+        assert_eq!(
+            latest
+                .links
+                .as_ref()
+                .and_then(|links| links.magnet.as_deref()),
+            Some("magnet:?xt=6")
+        );
+    }
+
+    #[test]
+    fn test_parse_atom() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+                <title>Erai-raws</title>
+                <entry>
+                    <title type="text">[Erai-raws] Oshi no Ko - 06 [1080p]</title>
+                    <link rel="enclosure" href="http://localhost/6.torrent"/>
+                    <link rel="alternate" href="http://localhost/6"/>
+                    <updated>2023-05-17T16:01:10Z</updated>
+                </entry>
+            </feed>"#;
+
+        let config = Config::default();
+        let feed = feed(FeedFormat::Atom, FeedLinkField::Url);
+        let latest = ReleaseFeed::new(&config, &feed).parse(xml).unwrap();
+
+        let latest = latest.0.get("Oshi no Ko").unwrap();
+        assert_eq!(latest.url, "http://localhost/6");
+        assert_eq!(latest.links, None);
+        assert_eq!(latest.released_at, Some(datetime!(2023-05-17 16:01:10 UTC)));
+    }
+
+    #[test]
+    fn test_parse_invalid_regex() {
+        let config = Config::default();
+        let mut feed = feed(FeedFormat::Rss, FeedLinkField::Url);
+        feed.title_regex = r"(?P<title>.+)".to_owned();
+
+        assert!(ReleaseFeed::new(&config, &feed).parse("").is_err());
+    }
+}
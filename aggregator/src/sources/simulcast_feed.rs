@@ -0,0 +1,339 @@
+use crate::anilist_api::{Media, MediaType, Stream};
+use crate::config::{Config, StreamFeedConfig};
+use crate::error::CustomError;
+use crate::http::HttpClient;
+use crate::options::{ExtractOptions, TransformOptions};
+use crate::release::ReleaseNumber;
+use crate::result::Result;
+use crate::sources::{Extract, Similar, Transform};
+
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_xml_rs::from_str;
+use std::collections::HashMap;
+use time::{format_description::well_known::Rfc2822, OffsetDateTime, UtcOffset};
+
+#[derive(Debug, Deserialize)]
+struct Restriction {
+    relationship: Option<String>,
+    #[serde(rename = "$value")]
+    countries: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamItem {
+    title: String,
+    link: String,
+    #[serde(rename = "pubDate")]
+    pub_date: Option<String>,
+    #[serde(rename = "seriesTitle")]
+    series_title: Option<String>,
+    #[serde(rename = "episodeNumber")]
+    episode_number: Option<String>,
+    restriction: Option<Restriction>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChannel {
+    #[serde(rename = "item", default)]
+    items: Vec<StreamItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamRss {
+    channel: StreamChannel,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct StreamLatest(pub HashMap<String, Stream>);
+
+#[derive(Debug, Clone)]
+pub struct SimulcastFeed<'a> {
+    config: &'a Config,
+    feed: &'a StreamFeedConfig,
+}
+
+impl<'a> SimulcastFeed<'a> {
+    pub fn new(config: &'a Config, feed: &'a StreamFeedConfig) -> SimulcastFeed<'a> {
+        SimulcastFeed { config, feed }
+    }
+
+    pub fn all(config: &'a Config) -> Vec<SimulcastFeed<'a>> {
+        config
+            .streams
+            .iter()
+            .map(|feed| SimulcastFeed::new(config, feed))
+            .collect()
+    }
+
+    pub fn platform(&self) -> &str {
+        &self.feed.platform
+    }
+
+    fn region(&self, restriction: Option<&Restriction>) -> Option<Option<String>> {
+        let (deny, countries) = match restriction {
+            Some(restriction) => (
+                restriction.relationship.as_deref() == Some("deny"),
+                restriction.countries.as_deref(),
+            ),
+            None => (false, None),
+        };
+
+        match (&self.feed.region, countries) {
+            (Some(region), Some(countries)) => {
+                let listed = countries
+                    .split_whitespace()
+                    .any(|country| country.eq_ignore_ascii_case(region));
+                (listed != deny).then(|| Some(region.to_owned()))
+            }
+            (Some(region), None) => Some(Some(region.to_owned())),
+            // A deny list doesn't tell which countries the stream is in.
+            (None, countries) => Some(countries.filter(|_| !deny).map(|c| c.to_owned())),
+        }
+    }
+
+    fn parse(&self, xml: &str) -> Result<StreamLatest> {
+        let re = Regex::new(&self.feed.title_regex)?;
+        if !re.capture_names().flatten().any(|name| name == "title")
+            || !re.capture_names().flatten().any(|name| name == "episode")
+        {
+            return Err(CustomError::boxed(&format!(
+                "Title regex for stream feed {} needs title and episode groups.",
+                self.feed.platform
+            )));
+        }
+
+        let mut latest: HashMap<String, Stream> = HashMap::new();
+        for item in from_str::<StreamRss>(xml)?.channel.items {
+            let caps = re.captures(&item.title);
+            let title = item
+                .series_title
+                .clone()
+                .or(caps.as_ref().map(|caps| caps["title"].to_owned()));
+            let episode = item
+                .episode_number
+                .clone()
+                .or(caps.as_ref().map(|caps| caps["episode"].to_owned()));
+            let (title, episode) = match (title, episode) {
+                (Some(title), Some(episode)) => (
+                    title.trim().to_owned(),
+                    ReleaseNumber::parse(&episode, None),
+                ),
+                _ => {
+                    eprintln!("Could not parse stream title: {}", item.title);
+                    continue;
+                }
+            };
+            let region = match self.region(item.restriction.as_ref()) {
+                Some(region) => region,
+                None => continue,
+            };
+
+            let newer = match latest.get(&title) {
+                Some(current) => current.episode < episode,
+                None => true,
+            };
+            if newer {
+                latest.insert(
+                    title.clone(),
+                    Stream {
+                        platform: self.feed.platform.to_owned(),
+                        title,
+                        episode,
+                        url: item.link,
+                        region,
+                        released_at: item
+                            .pub_date
+                            .as_deref()
+                            .and_then(|date| OffsetDateTime::parse(date.trim(), &Rfc2822).ok())
+                            .map(|date| date.to_offset(UtcOffset::UTC)),
+                    },
+                );
+            }
+        }
+
+        Ok(StreamLatest(latest))
+    }
+
+    pub async fn fetch(&self) -> Result<StreamLatest> {
+        let client = HttpClient::new(self.config, &self.feed.platform);
+        let xml = client
+            .send(client.get(self.feed.url.as_str()))
+            .await?
+            .error_for_status()?
+            .text();
+
+        self.parse(&xml)
+    }
+}
+
+#[async_trait]
+impl Extract<'_> for SimulcastFeed<'_> {
+    type Data = StreamLatest;
+
+    async fn extract(&self, _options: Option<ExtractOptions>) -> Result<Self::Data> {
+        self.fetch().await
+    }
+}
+
+impl Transform for SimulcastFeed<'_> {
+    type Extra = Stream;
+
+    fn get_source(&self) -> &str {
+        &self.feed.platform
+    }
+
+    fn set_media(media: &mut Media, extra: Option<Self::Extra>) {
+        if let Some(extra) = extra {
+            let streams = media.streams.get_or_insert_with(Vec::new);
+            streams.retain(|stream| stream.platform != extra.platform);
+            streams.push(extra);
+        }
+    }
+
+    fn transform(
+        &self,
+        media: &mut Media,
+        extras: &HashMap<String, Self::Extra>,
+        options: Option<TransformOptions>,
+    ) -> Result<Media> {
+        self.match_similar(media, MediaType::Anime, extras, options)
+    }
+}
+
+impl Similar for SimulcastFeed<'_> {
+    fn get_similarity_threshold(&self) -> f64 {
+        self.config.transform.similarity_threshold
+    }
+
+    fn get_similarity_candidates(&self) -> usize {
+        self.config.transform.similarity_candidates
+    }
+
+    fn get_enriched_statuses(&self) -> &[String] {
+        match &self.feed.enriched_statuses {
+            Some(enriched_statuses) => enriched_statuses,
+            None => &self.config.transform.enriched_statuses,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn feed(region: Option<&str>) -> StreamFeedConfig {
+        StreamFeedConfig {
+            platform: "crunchyroll".to_owned(),
+            url: "http://localhost/rss/anime".to_owned(),
+            title_regex: StreamFeedConfig::default_title_regex(),
+            region: region.map(|region| region.to_owned()),
+            enriched_statuses: None,
+        }
+    }
+
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <rss version="2.0" xmlns:crunchyroll="http://www.crunchyroll.com/rss" xmlns:media="http://search.yahoo.com/mrss/">
+            <channel>
+                <title>Crunchyroll Anime</title>
+                <item>
+                    <title>Oshi no Ko - Episode 6 - Ego-Searching</title>
+                    <link>http://localhost/watch/6</link>
+                    <pubDate>Wed, 17 May 2023 16:01:10 +0000</pubDate>
+                    <crunchyroll:seriesTitle>Oshi no Ko</crunchyroll:seriesTitle>
+                    <crunchyroll:episodeNumber>6</crunchyroll:episodeNumber>
+                    <media:restriction relationship="allow" type="country">us ca gb</media:restriction>
+                </item>
+                <item>
+                    <title>Oshi no Ko - Episode 5 - Influencer</title>
+                    <link>http://localhost/watch/5</link>
+                    <pubDate>Wed, 10 May 2023 16:01:10 +0000</pubDate>
+                </item>
+                <item>
+                    <title>Skip and Loafer - Episode 7 - Hot and Cold</title>
+                    <link>http://localhost/watch/skip/7</link>
+                    <media:restriction relationship="allow" type="country">jp</media:restriction>
+                </item>
+            </channel>
+        </rss>"#;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::default();
+        let feed = feed(Some("US"));
+        let latest = SimulcastFeed::new(&config, &feed).parse(XML).unwrap();
+
+        assert_eq!(latest.0.len(), 1);
+        let stream = latest.0.get("Oshi no Ko").unwrap();
+        assert_eq!(stream.platform, "crunchyroll");
+        assert_eq!(stream.episode, ReleaseNumber::parse("6", None));
+        assert_eq!(stream.url, "http://localhost/watch/6");
+        assert_eq!(stream.region.as_deref(), Some("US"));
+        assert_eq!(stream.released_at, Some(datetime!(2023-05-17 16:01:10 UTC)));
+
+        let feed = self::feed(None);
+        let latest = SimulcastFeed::new(&config, &feed).parse(XML).unwrap();
+        assert_eq!(latest.0.len(), 2);
+        assert_eq!(
+            latest.0.get("Skip and Loafer").unwrap().region.as_deref(),
+            Some("jp")
+        );
+    }
+
+    #[test]
+    fn test_deny_restriction() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <rss version="2.0" xmlns:crunchyroll="http://www.crunchyroll.com/rss" xmlns:media="http://search.yahoo.com/mrss/">
+                <channel>
+                    <item>
+                        <title>Oshi no Ko - Episode 6 - Ego-Searching</title>
+                        <link>http://localhost/watch/6</link>
+                        <media:restriction relationship="deny" type="country">jp</media:restriction>
+                    </item>
+                    <item>
+                        <title>Skip and Loafer - Episode 7 - Hot and Cold</title>
+                        <link>http://localhost/watch/skip/7</link>
+                        <media:restriction relationship="deny" type="country">us ca</media:restriction>
+                    </item>
+                </channel>
+            </rss>"#;
+        let config = Config::default();
+
+        let feed = feed(Some("US"));
+        let latest = SimulcastFeed::new(&config, &feed).parse(xml).unwrap();
+        assert_eq!(latest.0.len(), 1);
+        assert_eq!(
+            latest.0.get("Oshi no Ko").unwrap().region.as_deref(),
+            Some("US")
+        );
+
+        let feed = self::feed(None);
+        let latest = SimulcastFeed::new(&config, &feed).parse(xml).unwrap();
+        assert_eq!(latest.0.len(), 2);
+        assert_eq!(latest.0.get("Skip and Loafer").unwrap().region, None);
+    }
+
+    #[test]
+    fn test_set_media() {
+        let stream = |platform: &str, episode: &str| Stream {
+            platform: platform.to_owned(),
+            title: "Oshi no Ko".to_owned(),
+            episode: ReleaseNumber::parse(episode, None),
+            url: format!("http://localhost/{}/{}", platform, episode),
+            region: None,
+            released_at: None,
+        };
+
+        let mut media = Media::default();
+        SimulcastFeed::set_media(&mut media, Some(stream("crunchyroll", "5")));
+        SimulcastFeed::set_media(&mut media, Some(stream("hidive", "6")));
+        SimulcastFeed::set_media(&mut media, Some(stream("crunchyroll", "6")));
+
+        assert_eq!(
+            media.streams,
+            Some(vec![stream("hidive", "6"), stream("crunchyroll", "6")])
+        );
+    }
+}
@@ -1,14 +1,17 @@
 use crate::anilist_api::{Latest, Media, MediaType};
-use crate::config::Config;
-use crate::options::ExtractOptions;
+use crate::config::{Config, SubsPleaseRSSConfig};
+use crate::http::HttpClient;
+use crate::options::{ExtractOptions, TransformOptions};
+use crate::release::{ReleaseLinks, ReleaseNumber};
 use crate::result::Result;
 use crate::sources::{Extract, Similar, Transform};
 
 use async_trait::async_trait;
+use regex::Regex;
 use serde::{de::Visitor, Deserialize, Deserializer, Serialize};
 use serde_xml_rs::from_str;
 use std::{collections::HashMap, hash::Hash};
-use time::OffsetDateTime;
+use time::{OffsetDateTime, UtcOffset};
 
 #[derive(Debug, Serialize, Clone, Hash)]
 struct PubDate(OffsetDateTime);
@@ -59,7 +62,6 @@ pub struct AnimeRss {
     link: String,
     #[serde(rename = "pubDate")]
     pub_date: PubDate,
-    category: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Hash)]
@@ -68,6 +70,54 @@ pub struct Channel {
     rss_items: Vec<AnimeRss>,
 }
 
+#[derive(Debug, Deserialize)]
+struct LatestRelease {
+    show: String,
+    page: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubsPleaseTitle {
+    pub title: String,
+    pub number: ReleaseNumber,
+    pub resolution: Option<String>,
+    pub batch: bool,
+}
+
+impl SubsPleaseTitle {
+    pub fn regex() -> Result<Regex> {
+        Ok(Regex::new(
+            r#"^\[[^\]]+\]\s+(?P<title>.+?)\s+(?:(?:-\s+|\()(?P<first>\d+)-(?P<last>\d+(?:v\d+)?)\)?|-\s+(?P<episode>\d+(?:\.\d+)?(?:v\d+)?))(?:\s+\((?P<resolution>\d+)p\))?(?:\s+\[[^\]]+\])*(?:\.\w+)?$"#,
+        )?)
+    }
+
+    pub fn parse(re: &Regex, title: &str) -> Option<SubsPleaseTitle> {
+        let caps = re.captures(title.trim())?;
+        let resolution = caps.name("resolution").map(|m| m.as_str().to_owned());
+
+        let (number, batch) = match (caps.name("first"), caps.name("last")) {
+            (Some(first), Some(last)) => {
+                let number = ReleaseNumber {
+                    label: format!("{}-{}", first.as_str(), last.as_str()),
+                    ..ReleaseNumber::parse(last.as_str(), None)
+                };
+                (number, true)
+            }
+            _ => (
+                ReleaseNumber::parse(caps.name("episode")?.as_str(), None),
+                false,
+            ),
+        };
+
+        Some(SubsPleaseTitle {
+            title: caps["title"].to_owned(),
+            number,
+            resolution,
+            batch,
+        })
+    }
+}
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct AnimeLatest(pub HashMap<String, Latest>);
 
@@ -82,49 +132,107 @@ pub struct SubsPleaseRSS<'a> {
 }
 
 impl SubsPleaseRSS<'_> {
-    pub fn new(config: &Config) -> SubsPleaseRSS {
+    pub fn new(config: &Config) -> SubsPleaseRSS<'_> {
         SubsPleaseRSS { config }
     }
 
-    pub async fn fetch(&self) -> Result<AnimeLatest> {
-        let client = reqwest::Client::new();
+    async fn fetch_feed(&self, client: &HttpClient, torrent: bool) -> Result<Vec<AnimeRss>> {
+        let mut query = vec![("r", self.config.subsplease.rss.resolution.as_str())];
+        if torrent {
+            query.push(("t", ""));
+        }
+
         let xml = client
-            .get(self.config.subsplease.rss.url.as_str())
-            .send()
+            .send(
+                client
+                    .get(self.config.subsplease.rss.url.as_str())
+                    .query(&query),
+            )
             .await?
-            .text()
-            .await?;
-
+            .text();
         let rss: Rss = from_str(&xml)?;
 
-        let mut latest: HashMap<String, Latest> = HashMap::new();
+        Ok(rss.channel.rss_items)
+    }
+
+    // The feed has no show page links, the latest releases API has the page slug of each show.
+    async fn fetch_pages(&self, client: &HttpClient) -> Result<HashMap<String, String>> {
+        let releases = client
+            .send(client.get(self.config.subsplease.rss.latest_url.as_str()))
+            .await?
+            .error_for_status()?
+            .json::<HashMap<String, LatestRelease>>()?;
 
-        for item in &rss.channel.rss_items {
-            let title = str::replace(&item.category, " - 720", "");
-            let re = regex::Regex::new(r#"(?P<episode>\d+) \(720p\)"#)?;
-            let caps = re.captures(&item.title);
-            let episode = match caps {
-                Some(caps) => {
-                    let episode = &caps["episode"];
-                    let episode = episode.parse::<u64>();
-                    match episode {
-                        Ok(episode) => episode,
-                        Err(err) => {
-                            eprintln!("Could not parse episode number: {}", err);
-                            0
-                        }
-                    }
+        Ok(releases
+            .into_values()
+            .map(|release| (release.show, release.page))
+            .collect())
+    }
+
+    pub async fn fetch(&self) -> Result<AnimeLatest> {
+        let client = HttpClient::new(self.config, "subsplease_rss");
+        let (torrents, magnets, pages) = tokio::join!(
+            self.fetch_feed(&client, true),
+            self.fetch_feed(&client, false),
+            self.fetch_pages(&client)
+        );
+        let pages = pages.unwrap_or_else(|err| {
+            eprintln!("Could not fetch SubsPlease show pages: {}", err);
+            HashMap::new()
+        });
+
+        Self::parse(&torrents?, &magnets?, &pages, &self.config.subsplease.rss)
+    }
+
+    fn parse(
+        torrents: &[AnimeRss],
+        magnets: &[AnimeRss],
+        pages: &HashMap<String, String>,
+        config: &SubsPleaseRSSConfig,
+    ) -> Result<AnimeLatest> {
+        let re = SubsPleaseTitle::regex()?;
+        let magnets: HashMap<&str, &str> = magnets
+            .iter()
+            .map(|item| (item.title.as_str(), item.link.as_str()))
+            .collect();
+
+        let mut latest: HashMap<String, Latest> = HashMap::new();
+        for item in torrents {
+            let parsed = match SubsPleaseTitle::parse(&re, &item.title) {
+                Some(parsed) => parsed,
+                None => {
+                    eprintln!("Could not parse episode number: {}", item.title);
+                    continue;
                 }
-                None => 0,
             };
+            match &parsed.resolution {
+                Some(resolution) if *resolution != config.resolution => continue,
+                _ => (),
+            }
 
-            if !latest.contains_key(&title) || latest.get(&title).unwrap().episode < episode {
+            let newer = match latest.get(&parsed.title) {
+                Some(current) => current.number < parsed.number,
+                None => true,
+            };
+            if newer {
+                let links = ReleaseLinks {
+                    page: pages
+                        .get(&parsed.title)
+                        .map(|slug| config.page_url.replace("{slug}", slug)),
+                    magnet: magnets
+                        .get(item.title.as_str())
+                        .map(|link| link.to_string()),
+                    torrent: Some(item.link.clone()),
+                };
                 latest.insert(
-                    title.clone(),
+                    parsed.title.clone(),
                     Latest {
-                        title,
-                        episode,
+                        title: parsed.title,
+                        number: parsed.number,
                         url: item.link.clone(),
+                        source: "subsplease_rss".to_owned(),
+                        released_at: Some(item.pub_date.0.to_offset(UtcOffset::UTC)),
+                        links: Some(links),
                     },
                 );
             }
@@ -148,12 +256,23 @@ impl Extract<'_> for SubsPleaseRSS<'_> {
 impl Transform for SubsPleaseRSS<'_> {
     type Extra = Latest;
 
+    fn get_source(&self) -> &str {
+        "subsplease_rss"
+    }
+
     fn set_media(media: &mut Media, extra: Option<Self::Extra>) {
-        media.latest = extra;
+        if let Some(extra) = extra {
+            media.insert_latest(extra);
+        }
     }
 
-    fn transform(&self, media: &mut Media, extras: &HashMap<String, Self::Extra>) -> Result<Media> {
-        self.match_similar(media, MediaType::Anime, extras)
+    fn transform(
+        &self,
+        media: &mut Media,
+        extras: &HashMap<String, Self::Extra>,
+        options: Option<TransformOptions>,
+    ) -> Result<Media> {
+        self.match_similar(media, MediaType::Anime, extras, options)
     }
 }
 
@@ -161,19 +280,150 @@ impl Similar for SubsPleaseRSS<'_> {
     fn get_similarity_threshold(&self) -> f64 {
         self.config.transform.similarity_threshold
     }
+
+    fn get_similarity_candidates(&self) -> usize {
+        self.config.transform.similarity_candidates
+    }
+
+    fn get_enriched_statuses(&self) -> &[String] {
+        match &self.config.subsplease.rss.enriched_statuses {
+            Some(enriched_statuses) => enriched_statuses,
+            None => &self.config.transform.enriched_statuses,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::alt_titles_db::AltTitlesEntry;
     use crate::config::Config;
+    use crate::ngram_index::NgramIndex;
+    use crate::test::helpers::fixture_config;
+    use time::macros::datetime;
 
     #[tokio::test]
     async fn test_extract() {
-        let config = Config::default();
+        let config = fixture_config();
         let rss = SubsPleaseRSS::new(&config);
         let latest = rss.extract(None).await.unwrap();
-        assert!(!latest.0.is_empty());
+
+        assert_eq!(latest.0.len(), 2);
+        let episode = latest.0.get("Oshi no Ko").unwrap();
+        assert_eq!(episode.number, ReleaseNumber::parse("06", None));
+        assert_eq!(episode.url, "https://nyaa.si/view/5f1c8c9f/torrent");
+        assert_eq!(
+            episode.released_at,
+            Some(datetime!(2023-05-17 16:01:10 UTC))
+        );
+        assert!(episode
+            .links
+            .as_ref()
+            .and_then(|links| links.magnet.as_deref())
+            .unwrap()
+            .starts_with("magnet:?xt=urn:btih:5f1c8c9f"));
+
+        let episode = latest.0.get("Skip and Loafer").unwrap();
+        assert_eq!(
+            episode
+                .links
+                .as_ref()
+                .and_then(|links| links.page.as_deref()),
+            Some("https://subsplease.org/shows/skip-to-loafer/")
+        );
+    }
+
+    #[test]
+    fn test_parse_title() {
+        let re = SubsPleaseTitle::regex().unwrap();
+
+        let parsed =
+            SubsPleaseTitle::parse(&re, "[SubsPlease] Oshi no Ko - 05 (1080p) [5F1C8C9F].mkv")
+                .unwrap();
+        assert_eq!(parsed.title, "Oshi no Ko");
+        assert_eq!(parsed.number, ReleaseNumber::parse("05", None));
+        assert_eq!(parsed.resolution.as_deref(), Some("1080"));
+        assert!(!parsed.batch);
+
+        let parsed = SubsPleaseTitle::parse(
+            &re,
+            "[SubsPlease] Kimi ni Todoke - 12.5v2 (480p) [ABCD].mkv",
+        )
+        .unwrap();
+        assert_eq!(parsed.title, "Kimi ni Todoke");
+        assert_eq!(parsed.number.label, "12.5v2");
+        assert_eq!(parsed.resolution.as_deref(), Some("480"));
+
+        let parsed = SubsPleaseTitle::parse(
+            &re,
+            "[SubsPlease] Re:Zero - Season 2 (01-12) (720p) [Batch]",
+        )
+        .unwrap();
+        assert_eq!(parsed.title, "Re:Zero - Season 2");
+        assert_eq!(parsed.number.value, Some(12.0));
+        assert_eq!(parsed.number.label, "01-12");
+        assert!(parsed.batch);
+
+        assert!(SubsPleaseTitle::parse(&re, "Not a SubsPlease release").is_none());
+    }
+
+    #[test]
+    fn test_parse() {
+        let item = |title: &str, link: &str| AnimeRss {
+            title: title.to_owned(),
+            link: link.to_owned(),
+            pub_date: PubDate(datetime!(2023-05-20 12:00 UTC)),
+        };
+        let torrents = [
+            item(
+                "[SubsPlease] Oshi no Ko - 06v2 (720p) [B].mkv",
+                "torrent-6v2",
+            ),
+            item(
+                "[SubsPlease] Oshi no Ko - 06 (1080p) [C].mkv",
+                "torrent-6-1080",
+            ),
+            item("[SubsPlease] Oshi no Ko - 06 (720p) [A].mkv", "torrent-6"),
+            item("[SubsPlease] Oshi no Ko - 05 (720p) [D].mkv", "torrent-5"),
+        ];
+        let magnets = [item(
+            "[SubsPlease] Oshi no Ko - 06v2 (720p) [B].mkv",
+            "magnet:?xt=6v2",
+        )];
+
+        let pages = HashMap::from([("Oshi no Ko".to_owned(), "oshi-no-ko".to_owned())]);
+
+        let mut config = Config::default();
+        config.subsplease.rss.resolution = "720".to_owned();
+        let latest =
+            SubsPleaseRSS::parse(&torrents, &magnets, &pages, &config.subsplease.rss).unwrap();
+
+        let without_page =
+            SubsPleaseRSS::parse(&torrents, &magnets, &HashMap::new(), &config.subsplease.rss)
+                .unwrap();
+        assert_eq!(
+            without_page
+                .0
+                .get("Oshi no Ko")
+                .unwrap()
+                .links
+                .as_ref()
+                .unwrap()
+                .page,
+            None
+        );
+
+        let latest = latest.0.get("Oshi no Ko").unwrap();
+        assert_eq!(latest.number.label, "06v2");
+        assert_eq!(latest.url, "torrent-6v2");
+        assert_eq!(
+            latest.links,
+            Some(ReleaseLinks {
+                page: Some("https://subsplease.org/shows/oshi-no-ko/".to_owned()),
+                magnet: Some("magnet:?xt=6v2".to_owned()),
+                torrent: Some("torrent-6v2".to_owned()),
+            })
+        );
     }
 
     #[test]
@@ -191,23 +441,130 @@ mod tests {
             episodes: None,
             score: None,
             progress: None,
+            updated_at: None,
             latest: None,
+            latest_by_filters: None,
+            latest_by_source: None,
+            latest_sources: None,
+            streams: None,
             schedule: None,
+            next_release_at: None,
             alt_titles: None,
+            match_overrides: None,
+            mappings: None,
         }];
         let latest = HashMap::from([(
             "gintama".to_owned(),
             Latest {
                 title: "gintama".to_owned(),
-                episode: 1,
+                number: ReleaseNumber::parse("1", None),
                 url: "http://www.test.nyaa".to_owned(),
+                source: "test".to_owned(),
+                released_at: None,
+                links: None,
             },
         )]);
 
         let config = Config::default();
         let subsplease_rss = SubsPleaseRSS::new(&config);
 
-        let transformed = subsplease_rss.transform(&mut media[0], &latest).unwrap();
+        let transformed = subsplease_rss
+            .transform(&mut media[0], &latest, None)
+            .unwrap();
         assert_eq!(transformed.latest, latest.get("gintama").cloned());
     }
+
+    #[test]
+    fn test_transform_alt_titles() {
+        let latest = |title: &str| Latest {
+            title: title.to_owned(),
+            number: ReleaseNumber::parse("1", None),
+            url: "http://www.test.nyaa".to_owned(),
+            source: "test".to_owned(),
+            released_at: None,
+            links: None,
+        };
+        let latest = HashMap::from([
+            ("Oshi no Ko S2".to_owned(), latest("Oshi no Ko S2")),
+            ("Naruto".to_owned(), latest("Naruto")),
+        ]);
+        let index = NgramIndex::new(latest.keys());
+        let media = || Media {
+            media_id: Some(1),
+            status: Some("CURRENT".to_owned()),
+            title: Some("[Oshi no Ko] 2nd Season".to_owned()),
+            english_title: Some("[Oshi No Ko] Season 2".to_owned()),
+            media_type: Some(MediaType::Anime),
+            alt_titles: Some(AltTitlesEntry {
+                media_id: 1,
+                alt_titles: vec!["Oshi no Ko S 2".to_owned()],
+            }),
+            ..Default::default()
+        };
+
+        let config = Config::default();
+        let subsplease_rss = SubsPleaseRSS::new(&config);
+
+        let transformed = subsplease_rss
+            .transform(
+                &mut media(),
+                &latest,
+                Some(TransformOptions {
+                    index: Some(&index),
+                }),
+            )
+            .unwrap();
+        assert_eq!(transformed.latest, latest.get("Oshi no Ko S2").cloned());
+
+        let transformed = subsplease_rss
+            .transform(&mut media(), &latest, None)
+            .unwrap();
+        assert_eq!(transformed.latest, latest.get("Oshi no Ko S2").cloned());
+    }
+
+    #[test]
+    fn test_transform_enriched_statuses() {
+        let media = |status: &str| Media {
+            media_id: Some(1),
+            status: Some(status.to_owned()),
+            title: Some("Gintama".to_owned()),
+            media_type: Some(MediaType::Anime),
+            ..Default::default()
+        };
+        let latest = HashMap::from([(
+            "Gintama".to_owned(),
+            Latest {
+                title: "Gintama".to_owned(),
+                number: ReleaseNumber::parse("1", None),
+                url: "http://www.test.nyaa".to_owned(),
+                source: "test".to_owned(),
+                released_at: None,
+                links: None,
+            },
+        )]);
+
+        let mut config = Config::default();
+        config.transform.enriched_statuses = vec!["CURRENT".to_owned(), "REPEATING".to_owned()];
+        config.subsplease.rss.enriched_statuses = None;
+        let subsplease_rss = SubsPleaseRSS::new(&config);
+
+        let transformed = subsplease_rss
+            .transform(&mut media("REPEATING"), &latest, None)
+            .unwrap();
+        assert_eq!(transformed.latest, latest.get("Gintama").cloned());
+
+        let transformed = subsplease_rss
+            .transform(&mut media("PAUSED"), &latest, None)
+            .unwrap();
+        assert_eq!(transformed.latest, None);
+
+        let mut config = Config::default();
+        config.subsplease.rss.enriched_statuses = Some(vec!["PAUSED".to_owned()]);
+        let subsplease_rss = SubsPleaseRSS::new(&config);
+
+        let transformed = subsplease_rss
+            .transform(&mut media("PAUSED"), &latest, None)
+            .unwrap();
+        assert_eq!(transformed.latest, latest.get("Gintama").cloned());
+    }
 }
@@ -1,13 +1,18 @@
 use crate::anilist_api::{Media, MediaType};
-use crate::config::Config;
+use crate::config::{Config, ScheduleBackend};
 use crate::error::CustomError;
+use crate::http::HttpClient;
+use crate::options::TransformOptions;
 use crate::result::Result;
 use crate::sources::{Extract, ExtractOptions, Similar, Transform};
 
 use async_trait::async_trait;
+#[cfg(feature = "webdriver")]
 use scraper::{ElementRef, Html, Selector};
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, str::FromStr};
+use time::{Duration, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset, Weekday};
+use time_tz::{timezones, OffsetDateTimeExt, PrimitiveDateTimeExt, Tz};
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Hash)]
 pub enum Day {
@@ -37,6 +42,68 @@ impl FromStr for Day {
     }
 }
 
+impl From<Weekday> for Day {
+    fn from(weekday: Weekday) -> Day {
+        match weekday {
+            Weekday::Sunday => Day::Sunday,
+            Weekday::Monday => Day::Monday,
+            Weekday::Tuesday => Day::Tuesday,
+            Weekday::Wednesday => Day::Wednesday,
+            Weekday::Thursday => Day::Thursday,
+            Weekday::Friday => Day::Friday,
+            Weekday::Saturday => Day::Saturday,
+        }
+    }
+}
+
+impl From<&Day> for Weekday {
+    fn from(day: &Day) -> Weekday {
+        match day {
+            Day::Sunday => Weekday::Sunday,
+            Day::Monday => Weekday::Monday,
+            Day::Tuesday => Weekday::Tuesday,
+            Day::Wednesday => Weekday::Wednesday,
+            Day::Thursday => Weekday::Thursday,
+            Day::Friday => Weekday::Friday,
+            Day::Saturday => Weekday::Saturday,
+        }
+    }
+}
+
+pub fn get_timezone(timezone: &str) -> Result<&'static Tz> {
+    timezones::get_by_name(timezone).ok_or(CustomError::boxed(&format!(
+        "Invalid timezone: {}.",
+        timezone
+    )))
+}
+
+fn parse_time(time: &str) -> Result<Time> {
+    let time = time.trim().to_uppercase();
+    let (time, meridiem) = match time.split_once(' ') {
+        Some((time, meridiem)) => (time.to_owned(), Some(meridiem.trim().to_owned())),
+        None => (time, None),
+    };
+    let (hour, minute) = time
+        .split_once(':')
+        .ok_or(CustomError::boxed(&format!("Invalid time: {}.", time)))?;
+    let (mut hour, minute) = (hour.parse::<u8>()?, minute.parse::<u8>()?);
+
+    match meridiem.as_deref() {
+        Some("AM") if hour == 12 => hour = 0,
+        Some("PM") if hour < 12 => hour += 12,
+        Some("AM") | Some("PM") | None => (),
+        Some(meridiem) => {
+            return Err(CustomError::boxed(&format!("Invalid time: {}.", meridiem)));
+        }
+    }
+
+    Ok(Time::from_hms(hour, minute, 0)?)
+}
+
+fn days_until(from: Weekday, to: Weekday) -> i64 {
+    (to.number_days_from_sunday() as i64 - from.number_days_from_sunday() as i64).rem_euclid(7)
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Hash)]
 pub struct AnimeScheduleEntry {
     pub title: String,
@@ -44,20 +111,170 @@ pub struct AnimeScheduleEntry {
     pub time: String,
 }
 
+impl AnimeScheduleEntry {
+    pub fn parse(
+        title: String,
+        day: Day,
+        time: &str,
+        timezone: &Tz,
+        now: OffsetDateTime,
+    ) -> Result<AnimeScheduleEntry> {
+        let time = parse_time(time)?;
+        let local_now = now.to_timezone(timezone);
+        let date =
+            local_now.date() + Duration::days(days_until(local_now.weekday(), Weekday::from(&day)));
+
+        let released_at = PrimitiveDateTime::new(date, time)
+            .assume_timezone(timezone)
+            .take_first()
+            .ok_or(CustomError::boxed(&format!(
+                "Invalid local time for {}.",
+                title
+            )))?
+            .to_offset(UtcOffset::UTC);
+
+        Ok(AnimeScheduleEntry {
+            title,
+            day: Day::from(released_at.weekday()),
+            time: format!("{:02}:{:02}", released_at.hour(), released_at.minute()),
+        })
+    }
+
+    pub fn next_release_at(&self, now: OffsetDateTime) -> Option<OffsetDateTime> {
+        let time = parse_time(&self.time).ok()?;
+        let now = now.to_offset(UtcOffset::UTC);
+        let date = now.date() + Duration::days(days_until(now.weekday(), Weekday::from(&self.day)));
+
+        let next_release_at = PrimitiveDateTime::new(date, time).assume_utc();
+        if next_release_at < now {
+            Some(next_release_at + Duration::weeks(1))
+        } else {
+            Some(next_release_at)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ScheduledAnime {
+    pub media_id: Option<u64>,
+    pub title: Option<String>,
+    pub day: Day,
+    pub time: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub next_release_at: OffsetDateTime,
+}
+
+impl ScheduledAnime {
+    pub fn new(media: &Media, timezone: &Tz, now: OffsetDateTime) -> Option<ScheduledAnime> {
+        let next_release_at = media
+            .schedule
+            .as_ref()?
+            .next_release_at(now)?
+            .to_timezone(timezone);
+
+        Some(ScheduledAnime {
+            media_id: media.media_id,
+            title: media.title.to_owned().or(media.english_title.to_owned()),
+            day: Day::from(next_release_at.weekday()),
+            time: format!(
+                "{:02}:{:02}",
+                next_release_at.hour(),
+                next_release_at.minute()
+            ),
+            next_release_at,
+        })
+    }
+}
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct AnimeSchedule(pub HashMap<String, AnimeScheduleEntry>);
 
+#[derive(Debug, Deserialize)]
+struct ScheduleItem {
+    title: String,
+    time: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScheduleResponse {
+    schedule: HashMap<String, Vec<ScheduleItem>>,
+}
+
 #[derive(Clone)]
 pub struct SubsPleaseScraper<'a> {
     config: &'a Config,
 }
 
 impl SubsPleaseScraper<'_> {
-    pub fn new(config: &Config) -> SubsPleaseScraper {
+    pub fn new(config: &Config) -> SubsPleaseScraper<'_> {
         SubsPleaseScraper { config }
     }
 
+    fn parse_schedule(
+        response: ScheduleResponse,
+        timezone: &Tz,
+        now: OffsetDateTime,
+    ) -> AnimeSchedule {
+        let mut days: AnimeSchedule = AnimeSchedule(HashMap::new());
+
+        for (day, items) in response.schedule {
+            let day = match Day::from_str(&day) {
+                Ok(day) => day,
+                Err(err) => {
+                    eprintln!("Could not parse schedule day {}: {}", day, err);
+                    continue;
+                }
+            };
+
+            for item in items {
+                match AnimeScheduleEntry::parse(
+                    item.title.to_owned(),
+                    day.clone(),
+                    &item.time,
+                    timezone,
+                    now,
+                ) {
+                    Ok(entry) => {
+                        days.0.insert(item.title, entry);
+                    }
+                    Err(err) => {
+                        eprintln!("Could not parse schedule for {}: {}", item.title, err);
+                    }
+                }
+            }
+        }
+
+        days
+    }
+
+    async fn fetch_schedule(&self) -> Result<AnimeSchedule> {
+        let timezone = get_timezone(&self.config.subsplease.scraper.timezone)?;
+        let now = OffsetDateTime::now_utc();
+
+        let client = HttpClient::new(self.config, "subsplease_scraper");
+        let request = client
+            .get(self.config.subsplease.scraper.api_url.as_str())
+            .query(&[("tz", self.config.subsplease.scraper.timezone.as_str())]);
+        let response = client
+            .send(request)
+            .await?
+            .error_for_status()?
+            .json::<ScheduleResponse>()?;
+
+        Ok(Self::parse_schedule(response, timezone, now))
+    }
+
+    #[cfg(feature = "webdriver")]
     async fn load_schedule_table(&self) -> Result<Html> {
+        let http = HttpClient::new(self.config, "subsplease_scraper");
+        if http.is_fixture() {
+            let html = http
+                .send(http.get(self.config.subsplease.scraper.url.as_str()))
+                .await?
+                .text();
+            return Ok(Html::parse_document(&html));
+        }
+
         let mut caps = serde_json::map::Map::new();
         let chrome_opts: Vec<&str> = self
             .config
@@ -83,11 +300,19 @@ impl SubsPleaseScraper<'_> {
             .await?
             .html(false)
             .await?;
+        if http.is_recording() {
+            http.save_fixture(
+                http.get(self.config.subsplease.scraper.url.as_str()),
+                "html",
+                &table,
+            )?;
+        }
         let table = Html::parse_fragment(&table);
 
         Ok(table)
     }
 
+    #[cfg(feature = "webdriver")]
     fn extract_inner_html(selector: &str, element: ElementRef) -> String {
         let selector = Selector::parse(selector);
         match selector {
@@ -102,7 +327,17 @@ impl SubsPleaseScraper<'_> {
         }
     }
 
+    #[cfg(not(feature = "webdriver"))]
+    async fn scrape(&self) -> Result<AnimeSchedule> {
+        Err(CustomError::boxed(
+            "The webdriver schedule backend requires the webdriver feature.",
+        ))
+    }
+
+    #[cfg(feature = "webdriver")]
     async fn scrape(&self) -> Result<AnimeSchedule> {
+        let timezone = get_timezone(&self.config.subsplease.scraper.timezone)?;
+        let now = OffsetDateTime::now_utc();
         let table = self.load_schedule_table().await?;
 
         let mut days: AnimeSchedule = AnimeSchedule(HashMap::new());
@@ -127,15 +362,18 @@ impl SubsPleaseScraper<'_> {
                     let title = Self::extract_inner_html("a", element);
                     let time = Self::extract_inner_html(".all-schedule-time", element);
 
-                    if !title.is_empty() && !time.is_empty() && current_day.is_some() {
-                        days.0.insert(
-                            title.to_owned(),
-                            AnimeScheduleEntry {
-                                title,
-                                time,
-                                day: current_day.clone().unwrap(),
-                            },
-                        );
+                    if let (false, false, Some(day)) =
+                        (title.is_empty(), time.is_empty(), current_day.clone())
+                    {
+                        match AnimeScheduleEntry::parse(title.to_owned(), day, &time, timezone, now)
+                        {
+                            Ok(entry) => {
+                                days.0.insert(title, entry);
+                            }
+                            Err(err) => {
+                                eprintln!("Could not parse schedule for {}: {}", title, err);
+                            }
+                        }
                     }
                 }
             }
@@ -150,21 +388,37 @@ impl Extract<'_> for SubsPleaseScraper<'_> {
     type Data = AnimeSchedule;
 
     async fn extract(&self, _options: Option<ExtractOptions>) -> Result<Self::Data> {
-        let mut data = self.scrape().await?;
-
-        Ok(std::mem::take(&mut data))
+        match self.config.subsplease.scraper.backend {
+            ScheduleBackend::Api => self.fetch_schedule().await,
+            ScheduleBackend::Webdriver => self.scrape().await,
+        }
     }
 }
 
 impl Transform for SubsPleaseScraper<'_> {
     type Extra = AnimeScheduleEntry;
 
-    fn set_media(mut media: &mut Media, extra: Option<Self::Extra>) {
+    fn get_source(&self) -> &str {
+        "subsplease_scraper"
+    }
+
+    fn set_media(media: &mut Media, extra: Option<Self::Extra>) {
         media.schedule = extra;
     }
 
-    fn transform(&self, media: &mut Media, extras: &HashMap<String, Self::Extra>) -> Result<Media> {
-        self.match_similar(media, MediaType::Anime, extras)
+    fn transform(
+        &self,
+        media: &mut Media,
+        extras: &HashMap<String, Self::Extra>,
+        options: Option<TransformOptions>,
+    ) -> Result<Media> {
+        let mut media = self.match_similar(media, MediaType::Anime, extras, options)?;
+        media.next_release_at = match &media.schedule {
+            Some(schedule) => schedule.next_release_at(OffsetDateTime::now_utc()),
+            None => None,
+        };
+
+        Ok(media)
     }
 }
 
@@ -172,19 +426,93 @@ impl Similar for SubsPleaseScraper<'_> {
     fn get_similarity_threshold(&self) -> f64 {
         self.config.transform.similarity_threshold
     }
+
+    fn get_similarity_candidates(&self) -> usize {
+        self.config.transform.similarity_candidates
+    }
+
+    fn get_enriched_statuses(&self) -> &[String] {
+        match &self.config.subsplease.scraper.enriched_statuses {
+            Some(enriched_statuses) => enriched_statuses,
+            None => &self.config.transform.enriched_statuses,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::Config;
+    use crate::test::helpers::fixture_config;
+    use time::macros::datetime;
+    use wiremock::{
+        matchers::{method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
 
     #[tokio::test]
     async fn test_extract() {
-        let config = Config::default();
+        let config = fixture_config();
+        let scraper = SubsPleaseScraper::new(&config);
+        let actual = scraper.extract(None).await.unwrap();
+
+        assert_eq!(actual.0.len(), 2);
+        let entry = actual.0.get("Oshi no Ko").unwrap();
+        assert_eq!(entry.day, Day::Wednesday);
+        assert_eq!(entry.time, "16:00");
+    }
+
+    #[cfg(feature = "webdriver")]
+    #[tokio::test]
+    async fn test_scrape() {
+        let mut config = fixture_config();
+        config.subsplease.scraper.backend = ScheduleBackend::Webdriver;
         let scraper = SubsPleaseScraper::new(&config);
         let actual = scraper.extract(None).await.unwrap();
-        assert!(!actual.0.is_empty());
+
+        assert_eq!(actual.0.len(), 2);
+        let entry = actual.0.get("Skip and Loafer").unwrap();
+        assert_eq!(entry.day, Day::Monday);
+        assert_eq!(entry.time, "15:30");
+    }
+
+    #[tokio::test]
+    async fn test_schedule_api() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/"))
+            .and(query_param("f", "schedule"))
+            .and(query_param("tz", "America/New_York"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "tz": "America/New_York",
+                "schedule": {
+                    "Saturday": [
+                        { "title": "Gintama", "page": "gintama", "time": "22:30" }
+                    ],
+                    "Monday": [
+                        { "title": "Naruto", "page": "naruto", "time": "09:00" },
+                        { "title": "Broken", "page": "broken", "time": "noon" }
+                    ],
+                    "Someday": [
+                        { "title": "Unknown", "page": "unknown", "time": "09:00" }
+                    ]
+                }
+            })))
+            .mount(&server)
+            .await;
+
+        let mut config = Config::default();
+        config.subsplease.scraper.api_url = format!("{}/api/?f=schedule&h=true", server.uri());
+        config.subsplease.scraper.timezone = "America/New_York".to_owned();
+        config.subsplease.scraper.backend = ScheduleBackend::Api;
+
+        let schedule = SubsPleaseScraper::new(&config).extract(None).await.unwrap();
+
+        assert_eq!(schedule.0.len(), 2);
+        let gintama = schedule.0.get("Gintama").unwrap();
+        assert_eq!(gintama.day, Day::Sunday);
+        assert_eq!(gintama.time[3..].to_owned(), "30");
+        assert_eq!(schedule.0.get("Naruto").unwrap().day, Day::Monday);
     }
 
     #[test]
@@ -203,9 +531,17 @@ mod tests {
                 episodes: None,
                 score: None,
                 progress: None,
+                updated_at: None,
                 latest: None,
+                latest_by_filters: None,
+                latest_by_source: None,
+                latest_sources: None,
+                streams: None,
                 schedule: None,
+                next_release_at: None,
                 alt_titles: None,
+                match_overrides: None,
+                mappings: None,
             },
             Media {
                 media_id: Some(1),
@@ -220,9 +556,17 @@ mod tests {
                 episodes: None,
                 score: None,
                 progress: None,
+                updated_at: None,
                 latest: None,
+                latest_by_filters: None,
+                latest_by_source: None,
+                latest_sources: None,
+                streams: None,
                 schedule: None,
+                next_release_at: None,
                 alt_titles: None,
+                match_overrides: None,
+                mappings: None,
             },
             Media {
                 media_id: Some(1),
@@ -237,9 +581,17 @@ mod tests {
                 episodes: None,
                 score: None,
                 progress: None,
+                updated_at: None,
                 latest: None,
+                latest_by_filters: None,
+                latest_by_source: None,
+                latest_sources: None,
+                streams: None,
                 schedule: None,
+                next_release_at: None,
                 alt_titles: None,
+                match_overrides: None,
+                mappings: None,
             },
         ];
         let schedules = HashMap::from([
@@ -273,21 +625,87 @@ mod tests {
         let subsplease_scraper = SubsPleaseScraper::new(&config);
 
         let transformed = subsplease_scraper
-            .transform(&mut media[0], &schedules)
+            .transform(&mut media[0], &schedules, None)
             .unwrap();
         assert_eq!(transformed.schedule, schedules.get("gintama").cloned());
 
         let transformed = subsplease_scraper
-            .transform(&mut media[1], &schedules)
+            .transform(&mut media[1], &schedules, None)
             .unwrap();
         assert_eq!(transformed.schedule, schedules.get("naruto").cloned());
 
         let transformed = subsplease_scraper
-            .transform(&mut media[2], &schedules)
+            .transform(&mut media[2], &schedules, None)
             .unwrap();
         assert_eq!(
             transformed.schedule,
             schedules.get("tamako market").cloned()
         );
     }
+
+    #[test]
+    fn test_parse() {
+        let timezone = get_timezone("America/New_York").unwrap();
+        let now = datetime!(2023-05-17 12:00 UTC);
+
+        let entry = AnimeScheduleEntry::parse(
+            "Gintama".to_owned(),
+            Day::Saturday,
+            "10:30 PM",
+            timezone,
+            now,
+        )
+        .unwrap();
+        assert_eq!(entry.day, Day::Sunday);
+        assert_eq!(entry.time, "02:30");
+
+        let entry =
+            AnimeScheduleEntry::parse("Gintama".to_owned(), Day::Monday, "09:00", timezone, now)
+                .unwrap();
+        assert_eq!(entry.day, Day::Monday);
+        assert_eq!(entry.time, "13:00");
+
+        assert!(AnimeScheduleEntry::parse(
+            "Gintama".to_owned(),
+            Day::Monday,
+            "noon",
+            timezone,
+            now
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_next_release_at() {
+        let entry = AnimeScheduleEntry {
+            title: "Gintama".to_owned(),
+            day: Day::Wednesday,
+            time: "15:00".to_owned(),
+        };
+
+        assert_eq!(
+            entry.next_release_at(datetime!(2023-05-17 12:00 UTC)),
+            Some(datetime!(2023-05-17 15:00 UTC))
+        );
+        assert_eq!(
+            entry.next_release_at(datetime!(2023-05-17 16:00 UTC)),
+            Some(datetime!(2023-05-24 15:00 UTC))
+        );
+
+        let media = Media {
+            media_id: Some(1),
+            title: Some("Gintama".to_owned()),
+            schedule: Some(entry),
+            ..Default::default()
+        };
+        let scheduled = ScheduledAnime::new(
+            &media,
+            get_timezone("Asia/Tokyo").unwrap(),
+            datetime!(2023-05-17 16:00 UTC),
+        )
+        .unwrap();
+        assert_eq!(scheduled.day, Day::Thursday);
+        assert_eq!(scheduled.time, "00:00");
+        assert_eq!(scheduled.next_release_at, datetime!(2023-05-24 15:00 UTC));
+    }
 }
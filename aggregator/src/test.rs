@@ -1,12 +1,12 @@
 #[cfg(test)]
 pub mod helpers {
     use crate::anilist_api::User;
-    use crate::config::Config;
+    use crate::config::{Config, HttpBackend};
     use crate::db::{MongoDB, Redis};
 
     use bson::doc;
     use serde::Deserialize;
-    use std::fs;
+    use std::{env, fs};
     use tokio::sync::OnceCell;
 
     pub static ONCE: OnceCell<()> = OnceCell::const_new();
@@ -30,6 +30,17 @@ pub mod helpers {
         }
     }
 
+    // Set OSHIRASE_RECORD to re-record the responses in testdata/http from the live sites.
+    pub fn fixture_config() -> Config {
+        let mut config = Config::default();
+        config.http.backend = match env::var("OSHIRASE_RECORD") {
+            Ok(_) => HttpBackend::Record,
+            Err(_) => HttpBackend::Fixture,
+        };
+        config.http.fixture_dir = "testdata/http".to_owned();
+        config
+    }
+
     pub async fn init() {
         let config = Config::default();
         let mongodb = MongoDB::init(&config).await;
@@ -66,6 +77,21 @@ pub mod helpers {
             .drop(None)
             .await
             .unwrap();
+        database
+            .collection::<()>("media_mappings")
+            .drop(None)
+            .await
+            .unwrap();
+        database
+            .collection::<()>("match_overrides")
+            .drop(None)
+            .await
+            .unwrap();
+        database
+            .collection::<()>("releases")
+            .drop(None)
+            .await
+            .unwrap();
         database.collection::<()>("test").drop(None).await.unwrap();
     }
 }
@@ -53,8 +53,9 @@ impl<'a> Worker<'a> {
                                     );
                                     let start = std::time::Instant::now();
                                     match self.aggregator.run().await {
-                                        Ok(_) => println!(
-                                            "Finished running aggregator: {:?}.",
+                                        Ok(data) => println!(
+                                            "Finished running aggregator with {} issues: {:?}.",
+                                            data.report().issues.len(),
                                             start.elapsed()
                                         ),
                                         Err(err) => eprintln!("Could not run aggregator: {}", err),